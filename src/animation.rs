@@ -0,0 +1,418 @@
+use std::{fs::File, io::BufReader, time::Duration};
+
+use crate::AppError;
+
+pub mod bubble;
+pub mod clip;
+
+/// Delay used for frames that declare 0 or 10 ms, same as browsers do
+pub const DEFAULT_FRAME_DELAY: Duration = Duration::from_millis(100);
+pub const MIN_SPEED: f32 = 0.25;
+pub const MAX_SPEED: f32 = 8.0;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LoopCount {
+    #[default]
+    Infinite,
+    /// Number of repetitions after the first play, `Finite(0)` plays once
+    Finite(u16),
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    #[default]
+    Forward,
+    Reverse,
+    /// Forward then backward, one loop ends back on the first frame
+    PingPong,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PlaybackCommand {
+    SetDirection(Direction),
+    SetSpeed(f32),
+    SeekFrame(usize),
+    SeekTime(Duration),
+    SetLoopCount(LoopCount),
+    /// Plays until the end of the current loop then holds the last frame
+    PlayOnce,
+    /// Restores the loop count read from the file and restarts
+    Restart,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameInfo {
+    pub index: usize,
+    pub delay: Duration,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct AnimationInfo {
+    pub frames: Vec<FrameInfo>,
+    pub loop_count: LoopCount,
+}
+
+impl AnimationInfo {
+    /// Frame delays and loop count, without decoding pixels
+    pub fn from_gif(path: &str, first_index: usize) -> Result<Self, AppError> {
+        let mut options = gif::DecodeOptions::new();
+        options.skip_frame_decoding(true);
+        let mut decoder = options.read_info(BufReader::new(File::open(path)?))?;
+
+        let mut frames = Vec::new();
+        while let Some(frame) = decoder.read_next_frame()? {
+            frames.push(FrameInfo {
+                index: first_index + frames.len(),
+                delay: frame_delay(frame.delay),
+            });
+        }
+        let loop_count = match decoder.repeat() {
+            gif::Repeat::Infinite => LoopCount::Infinite,
+            gif::Repeat::Finite(n) => LoopCount::Finite(n),
+        };
+        Ok(Self { frames, loop_count })
+    }
+}
+
+pub fn frame_delay(centiseconds: u16) -> Duration {
+    match centiseconds {
+        0 | 1 => DEFAULT_FRAME_DELAY,
+        cs => Duration::from_millis(cs as u64 * 10),
+    }
+}
+
+/// Playback state of one animation, the render owns the pixels
+#[derive(Debug, Clone)]
+pub struct AnimationPlayer {
+    frames: Vec<FrameInfo>,
+    loop_count: LoopCount,
+    source_loop_count: LoopCount,
+    direction: Direction,
+    /// Ping-pong is on its way back to the first frame
+    bouncing: bool,
+    speed: f32,
+    position: usize,
+    elapsed: Duration,
+    pending: f32,
+    completed_loops: u32,
+    finished: bool,
+}
+
+impl AnimationPlayer {
+    pub fn new(info: AnimationInfo) -> Self {
+        Self {
+            frames: info.frames,
+            loop_count: info.loop_count,
+            source_loop_count: info.loop_count,
+            direction: Direction::Forward,
+            bouncing: false,
+            speed: 1.0,
+            position: 0,
+            elapsed: Duration::ZERO,
+            pending: 0.0,
+            completed_loops: 0,
+            finished: false,
+        }
+    }
+
+    pub fn current_frame(&self) -> Option<usize> {
+        self.frames.get(self.position).map(|f| f.index)
+    }
+
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn frames(&self) -> &[FrameInfo] {
+        &self.frames
+    }
+
+    pub fn loop_count(&self) -> LoopCount {
+        self.loop_count
+    }
+
+    pub fn completed_loops(&self) -> u32 {
+        self.completed_loops
+    }
+
+    /// True once a finite loop count is exhausted, the last frame is held
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    pub fn set_direction(&mut self, direction: Direction) {
+        if direction != self.direction {
+            self.direction = direction;
+            self.bouncing = false;
+            self.finished = false;
+        }
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// Clamps to `MIN_SPEED..=MAX_SPEED`, ignores NaN
+    pub fn set_speed(&mut self, speed: f32) {
+        if !speed.is_nan() {
+            self.speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+        }
+    }
+
+    /// Restarting is up to the caller, a finished player stays on its last frame
+    pub fn set_loop_count(&mut self, loop_count: LoopCount) {
+        self.loop_count = loop_count;
+        self.completed_loops = 0;
+    }
+
+    pub fn reset(&mut self) {
+        self.position = match self.direction {
+            Direction::Forward | Direction::PingPong => 0,
+            Direction::Reverse => self.frames.len().saturating_sub(1),
+        };
+        self.bouncing = false;
+        self.elapsed = Duration::ZERO;
+        self.pending = 0.0;
+        self.completed_loops = 0;
+        self.finished = false;
+    }
+
+    pub fn seek_frame(&mut self, position: usize) {
+        if self.frames.is_empty() {
+            return;
+        }
+        self.position = position.min(self.frames.len() - 1);
+        self.elapsed = Duration::ZERO;
+        self.pending = 0.0;
+        self.finished = false;
+    }
+
+    /// Timestamps past the end wrap around
+    pub fn seek_time(&mut self, time: Duration) {
+        let total: Duration = self.frames.iter().map(|f| f.delay).sum();
+        if total.is_zero() {
+            return;
+        }
+        let mut remaining = Duration::from_nanos((time.as_nanos() % total.as_nanos()) as u64);
+        for (position, frame) in self.frames.iter().enumerate() {
+            if remaining < frame.delay {
+                self.seek_frame(position);
+                self.elapsed = remaining;
+                return;
+            }
+            remaining -= frame.delay;
+        }
+    }
+
+    pub fn apply(&mut self, command: PlaybackCommand) {
+        match command {
+            PlaybackCommand::SetDirection(direction) => self.set_direction(direction),
+            PlaybackCommand::SetSpeed(speed) => self.set_speed(speed),
+            PlaybackCommand::SeekFrame(position) => self.seek_frame(position),
+            PlaybackCommand::SeekTime(time) => self.seek_time(time),
+            PlaybackCommand::SetLoopCount(loop_count) => self.set_loop_count(loop_count),
+            PlaybackCommand::PlayOnce => self.set_loop_count(LoopCount::Finite(0)),
+            PlaybackCommand::Restart => {
+                self.loop_count = self.source_loop_count;
+                self.reset();
+            }
+        }
+    }
+
+    /// Advances `speed` frames regardless of delays, the caller's timer sets the pace
+    pub fn step(&mut self) {
+        if self.finished || self.frames.is_empty() {
+            return;
+        }
+        self.pending += self.speed;
+        while self.pending >= 1.0 && !self.finished {
+            self.pending -= 1.0;
+            self.advance_one();
+        }
+    }
+
+    /// Advances by wall clock time, honouring per-frame delays scaled by `speed`
+    pub fn advance(&mut self, dt: Duration) {
+        if self.finished || self.frames.is_empty() {
+            return;
+        }
+        self.elapsed += dt.mul_f64(self.speed as f64);
+        loop {
+            let delay = self.frames[self.position].delay.max(Duration::from_millis(1));
+            if self.elapsed < delay {
+                break;
+            }
+            self.elapsed -= delay;
+            self.advance_one();
+            if self.finished {
+                self.elapsed = Duration::ZERO;
+                break;
+            }
+        }
+    }
+
+    fn advance_one(&mut self) {
+        let last = self.frames.len() - 1;
+        let backwards = match self.direction {
+            Direction::Forward => false,
+            Direction::Reverse => true,
+            Direction::PingPong => self.bouncing,
+        };
+        let at_end = if backwards { self.position == 0 } else { self.position == last };
+        if !at_end {
+            if backwards {
+                self.position -= 1;
+            } else {
+                self.position += 1;
+            }
+            return;
+        }
+        if self.direction == Direction::PingPong && !self.bouncing && last > 0 {
+            self.bouncing = true;
+            self.position -= 1;
+            return;
+        }
+
+        self.completed_loops += 1;
+        if let LoopCount::Finite(repeats) = self.loop_count
+            && self.completed_loops > repeats as u32
+        {
+            self.finished = true;
+            return;
+        }
+        self.bouncing = false;
+        self.position = match self.direction {
+            Direction::Forward => 0,
+            Direction::Reverse => last,
+            Direction::PingPong => last.min(1),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn player(delays_ms: &[u64], loop_count: LoopCount) -> AnimationPlayer {
+        let frames = delays_ms
+            .iter()
+            .enumerate()
+            .map(|(index, ms)| FrameInfo {
+                index,
+                delay: Duration::from_millis(*ms),
+            })
+            .collect();
+        AnimationPlayer::new(AnimationInfo { frames, loop_count })
+    }
+
+    fn positions(player: &mut AnimationPlayer, steps: usize) -> Vec<usize> {
+        (0..steps)
+            .map(|_| {
+                player.step();
+                player.position()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn finite_loop_count_holds_last_frame() {
+        let mut p = player(&[10, 10, 10], LoopCount::Finite(1));
+        assert_eq!(positions(&mut p, 7), [1, 2, 0, 1, 2, 2, 2]);
+        assert!(p.is_finished());
+        assert_eq!(p.completed_loops(), 2);
+        p.apply(PlaybackCommand::Restart);
+        assert_eq!((p.position(), p.is_finished()), (0, false));
+    }
+
+    #[test]
+    fn play_once_stops_at_end_of_loop() {
+        let mut p = player(&[10, 10, 10], LoopCount::Infinite);
+        p.step();
+        p.apply(PlaybackCommand::PlayOnce);
+        assert_eq!(positions(&mut p, 3), [2, 2, 2]);
+        assert!(p.is_finished());
+    }
+
+    #[test]
+    fn reverse_and_ping_pong() {
+        let mut p = player(&[10, 10, 10], LoopCount::Infinite);
+        p.set_direction(Direction::Reverse);
+        p.reset();
+        assert_eq!(positions(&mut p, 4), [1, 0, 2, 1]);
+
+        let mut p = player(&[10, 10, 10], LoopCount::Finite(0));
+        p.set_direction(Direction::PingPong);
+        assert_eq!(positions(&mut p, 5), [1, 2, 1, 0, 0]);
+        assert!(p.is_finished());
+
+        let mut p = player(&[10], LoopCount::Infinite);
+        p.set_direction(Direction::PingPong);
+        assert_eq!(positions(&mut p, 3), [0, 0, 0]);
+        assert_eq!(p.completed_loops(), 3);
+    }
+
+    #[test]
+    fn seeking() {
+        let mut p = player(&[100, 200, 300], LoopCount::Infinite);
+        p.seek_frame(10);
+        assert_eq!(p.position(), 2);
+        p.seek_time(Duration::from_millis(250));
+        assert_eq!(p.position(), 1);
+        p.advance(Duration::from_millis(49));
+        assert_eq!(p.position(), 1);
+        p.advance(Duration::from_millis(1));
+        assert_eq!(p.position(), 2);
+        // past the end wraps round
+        p.seek_time(Duration::from_millis(650));
+        assert_eq!(p.position(), 0);
+    }
+
+    #[test]
+    fn advance_honours_delays_and_speed() {
+        let mut p = player(&[100, 50, 100], LoopCount::Infinite);
+        p.advance(Duration::from_millis(149));
+        assert_eq!(p.position(), 1);
+        p.advance(Duration::from_millis(1));
+        assert_eq!(p.position(), 2);
+        p.set_speed(2.0);
+        p.advance(Duration::from_millis(50));
+        assert_eq!(p.position(), 0);
+        p.set_speed(100.0);
+        assert_eq!(p.speed(), MAX_SPEED);
+        p.set_speed(f32::NAN);
+        assert_eq!(p.speed(), MAX_SPEED);
+        p.set_speed(0.0);
+        assert_eq!(p.speed(), MIN_SPEED);
+    }
+
+    #[test]
+    fn delay_clamp() {
+        assert_eq!(frame_delay(0), DEFAULT_FRAME_DELAY);
+        assert_eq!(frame_delay(1), DEFAULT_FRAME_DELAY);
+        assert_eq!(frame_delay(2), Duration::from_millis(20));
+        // zero delays from elsewhere still advance one frame per millisecond
+        let mut p = player(&[0, 0, 0], LoopCount::Infinite);
+        p.advance(Duration::from_millis(2));
+        assert_eq!(p.position(), 2);
+    }
+
+    #[test]
+    fn gif_info() {
+        let info = AnimationInfo::from_gif(concat!(env!("CARGO_MANIFEST_DIR"), "/resources/gif/cat-rainbow.gif"), 5).unwrap();
+        assert!(!info.frames.is_empty());
+        assert_eq!(info.frames[0].index, 5);
+        assert!(info.frames.iter().all(|f| f.delay >= Duration::from_millis(20)));
+    }
+}
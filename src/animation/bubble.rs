@@ -0,0 +1,166 @@
+use std::{collections::VecDeque, time::Duration};
+
+use log::debug;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BubbleTiming {
+    pub fade_in: Duration,
+    pub hold: Duration,
+    pub fade_out: Duration,
+    /// Opacity changes per fade, each one a one-shot timer
+    pub fade_steps: u32,
+    /// Messages waiting beyond this drop the oldest
+    pub capacity: usize,
+}
+
+impl Default for BubbleTiming {
+    fn default() -> Self {
+        Self {
+            fade_in: Duration::from_millis(200),
+            hold: Duration::from_secs(3),
+            fade_out: Duration::from_millis(400),
+            fade_steps: 8,
+            capacity: 8,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    FadeIn(u32),
+    Hold,
+    FadeOut(u32),
+}
+
+/// Messages shown one at a time, `push` and `tick` return the delay until the next `tick`
+#[derive(Debug, Clone, Default)]
+pub struct BubbleQueue {
+    pub timing: BubbleTiming,
+    pending: VecDeque<String>,
+    current: Option<(String, Phase)>,
+}
+
+impl BubbleQueue {
+    pub fn new(timing: BubbleTiming) -> Self {
+        Self { timing, ..Default::default() }
+    }
+
+    /// Returns the first delay when the queue was idle
+    pub fn push(&mut self, text: String) -> Option<Duration> {
+        if self.current.as_ref().is_some_and(|(current, _)| *current == text) || self.pending.contains(&text) {
+            return None;
+        }
+        self.pending.push_back(text);
+        if self.pending.len() > self.timing.capacity.max(1) {
+            let dropped = self.pending.pop_front();
+            debug!("Bubble queue full, dropping {:?}", dropped);
+        }
+        match self.current {
+            Some(_) => None,
+            None => self.next(),
+        }
+    }
+
+    pub fn tick(&mut self) -> Option<Duration> {
+        let steps = self.timing.fade_steps.max(1);
+        let Some((_, phase)) = self.current.as_mut() else {
+            return self.next();
+        };
+        match *phase {
+            Phase::FadeIn(step) if step + 1 < steps => *phase = Phase::FadeIn(step + 1),
+            Phase::FadeIn(_) => *phase = Phase::Hold,
+            Phase::Hold => *phase = Phase::FadeOut(0),
+            Phase::FadeOut(step) if step + 1 < steps => *phase = Phase::FadeOut(step + 1),
+            Phase::FadeOut(_) => {
+                self.current = None;
+                return self.next();
+            }
+        }
+        Some(self.delay())
+    }
+
+    pub fn current(&self) -> Option<(&str, f32)> {
+        let (text, phase) = self.current.as_ref()?;
+        let steps = self.timing.fade_steps.max(1) as f32;
+        let opacity = match phase {
+            Phase::FadeIn(step) => (*step + 1) as f32 / steps,
+            Phase::Hold => 1.0,
+            Phase::FadeOut(step) => 1.0 - (*step + 1) as f32 / steps,
+        };
+        Some((text, opacity))
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.current.is_none() && self.pending.is_empty()
+    }
+
+    fn next(&mut self) -> Option<Duration> {
+        self.current = Some((self.pending.pop_front()?, Phase::FadeIn(0)));
+        Some(self.delay())
+    }
+
+    fn delay(&self) -> Duration {
+        let steps = self.timing.fade_steps.max(1);
+        match self.current {
+            Some((_, Phase::FadeIn(_))) => self.timing.fade_in / steps,
+            Some((_, Phase::Hold)) => self.timing.hold,
+            Some((_, Phase::FadeOut(_))) => self.timing.fade_out / steps,
+            None => Duration::ZERO,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue(capacity: usize) -> BubbleQueue {
+        BubbleQueue::new(BubbleTiming {
+            fade_in: Duration::from_millis(200),
+            hold: Duration::from_secs(1),
+            fade_out: Duration::from_millis(400),
+            fade_steps: 2,
+            capacity,
+        })
+    }
+
+    #[test]
+    fn push_dedupe_capacity() {
+        let mut bubbles = queue(2);
+        assert!(bubbles.is_idle());
+        assert_eq!(bubbles.push("hi".into()), Some(Duration::from_millis(100)));
+        // already showing or waiting
+        assert_eq!(bubbles.push("hi".into()), None);
+        assert_eq!(bubbles.push("a".into()), None);
+        assert_eq!(bubbles.push("a".into()), None);
+        bubbles.push("b".into());
+        // the oldest waiting message goes
+        bubbles.push("c".into());
+        assert_eq!(bubbles.pending, ["b".to_string(), "c".into()]);
+        assert_eq!(bubbles.current(), Some(("hi", 0.5)));
+    }
+
+    #[test]
+    fn fade_in_hold_fade_out() {
+        let mut bubbles = queue(8);
+        bubbles.push("hi".into());
+        bubbles.push("bye".into());
+        let steps = [
+            (Some(Duration::from_millis(100)), ("hi", 1.0)),
+            (Some(Duration::from_secs(1)), ("hi", 1.0)),
+            (Some(Duration::from_millis(200)), ("hi", 0.5)),
+            (Some(Duration::from_millis(200)), ("hi", 0.0)),
+            (Some(Duration::from_millis(100)), ("bye", 0.5)),
+        ];
+        for (delay, current) in steps {
+            assert_eq!(bubbles.tick(), delay);
+            assert_eq!(bubbles.current(), Some(current));
+        }
+        for _ in 0..4 {
+            assert!(bubbles.tick().is_some());
+        }
+        assert_eq!(bubbles.tick(), None);
+        assert!(bubbles.is_idle());
+        assert_eq!(bubbles.current(), None);
+    }
+}
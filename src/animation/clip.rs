@@ -0,0 +1,276 @@
+use std::{ops::Range, time::Duration};
+
+use super::{AnimationInfo, AnimationPlayer, PlaybackCommand};
+use crate::{
+    AppError,
+    render::{Render, transform::FrameTransform},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClipSource {
+    /// Every frame of a file, with the file's loop count
+    File(String),
+    /// A frame range of a file, looped forever
+    Frames(String, Range<usize>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClipDef {
+    pub name: String,
+    pub source: ClipSource,
+    /// Metric value from which this clip is wanted while the load rises
+    pub threshold: f32,
+    pub transform: FrameTransform,
+}
+
+/// Picks a clip from a metric value, with hysteresis on the way down and switches at clip boundaries
+#[derive(Clone)]
+pub struct ClipMachine {
+    names: Vec<String>,
+    thresholds: Vec<f32>,
+    players: Vec<AnimationPlayer>,
+    hysteresis: f32,
+    active: usize,
+    pending: Option<usize>,
+}
+
+impl ClipMachine {
+    /// Files shared by clips with the same transform are decoded once
+    pub fn load(render: &mut dyn Render, mut clips: Vec<ClipDef>, hysteresis: f32) -> Result<Self, AppError> {
+        if clips.is_empty() {
+            return Err(AppError("No clip defined".into()));
+        }
+        clips.sort_by(|a, b| a.threshold.total_cmp(&b.threshold));
+
+        let mut loaded: Vec<(&str, &FrameTransform, AnimationInfo)> = Vec::new();
+        let mut players = Vec::with_capacity(clips.len());
+        for clip in &clips {
+            let (ClipSource::File(path) | ClipSource::Frames(path, _)) = &clip.source;
+            let info = match loaded.iter().find(|(p, t, _)| p == path && **t == clip.transform) {
+                Some((_, _, info)) => info.clone(),
+                None => {
+                    render.set_frame_transform(clip.transform.clone())?;
+                    let info = render.load_src_data(path)?;
+                    loaded.push((path, &clip.transform, info.clone()));
+                    info
+                }
+            };
+            let info = match &clip.source {
+                ClipSource::File(_) => info,
+                ClipSource::Frames(_, range) => AnimationInfo {
+                    frames: info
+                        .frames
+                        .get(range.clone())
+                        .ok_or_else(|| AppError(format!("Clip {} range {:?} is out of {} frames", clip.name, range, info.frames.len())))?
+                        .to_vec(),
+                    loop_count: Default::default(),
+                },
+            };
+            players.push(AnimationPlayer::new(info));
+        }
+
+        Ok(Self {
+            names: clips.iter().map(|c| c.name.clone()).collect(),
+            thresholds: clips.iter().map(|c| c.threshold).collect(),
+            players,
+            hysteresis: hysteresis.max(0.0),
+            active: 0,
+            pending: None,
+        })
+    }
+
+    pub fn single(render: &mut dyn Render, path: &str, transform: FrameTransform) -> Result<Self, AppError> {
+        let clip = ClipDef {
+            name: "default".into(),
+            source: ClipSource::File(path.into()),
+            threshold: 0.0,
+            transform,
+        };
+        Self::load(render, vec![clip], 0.0)
+    }
+
+    pub fn active_clip(&self) -> &str {
+        &self.names[self.active]
+    }
+
+    pub fn pending_clip(&self) -> Option<&str> {
+        self.pending.map(|i| self.names[i].as_str())
+    }
+
+    pub fn player(&self) -> &AnimationPlayer {
+        &self.players[self.active]
+    }
+
+    pub fn current_frame(&self) -> Option<usize> {
+        self.player().current_frame()
+    }
+
+    /// The switch happens at the next clip boundary
+    pub fn update(&mut self, value: f32) {
+        let target = self.target(value);
+        self.pending = (target != self.active).then_some(target);
+    }
+
+    pub fn request(&mut self, name: &str) {
+        if let Some(target) = self.names.iter().position(|n| n == name) {
+            self.pending = (target != self.active).then_some(target);
+        }
+    }
+
+    /// Speed and direction go to every clip so they survive switches
+    pub fn apply(&mut self, command: PlaybackCommand) {
+        match command {
+            PlaybackCommand::SetSpeed(_) | PlaybackCommand::SetDirection(_) => self.players.iter_mut().for_each(|player| player.apply(command)),
+            _ => self.players[self.active].apply(command),
+        }
+    }
+
+    pub fn step(&mut self) {
+        self.drive(AnimationPlayer::step);
+    }
+
+    pub fn advance(&mut self, dt: Duration) {
+        self.drive(|player| player.advance(dt));
+    }
+
+    fn drive(&mut self, f: impl FnOnce(&mut AnimationPlayer)) {
+        let player = &mut self.players[self.active];
+        let loops = player.completed_loops();
+        f(player);
+        let boundary = player.completed_loops() != loops || player.is_finished();
+        if boundary && let Some(next) = self.pending.take() {
+            self.active = next;
+            self.players[next].reset();
+        }
+    }
+
+    fn target(&self, value: f32) -> usize {
+        let mut target = self.active;
+        while target + 1 < self.thresholds.len() && value >= self.thresholds[target + 1] {
+            target += 1;
+        }
+        while target > 0 && value < self.thresholds[target] - self.hysteresis {
+            target -= 1;
+        }
+        target
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        animation::{Direction, LoopCount},
+        render::software::SoftwareRender,
+    };
+
+    const GIF: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/resources/gif/cat-rainbow.gif");
+
+    fn clip(name: &str, threshold: f32, frames: Range<usize>) -> ClipDef {
+        ClipDef {
+            name: name.into(),
+            source: ClipSource::Frames(GIF.into(), frames),
+            threshold,
+            transform: FrameTransform::default(),
+        }
+    }
+
+    fn machine() -> ClipMachine {
+        let clips = vec![clip("run", 60.0, 2..4), clip("idle", 0.0, 0..2), clip("walk", 30.0, 1..3)];
+        ClipMachine::load(&mut SoftwareRender::new(4, 4), clips, 5.0).unwrap()
+    }
+
+    fn step_until_switch(clips: &mut ClipMachine, limit: usize) -> usize {
+        let active = clips.active_clip().to_string();
+        (1..=limit)
+            .find(|_| {
+                clips.step();
+                clips.active_clip() != active
+            })
+            .unwrap_or(limit + 1)
+    }
+
+    #[test]
+    fn shared_file_decoded_once() {
+        let mut render = SoftwareRender::new(4, 4);
+        let clips = ClipMachine::load(&mut render, vec![clip("idle", 0.0, 0..2), clip("walk", 30.0, 1..3)], 5.0).unwrap();
+        assert_eq!(clips.current_frame(), Some(0));
+        let file_frames = AnimationInfo::from_gif(GIF, 0).unwrap().frames.len();
+        assert_eq!(render.load_src_data(GIF).unwrap().frames[0].index, file_frames);
+    }
+
+    #[test]
+    fn transforms_per_clip() {
+        let mut render = SoftwareRender::new(4, 4);
+        let file_frames = AnimationInfo::from_gif(GIF, 0).unwrap().frames.len();
+        let mirrored = ClipDef {
+            transform: "flip-h".parse().unwrap(),
+            ..clip("walk", 30.0, 1..3)
+        };
+        let clips = vec![clip("idle", 0.0, 0..2), mirrored, clip("run", 60.0, 2..4)];
+        let mut clips = ClipMachine::load(&mut render, clips, 5.0).unwrap();
+        // the mirrored clip plays its own copy of the file
+        clips.request("walk");
+        while clips.active_clip() != "walk" {
+            clips.step();
+        }
+        assert_eq!(clips.current_frame(), Some(file_frames + 1));
+        assert_eq!(render.load_src_data(GIF).unwrap().frames[0].index, 2 * file_frames);
+    }
+
+    #[test]
+    fn out_of_range_clip_fails() {
+        assert!(ClipMachine::load(&mut SoftwareRender::new(4, 4), vec![clip("idle", 0.0, 0..10_000)], 0.0).is_err());
+        assert!(ClipMachine::load(&mut SoftwareRender::new(4, 4), Vec::new(), 0.0).is_err());
+    }
+
+    #[test]
+    fn thresholds_with_hysteresis() {
+        let mut clips = machine();
+        assert_eq!(clips.active_clip(), "idle");
+        let cases = [
+            (10.0, "idle"),
+            (30.0, "walk"),
+            (26.0, "walk"),
+            (24.9, "idle"),
+            (90.0, "run"),
+            (56.0, "run"),
+            (40.0, "walk"),
+        ];
+        for (value, expected) in cases {
+            clips.update(value);
+            step_until_switch(&mut clips, 4);
+            assert_eq!(clips.active_clip(), expected, "at {}", value);
+        }
+    }
+
+    #[test]
+    fn switches_wait_for_clip_boundary() {
+        let mut clips = machine();
+        clips.update(35.0);
+        assert_eq!(clips.pending_clip(), Some("walk"));
+        // idle is two frames long, the switch happens as it loops
+        assert_eq!(step_until_switch(&mut clips, 10), 2);
+        assert_eq!(clips.player().position(), 0);
+        assert_eq!(clips.pending_clip(), None);
+        clips.request("run");
+        clips.request("missing");
+        assert_eq!(step_until_switch(&mut clips, 10), 2);
+        assert_eq!(clips.active_clip(), "run");
+    }
+
+    #[test]
+    fn commands_reach_active_clip() {
+        let mut clips = machine();
+        clips.apply(PlaybackCommand::SeekFrame(1));
+        clips.apply(PlaybackCommand::SetLoopCount(LoopCount::Finite(3)));
+        clips.apply(PlaybackCommand::SetSpeed(2.0));
+        clips.apply(PlaybackCommand::SetDirection(Direction::Reverse));
+        assert_eq!(clips.players[0].position(), 1);
+        assert_eq!(clips.players[0].loop_count(), LoopCount::Finite(3));
+        for player in &clips.players[1..] {
+            assert_eq!((player.position(), player.loop_count()), (0, LoopCount::Infinite));
+        }
+        assert!(clips.players.iter().all(|p| p.speed() == 2.0 && p.direction() == Direction::Reverse));
+    }
+}
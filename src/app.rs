@@ -1,27 +1,320 @@
-use std::{path::Path, time::Duration};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
 
-use log::debug;
+use log::{debug, info, warn};
 
 use super::ApplicationEventHandler;
 use crate::{
+    animation::{PlaybackCommand, bubble::BubbleQueue, clip::ClipMachine},
+    config::{Config, Layout},
     eventloop::{Event, EventLoop},
-    render::{Render, dx_render::DxRender},
+    metrics::{
+        MetricKind, MetricSampler, Sample,
+        alert::{AlertEvent, AlertState},
+        history::MetricHistory,
+        input::InputActivity,
+        power::{BatteryAction, PowerState},
+        speed_curve::SpeedCurve,
+    },
+    notify::{Notifier, Urgency},
+    render::{Bubble, Render, Sparkline, Text, Tint, color::ColorTransform, dx_render::DxRender},
+    script::{ScriptDecision, ScriptPolicy},
     timer::TimerManager,
     window::Window,
 };
 #[derive(Default)]
 pub struct App {
+    pub config: Config,
     pub window: Option<Window>,
     pub render: Option<Box<dyn Render>>,
+    pub clips: Option<ClipMachine>,
     pub timer_manager: Option<TimerManager>,
+    pub metric_sampler: Option<MetricSampler>,
+    pub speed_curves: HashMap<MetricKind, SpeedCurve>,
+    /// Per core in `Layout::PerCore`, cloned from `clips` so frames are shared
+    pub cores: Vec<(SpeedCurve, ClipMachine)>,
+    pub power_state: Option<PowerState>,
+    pub battery: Option<f32>,
+    pub battery_action: BatteryAction,
+    pub input_activity: Option<InputActivity>,
+    pub script: Option<ScriptPolicy>,
+    pub script_fps: Option<f32>,
+    /// Shown while the script sets no tint
+    pub heat_tint: Option<Tint>,
+    pub script_tint: Option<Tint>,
+    pub overlay_text: Option<String>,
+    /// Last handed to the render, so it is only rasterised on change
+    pub shown_text: Option<String>,
+    pub history: MetricHistory,
+    pub bubbles: BubbleQueue,
+    /// Indices into `config.alerts`, in firing order
+    pub firing_alerts: Vec<usize>,
+    pub notifier: Option<Notifier>,
+    pub recolor: Option<ColorTransform>,
+    /// Frames advance by the time since the last Paint
+    pub last_paint: Option<Instant>,
 }
 
 impl App {
     pub fn into_with_render<R: Render + 'static>(self, render: R) -> App {
         App {
-            window: self.window,
             render: Some(Box::new(render)),
-            timer_manager: self.timer_manager,
+            ..self
+        }
+    }
+
+    fn paint(&mut self) {
+        let now = Instant::now();
+        let elapsed = self.last_paint.map_or(Duration::ZERO, |last| now - last);
+        self.last_paint = Some(now);
+        let Some(render) = self.render.as_ref() else {
+            return;
+        };
+        match self.config.layout {
+            Layout::PerCore { columns } if !self.cores.is_empty() => {
+                let frames: Vec<usize> = self.cores.iter().filter_map(|(_, clips)| clips.current_frame()).collect();
+                let _ = render.render_grid(&frames, columns);
+                for (_, clips) in self.cores.iter_mut() {
+                    clips.advance(elapsed);
+                }
+            }
+            _ => {
+                if let Some(clips) = self.clips.as_mut() {
+                    if let Some(frame) = clips.current_frame() {
+                        let _ = render.render_frame(frame);
+                    }
+                    clips.advance(elapsed);
+                }
+            }
+        }
+    }
+
+    /// Paint timer and playback speed from the drive curve and layout, capped or stopped by the power policy
+    fn update_paint_timer(&mut self) {
+        let Some(timer_manager) = self.timer_manager.as_ref() else {
+            return;
+        };
+        let interval = match self.config.layout {
+            Layout::Single => {
+                let curve = self.speed_curves.get(&self.config.drive_metric);
+                let fps = self.script_fps.or(curve.map(SpeedCurve::fps)).unwrap_or(50.0);
+                // the file's own timing at the curve's top rate
+                if let (Some(clips), Some(curve)) = (self.clips.as_mut(), curve) {
+                    clips.apply(PlaybackCommand::SetSpeed(fps / curve.config.max_fps));
+                }
+                Duration::from_secs_f32(1.0 / fps.max(0.1))
+            }
+            Layout::PerCore { .. } => Duration::from_secs_f32(1.0 / self.config.core_curve.max_fps.max(0.1)),
+        };
+        match self.battery_action {
+            BatteryAction::None => timer_manager.start_timer(Event::Paint, interval),
+            BatteryAction::CapFps(fps) => timer_manager.start_timer(Event::Paint, interval.max(Duration::from_secs_f32(1.0 / fps.max(0.1)))),
+            BatteryAction::Pause => timer_manager.stop_timer(Event::Paint),
+        }
+    }
+
+    fn on_power_change(&mut self) {
+        let action = match (self.config.power, self.power_state) {
+            (Some(policy), Some(state)) => policy.action(state, self.battery),
+            _ => BatteryAction::None,
+        };
+        if action != self.battery_action {
+            debug!("Power {:?} at {:?}%, {:?}", self.power_state, self.battery, action);
+            self.battery_action = action;
+            self.update_paint_timer();
+        }
+    }
+
+    fn on_metric(&mut self, sample: Sample) {
+        self.history.record(sample);
+        if self.config.sparkline.as_ref().is_some_and(|s| s.metric == sample.kind) {
+            self.update_sparkline();
+        }
+        if self.config.text.as_ref().is_some_and(|t| t.metric == sample.kind) {
+            self.update_text();
+        }
+        if let Some(script) = self.script.as_mut() {
+            script.record(sample);
+        }
+        if self.config.recolor.as_ref().is_some_and(|r| r.metric == sample.kind) {
+            self.update_recolor(sample.value);
+        }
+        if sample.kind == MetricKind::Battery {
+            self.battery = Some(sample.value);
+            self.on_power_change();
+        }
+        if let MetricKind::CpuCore(core) = sample.kind
+            && matches!(self.config.layout, Layout::PerCore { .. })
+        {
+            self.on_core_metric(core as usize, sample.value);
+            return;
+        }
+        if sample.kind == MetricKind::Temperature
+            && let Some(heat_tint) = self.config.hwmon.as_ref().and_then(|h| h.heat_tint)
+        {
+            self.heat_tint = heat_tint.tint(sample.value);
+            self.update_tint();
+        }
+        let fps = self.speed_curves.get_mut(&sample.kind).and_then(|curve| curve.feed(sample.value));
+        if sample.kind != self.config.drive_metric {
+            return;
+        }
+        let decision = self.run_script();
+        self.pick_clip(decision.clip.as_deref());
+        if let Some(tint) = decision.tint {
+            self.script_tint = tint;
+            self.update_tint();
+        }
+        if let Some(text) = decision.text {
+            self.overlay_text = text;
+            self.update_text();
+        }
+        if let Some(say) = decision.say {
+            self.say(say);
+        }
+        if let Some(fps) = fps {
+            debug!("{:?} {:.1} -> {:.1} fps", sample.kind, sample.value, fps);
+        }
+        if fps.is_some() || decision.fps != self.script_fps {
+            self.script_fps = decision.fps;
+            self.update_paint_timer();
+        }
+    }
+
+    fn update_tint(&mut self) {
+        if let Some(render) = self.render.as_mut() {
+            let _ = render.set_tint(self.script_tint.or(self.heat_tint));
+        }
+    }
+
+    fn update_sparkline(&mut self) {
+        let (Some(config), Some(render)) = (self.config.sparkline.as_ref(), self.render.as_mut()) else {
+            return;
+        };
+        let values = self.history.values(config.metric, config.window);
+        let lowest = values.iter().copied().fold(f32::INFINITY, f32::min);
+        let highest = values.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        let (min, max) = config.range.unwrap_or((lowest, highest));
+        let _ = render.set_sparkline(Some(Sparkline {
+            values,
+            min,
+            max,
+            color: config.color,
+            region: config.region,
+        }));
+    }
+
+    /// The script's text if it set one, else the configured metric's latest value
+    fn update_text(&mut self) {
+        let text = self.overlay_text.clone().or_else(|| {
+            let config = self.config.text.as_ref()?;
+            Some(config.format(self.history.latest(config.metric)?))
+        });
+        let Some(render) = self.render.as_mut() else {
+            return;
+        };
+        if text == self.shown_text {
+            return;
+        }
+        let style = self.config.text.as_ref().map(|t| t.style).unwrap_or_default();
+        let _ = render.set_text(text.clone().map(|text| Text { text, style }));
+        self.shown_text = text;
+    }
+
+    /// Frames are only recoloured on change
+    fn update_recolor(&mut self, value: f32) {
+        let transform = self.config.recolor.as_ref().and_then(|r| r.stops.pick(value)).cloned();
+        let Some(render) = self.render.as_mut() else {
+            return;
+        };
+        if transform == self.recolor {
+            return;
+        }
+        debug!("Recolor at {}: {:?}", value, transform);
+        let _ = render.set_recolor(transform.clone());
+        self.recolor = transform;
+    }
+
+    /// The script's clip, else the latest alert's, else the one the drive metric picks
+    fn pick_clip(&mut self, requested: Option<&str>) {
+        let alert_clip = self.firing_alerts.iter().rev().find_map(|rule| self.config.alerts.get(*rule)?.clip.as_deref());
+        let value = self.speed_curves.get(&self.config.drive_metric).and_then(SpeedCurve::value);
+        let Some(clips) = self.clips.as_mut() else {
+            return;
+        };
+        match (requested.or(alert_clip), value) {
+            (Some(clip), _) => clips.request(clip),
+            (None, Some(value)) => clips.update(value),
+            (None, None) => {}
+        }
+    }
+
+    fn on_alert(&mut self, alert: AlertEvent) {
+        let Some(rule) = self.config.alerts.get(alert.rule) else {
+            return;
+        };
+        let (message, urgency) = match alert.state {
+            AlertState::Fired => (rule.fired_message(alert.value), Urgency::Critical),
+            AlertState::Recovered => (rule.recovered_message(alert.value), Urgency::Normal),
+        };
+        info!("Alert {}: {}", alert.rule, message);
+        if let Some(notifier) = &self.notifier {
+            // the recovery replaces the alert's notification
+            notifier.notify(alert.rule, &message, "", urgency);
+        }
+        match alert.state {
+            AlertState::Fired => self.firing_alerts.push(alert.rule),
+            AlertState::Recovered => self.firing_alerts.retain(|rule| *rule != alert.rule),
+        }
+        self.pick_clip(None);
+        self.say(message);
+    }
+
+    pub fn say(&mut self, text: impl Into<String>) {
+        if let Some(delay) = self.bubbles.push(text.into()) {
+            self.show_bubble(Some(delay));
+        }
+    }
+
+    fn show_bubble(&mut self, next: Option<Duration>) {
+        if let Some(render) = self.render.as_mut() {
+            let bubble = self.bubbles.current().map(|(text, opacity)| Bubble {
+                text: text.into(),
+                style: self.config.bubble_style,
+                opacity,
+            });
+            let _ = render.set_bubble(bubble);
+        }
+        if let (Some(delay), Some(timer_manager)) = (next, self.timer_manager.as_ref()) {
+            timer_manager.start_once(Event::Bubble, delay);
+        }
+    }
+
+    fn run_script(&mut self) -> ScriptDecision {
+        let Some(script) = self.script.as_mut() else {
+            return ScriptDecision::default();
+        };
+        script.decide().unwrap_or_else(|e| {
+            warn!("{}", e);
+            ScriptDecision::default()
+        })
+    }
+
+    fn on_core_metric(&mut self, core: usize, value: f32) {
+        let Some(template) = self.clips.as_ref() else {
+            return;
+        };
+        while self.cores.len() <= core {
+            self.cores.push((SpeedCurve::new(self.config.core_curve.clone()), template.clone()));
+        }
+        let (curve, clips) = &mut self.cores[core];
+        if let Some(fps) = curve.feed(value) {
+            clips.apply(PlaybackCommand::SetSpeed(fps / curve.config.max_fps));
+        }
+        if let Some(value) = curve.value() {
+            clips.update(value);
         }
     }
 }
@@ -29,21 +322,57 @@ impl App {
 impl ApplicationEventHandler for App {
     fn resumed(&mut self, event_loop: &crate::eventloop::EventLoop) {
         self.window = Some(Window::init(event_loop).unwrap());
-        self.timer_manager.as_mut().unwrap().start_timer(crate::eventloop::Event::Paint, Duration::from_millis(20));
-        let render = DxRender::new(self.window.as_ref().unwrap().hwnd);
-        let base = std::env::var("CARGO_MANIFEST_DIR").unwrap();
-        let abs_path = Path::new(&base).join("resources/gif/cat-rainbow.gif");
-        let path = abs_path.to_str().unwrap();
+        self.bubbles = BubbleQueue::new(self.config.bubble_timing);
+        self.speed_curves = self.config.speed_curves.iter().map(|(kind, config)| (*kind, SpeedCurve::new(config.clone()))).collect();
+        let window = self.window.as_ref().unwrap();
+        let render = DxRender::new(window.hwnd, window.bubble);
         let mut render = render.unwrap();
-        let _ = render.load_src_data(path);
+        render.set_budgets(self.config.frame_cache_budget, self.config.stream_budget);
+        render.set_keep_images(self.config.recolor.is_some());
+        self.clips = if self.config.clips.is_empty() {
+            ClipMachine::single(&mut render, &self.config.gif_path, self.config.frame_transform.clone())
+        } else {
+            ClipMachine::load(&mut render, self.config.clips.clone(), self.config.clip_hysteresis)
+        }
+        .ok();
         self.render = Some(Box::new(render));
+        self.update_paint_timer();
     }
 
     fn event(&mut self, event_loop: &EventLoop, event: Event) {
         match event {
-            Event::Paint => {
-                if let Some(ref mut render) = self.render {
-                    let _ = render.render_frame().and_then(|()| render.next_frame());
+            Event::Paint => self.paint(),
+            Event::Playback(command) => {
+                for clips in self.clips.iter_mut().chain(self.cores.iter_mut().map(|(_, clips)| clips)) {
+                    clips.apply(command);
+                }
+            }
+            Event::Resize(width, height) => {
+                if let Some(render) = self.render.as_mut() {
+                    let _ = render.set_window_size(width, height);
+                }
+            }
+            Event::DpiChanged(dpi) => {
+                if let Some(render) = self.render.as_mut() {
+                    let _ = render.set_dpi(dpi);
+                }
+                // rasterised again for the new DPI
+                self.shown_text = None;
+                self.update_text();
+            }
+            Event::Metric(sample) => self.on_metric(sample),
+            Event::Alert(alert) => self.on_alert(alert),
+            Event::Bubble => {
+                let next = self.bubbles.tick();
+                self.show_bubble(next);
+            }
+            Event::Power(state) => {
+                self.power_state = Some(state);
+                self.on_power_change();
+            }
+            Event::KeyDown(_) | Event::MouseMove(..) if self.input_activity.is_some() => {
+                if let Some(activity) = self.input_activity.as_ref() {
+                    activity.record(&event);
                 }
             }
             _ => {
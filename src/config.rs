@@ -0,0 +1,667 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use crate::{
+    AppError,
+    animation::{
+        bubble::BubbleTiming,
+        clip::{ClipDef, ClipSource},
+    },
+    metrics::{
+        MetricKind,
+        alert::AlertRule,
+        cgroup::CgroupTarget,
+        external::CommandConfig,
+        hwmon::{HeatTint, HwmonConfig},
+        input::InputCapture,
+        log_tail::LogTailConfig,
+        power::{BatteryAction, PowerPolicy},
+        process::{ProcessFilter, ProcessTarget},
+        speed_curve::SpeedCurveConfig,
+        trace::TraceReplayConfig,
+    },
+    render::{BubbleStyle, Color, TextStyle, color::ColorStops, layout::OverlayRegion, transform::FrameTransform},
+};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    #[default]
+    Single,
+    /// One animation per cpu core on a grid `columns` wide, 0 for a single row
+    PerCore { columns: usize },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SparklineConfig {
+    pub metric: MetricKind,
+    /// History shown, oldest on the left
+    pub window: Duration,
+    /// Fixed value range, `None` follows the values shown
+    pub range: Option<(f32, f32)>,
+    pub color: Color,
+    pub region: OverlayRegion,
+}
+
+impl Default for SparklineConfig {
+    fn default() -> Self {
+        Self {
+            metric: MetricKind::Cpu,
+            window: Duration::from_secs(60),
+            range: None,
+            color: Color {
+                r: 0.3,
+                g: 0.9,
+                b: 0.4,
+                a: 1.0,
+            },
+            region: OverlayRegion::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextConfig {
+    pub metric: MetricKind,
+    pub label: Option<String>,
+    pub style: TextStyle,
+}
+
+impl TextConfig {
+    /// `CPU 42%`, the value rounded
+    pub fn format(&self, value: f32) -> String {
+        let value = format!("{:.0}{}", value, self.metric.unit());
+        match &self.label {
+            Some(label) => format!("{} {}", label, value),
+            None => value,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecolorConfig {
+    pub metric: MetricKind,
+    pub stops: ColorStops,
+}
+
+pub struct Config {
+    pub gif_path: String,
+    /// Clips picked by `drive_metric`, empty plays `gif_path` alone
+    pub clips: Vec<ClipDef>,
+    pub clip_hysteresis: f32,
+    pub sample_interval: Duration,
+    /// Metric that sets the Paint rate and picks the clip
+    pub drive_metric: MetricKind,
+    pub speed_curves: HashMap<MetricKind, SpeedCurveConfig>,
+    pub layout: Layout,
+    /// Shared by every core in `Layout::PerCore`, the Paint timer runs at its `max_fps`
+    pub core_curve: SpeedCurveConfig,
+    pub process: Option<ProcessFilter>,
+    pub cgroup: Option<CgroupTarget>,
+    pub hwmon: Option<HwmonConfig>,
+    pub power: Option<PowerPolicy>,
+    pub input: Option<InputCapture>,
+    pub command: Option<CommandConfig>,
+    pub stdin: bool,
+    pub log_tail: Option<LogTailConfig>,
+    /// Recorded trace played back in place of the live cpu source
+    pub replay: Option<TraceReplayConfig>,
+    pub record: Option<PathBuf>,
+    pub script: Option<PathBuf>,
+    pub sparkline: Option<SparklineConfig>,
+    pub text: Option<TextConfig>,
+    pub bubble_style: BubbleStyle,
+    pub bubble_timing: BubbleTiming,
+    pub alerts: Vec<AlertRule>,
+    pub notify: bool,
+    pub disk: Option<PathBuf>,
+    pub recolor: Option<RecolorConfig>,
+    /// Applied to the default gif's frames as they are loaded, clips have their own
+    pub frame_transform: FrameTransform,
+    pub frame_cache_budget: usize,
+    /// `None` keeps every frame decoded
+    pub stream_budget: Option<usize>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let base = std::env::var("CARGO_MANIFEST_DIR").unwrap_or_else(|_| ".".into());
+        let gif_path = Path::new(&base).join("resources/gif/cat-rainbow.gif");
+        Self {
+            gif_path: gif_path.to_string_lossy().into_owned(),
+            clips: Vec::new(),
+            clip_hysteresis: 5.0,
+            sample_interval: Duration::from_secs(1),
+            drive_metric: MetricKind::Cpu,
+            speed_curves: HashMap::from([
+                (MetricKind::Cpu, SpeedCurveConfig::default()),
+                (MetricKind::ProcessCpu, SpeedCurveConfig::default()),
+                (MetricKind::CgroupCpu, SpeedCurveConfig::default()),
+                (MetricKind::Command, SpeedCurveConfig::default()),
+                (MetricKind::Stdin, SpeedCurveConfig::default()),
+                (
+                    MetricKind::LogRate,
+                    SpeedCurveConfig {
+                        input_max: 10.0,
+                        ..Default::default()
+                    },
+                ),
+                (
+                    MetricKind::InputRate,
+                    SpeedCurveConfig {
+                        input_max: 20.0,
+                        ..Default::default()
+                    },
+                ),
+                (
+                    MetricKind::Temperature,
+                    SpeedCurveConfig {
+                        input_min: 40.0,
+                        input_max: 95.0,
+                        ..Default::default()
+                    },
+                ),
+            ]),
+            layout: Layout::Single,
+            core_curve: SpeedCurveConfig::default(),
+            process: None,
+            cgroup: None,
+            hwmon: None,
+            power: None,
+            input: None,
+            command: None,
+            stdin: false,
+            log_tail: None,
+            replay: None,
+            record: None,
+            script: None,
+            sparkline: None,
+            text: None,
+            bubble_style: BubbleStyle::default(),
+            bubble_timing: BubbleTiming::default(),
+            alerts: Vec::new(),
+            notify: false,
+            disk: None,
+            recolor: None,
+            frame_transform: FrameTransform::default(),
+            frame_cache_budget: 64 << 20,
+            stream_budget: None,
+        }
+    }
+}
+
+impl Config {
+    /// Defaults overridden by command line flags, each source flag also makes it the drive metric.
+    /// Sources: `--process <pid|glob>`, `--process-tree`, `--cgroup <path|self>`, `--hwmon <chip>:<label>`, `--hwmon-fan <chip>:<label>`,
+    /// `--input window|global`, `--input-device <path>`, `--input-fixture <path>`, `--stdin`, `--command <cmd>`, `--log <path> <regex>`,
+    /// `--disk <path>`, `--replay <trace>`, `--replay-once`, `--replay-speed <factor>`, `--record <trace>`, `--power <percent> <pause|fps>`.
+    /// Drawing: `--per-core <columns>`, `--heat-tint <cool> <hot>`, `--script <file.rhai>`, `--sparkline <metric>`, `--text <metric>`,
+    /// `--text-label <label>`, `--recolor <metric>`, `--transform <steps>`, `--frame-cache-mb <n>`, `--stream-mb <n>`.
+    /// Alerts: `--alert "Cpu > 90 for 30s"`, `--alert-clip`, `--alert-message`, `--alert-recovery`, `--notify`.
+    /// Clips: `--clip <name> <threshold> <path>`, `--clip-frames <start>..<end>`, `--clip-transform <steps>`, `--clip-hysteresis <n>`.
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self, AppError> {
+        let mut config = Self::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--per-core" => {
+                    config.layout = Layout::PerCore {
+                        columns: number(&mut args, &arg)?,
+                    }
+                }
+                "--process" => {
+                    let target = args.next().ok_or_else(|| AppError("--process needs a pid or a name".into()))?;
+                    let target = target.parse().map_or(ProcessTarget::Name(target), ProcessTarget::Pid);
+                    config.process = Some(ProcessFilter { target, tree: false });
+                    config.drive_metric = MetricKind::ProcessCpu;
+                }
+                "--process-tree" => {
+                    let process = config.process.as_mut().ok_or_else(|| AppError("--process-tree needs --process first".into()))?;
+                    process.tree = true;
+                }
+                "--cgroup" => {
+                    let target = args.next().ok_or_else(|| AppError("--cgroup needs a path or self".into()))?;
+                    config.cgroup = Some(match target.as_str() {
+                        "self" => CgroupTarget::Own,
+                        _ => CgroupTarget::Path(target.into()),
+                    });
+                    config.drive_metric = MetricKind::CgroupCpu;
+                }
+                "--hwmon" | "--hwmon-fan" => {
+                    let selector = args.next().ok_or_else(|| AppError(format!("{} needs <chip>:<label>", arg)))?.parse()?;
+                    let hwmon = config.hwmon.get_or_insert_default();
+                    if arg == "--hwmon" {
+                        hwmon.temperature = selector;
+                        config.drive_metric = MetricKind::Temperature;
+                    } else {
+                        hwmon.fan = Some(selector);
+                    }
+                }
+                "--heat-tint" => {
+                    let (cool, hot) = (number(&mut args, &arg)?, number(&mut args, &arg)?);
+                    config.hwmon.get_or_insert_default().heat_tint = Some(HeatTint { cool, hot });
+                }
+                "--power" => {
+                    let threshold = number(&mut args, &arg)?;
+                    let action = match args.next().as_deref() {
+                        Some("pause") => BatteryAction::Pause,
+                        Some(fps) => BatteryAction::CapFps(fps.parse().map_err(|_| AppError(format!("Bad --power action {}", fps)))?),
+                        None => return Err(AppError("--power needs pause or a frame rate".into())),
+                    };
+                    config.power = Some(PowerPolicy { threshold, action });
+                }
+                "--input" | "--input-device" | "--input-fixture" => {
+                    let value = args.next().ok_or_else(|| AppError(format!("{} needs a value", arg)))?;
+                    config.input = Some(match (arg.as_str(), value.as_str(), config.input.take()) {
+                        ("--input", "window", _) => InputCapture::Window,
+                        ("--input", "global", _) => InputCapture::Global(Vec::new()),
+                        ("--input", _, _) => return Err(AppError(format!("--input is window or global, not {}", value))),
+                        ("--input-device", _, Some(InputCapture::Global(mut devices))) => {
+                            devices.push(value.into());
+                            InputCapture::Global(devices)
+                        }
+                        ("--input-device", _, _) => InputCapture::Global(vec![value.into()]),
+                        _ => InputCapture::Fixture(value.into()),
+                    });
+                    config.drive_metric = MetricKind::InputRate;
+                }
+                "--stdin" => {
+                    config.stdin = true;
+                    config.drive_metric = MetricKind::Stdin;
+                }
+                "--command" => {
+                    let command = args.next().ok_or_else(|| AppError("--command needs a value".into()))?;
+                    config.command = Some(CommandConfig {
+                        command,
+                        interval: Duration::from_secs(5),
+                        timeout: Duration::from_secs(10),
+                    });
+                    config.drive_metric = MetricKind::Command;
+                }
+                "--log" => {
+                    let (Some(path), Some(pattern)) = (args.next(), args.next()) else {
+                        return Err(AppError("--log needs a path and a pattern".into()));
+                    };
+                    config.log_tail = Some(LogTailConfig {
+                        path: path.into(),
+                        pattern,
+                        window: Duration::from_secs(10),
+                    });
+                    config.drive_metric = MetricKind::LogRate;
+                }
+                "--replay" => {
+                    let path = args.next().ok_or_else(|| AppError("--replay needs a trace".into()))?;
+                    config.replay = Some(TraceReplayConfig {
+                        path: path.into(),
+                        looping: true,
+                        speed: 1.0,
+                    });
+                }
+                "--replay-once" => {
+                    config
+                        .replay
+                        .as_mut()
+                        .ok_or_else(|| AppError("--replay-once needs --replay first".into()))?
+                        .looping = false
+                }
+                "--replay-speed" => {
+                    let speed = args.next().and_then(|s| s.parse().ok()).filter(|s: &f32| *s > 0.0);
+                    let replay = config.replay.as_mut().ok_or_else(|| AppError("--replay-speed needs --replay first".into()))?;
+                    replay.speed = speed.ok_or_else(|| AppError("--replay-speed needs a positive factor".into()))?;
+                }
+                "--record" => config.record = Some(args.next().ok_or_else(|| AppError("--record needs a path".into()))?.into()),
+                "--script" => config.script = Some(args.next().ok_or_else(|| AppError("--script needs a path".into()))?.into()),
+                "--sparkline" => {
+                    let metric = args.next().ok_or_else(|| AppError("--sparkline needs a metric".into()))?.parse()?;
+                    config.sparkline = Some(SparklineConfig { metric, ..Default::default() });
+                }
+                "--text" => {
+                    let metric = args.next().ok_or_else(|| AppError("--text needs a metric".into()))?.parse()?;
+                    config.text = Some(TextConfig {
+                        metric,
+                        label: None,
+                        style: TextStyle::default(),
+                    });
+                }
+                "--text-label" => {
+                    let label = args.next().ok_or_else(|| AppError("--text-label needs a value".into()))?;
+                    config.text.as_mut().ok_or_else(|| AppError("--text-label needs --text first".into()))?.label = Some(label);
+                }
+                "--alert" => config.alerts.push(args.next().ok_or_else(|| AppError("--alert needs a rule".into()))?.parse()?),
+                "--alert-clip" | "--alert-message" | "--alert-recovery" => {
+                    let value = args.next().ok_or_else(|| AppError(format!("{} needs a value", arg)))?;
+                    let rule = config.alerts.last_mut().ok_or_else(|| AppError(format!("{} needs --alert first", arg)))?;
+                    match arg.as_str() {
+                        "--alert-clip" => rule.clip = Some(value),
+                        "--alert-message" => rule.message = Some(value),
+                        _ => rule.recovery = Some(value),
+                    }
+                }
+                "--notify" => config.notify = true,
+                "--disk" => config.disk = Some(args.next().ok_or_else(|| AppError("--disk needs a path".into()))?.into()),
+                "--transform" => config.frame_transform = args.next().ok_or_else(|| AppError("--transform needs steps".into()))?.parse()?,
+                "--frame-cache-mb" => config.frame_cache_budget = megabytes(&mut args, &arg)?,
+                "--stream-mb" => config.stream_budget = Some(megabytes(&mut args, &arg)?),
+                "--clip" => {
+                    let (Some(name), Some(threshold), Some(path)) = (args.next(), args.next(), args.next()) else {
+                        return Err(AppError("--clip needs a name, a threshold and a path".into()));
+                    };
+                    let threshold = threshold.parse().map_err(|_| AppError(format!("Bad clip threshold {}", threshold)))?;
+                    config.clips.push(ClipDef {
+                        name,
+                        source: ClipSource::File(path),
+                        threshold,
+                        transform: FrameTransform::default(),
+                    });
+                }
+                "--clip-frames" => {
+                    let range = args
+                        .next()
+                        .and_then(|range| {
+                            let (start, end) = range.split_once("..")?;
+                            Some(start.parse().ok()?..end.parse().ok()?)
+                        })
+                        .ok_or_else(|| AppError("--clip-frames needs <start>..<end>".into()))?;
+                    let clip = config.clips.last_mut().ok_or_else(|| AppError("--clip-frames needs --clip first".into()))?;
+                    let (ClipSource::File(path) | ClipSource::Frames(path, _)) = &clip.source;
+                    clip.source = ClipSource::Frames(path.clone(), range);
+                }
+                "--clip-transform" => {
+                    let transform = args.next().ok_or_else(|| AppError("--clip-transform needs steps".into()))?.parse()?;
+                    let clip = config.clips.last_mut().ok_or_else(|| AppError("--clip-transform needs --clip first".into()))?;
+                    clip.transform = transform;
+                }
+                "--clip-hysteresis" => config.clip_hysteresis = number(&mut args, &arg)?,
+                "--recolor" => {
+                    let metric = args.next().ok_or_else(|| AppError("--recolor needs a metric".into()))?.parse()?;
+                    config.recolor = Some(RecolorConfig {
+                        metric,
+                        stops: ColorStops::default(),
+                    });
+                }
+                _ => return Err(AppError(format!("Unknown argument {}", arg))),
+            }
+        }
+        Ok(config)
+    }
+}
+
+fn number<T: FromStr>(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<T, AppError> {
+    args.next()
+        .and_then(|v| v.parse().ok())
+        .ok_or_else(|| AppError(format!("{} needs a number", flag)))
+}
+
+fn megabytes(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<usize, AppError> {
+    let megabytes: usize = number(args, flag)?;
+    megabytes
+        .checked_mul(1 << 20)
+        .ok_or_else(|| AppError(format!("{} {} is too large", flag, megabytes)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::transform::{Filter, FrameOp};
+
+    fn parse(args: &str) -> Result<Config, AppError> {
+        Config::from_args(args.split_whitespace().map(str::to_string))
+    }
+
+    #[test]
+    fn clips() {
+        let config = parse("--clip idle 0 cat.gif --clip run 60 cat.gif --clip-frames 4..8 --clip-hysteresis 3").unwrap();
+        assert_eq!(config.clips.len(), 2);
+        assert_eq!(config.clips[0].source, ClipSource::File("cat.gif".into()));
+        assert_eq!((config.clips[1].name.as_str(), config.clips[1].threshold), ("run", 60.0));
+        assert_eq!(config.clips[1].source, ClipSource::Frames("cat.gif".into(), 4..8));
+        assert_eq!(config.clip_hysteresis, 3.0);
+        let config = parse("--clip idle 0 cat.gif --clip run 60 cat.gif --clip-transform flip-h").unwrap();
+        assert_eq!(config.clips[0].transform, FrameTransform::default());
+        assert_eq!(config.clips[1].transform, "flip-h".parse().unwrap());
+        assert!(parse("--clip-transform flip-h").is_err());
+        assert!(parse("--clip-frames 0..2").is_err());
+        assert!(parse("--clip idle low cat.gif").is_err());
+        assert!(parse("--clip idle 0 cat.gif --clip-frames 2").is_err());
+    }
+
+    #[test]
+    fn per_core() {
+        assert_eq!(parse("").unwrap().layout, Layout::Single);
+        assert_eq!(parse("--per-core 4").unwrap().layout, Layout::PerCore { columns: 4 });
+        assert!(parse("--per-core").is_err());
+    }
+
+    #[test]
+    fn process() {
+        let config = parse("--process 42").unwrap();
+        assert_eq!(config.process.map(|p| p.target), Some(ProcessTarget::Pid(42)));
+        assert_eq!(config.drive_metric, MetricKind::ProcessCpu);
+        let config = parse("--process firefox* --process-tree").unwrap();
+        assert_eq!(
+            config.process,
+            Some(ProcessFilter {
+                target: ProcessTarget::Name("firefox*".into()),
+                tree: true
+            })
+        );
+        assert!(parse("--process-tree").is_err());
+    }
+
+    #[test]
+    fn cgroup() {
+        assert_eq!(parse("--cgroup self").unwrap().cgroup, Some(CgroupTarget::Own));
+        let config = parse("--cgroup system.slice/docker.service").unwrap();
+        assert_eq!(config.cgroup, Some(CgroupTarget::Path("system.slice/docker.service".into())));
+        assert_eq!(config.drive_metric, MetricKind::CgroupCpu);
+    }
+
+    #[test]
+    fn hwmon() {
+        let hwmon = parse("--hwmon coretemp:Package* --hwmon-fan nct*").unwrap().hwmon.unwrap();
+        assert_eq!(hwmon.temperature.chip.as_deref(), Some("coretemp"));
+        assert_eq!(hwmon.temperature.label.as_deref(), Some("Package*"));
+        assert_eq!(hwmon.fan.and_then(|f| f.chip).as_deref(), Some("nct*"));
+        let config = parse("--heat-tint 50 90").unwrap();
+        assert_eq!(config.hwmon.unwrap().heat_tint, Some(HeatTint { cool: 50.0, hot: 90.0 }));
+        assert_eq!(config.drive_metric, MetricKind::Cpu);
+        assert!(parse("--heat-tint 50").is_err());
+    }
+
+    #[test]
+    fn budgets() {
+        let config = parse("--frame-cache-mb 16 --stream-mb 3").unwrap();
+        assert_eq!((config.frame_cache_budget, config.stream_budget), (16 << 20, Some(3 << 20)));
+        assert!(parse(&format!("--stream-mb {}", usize::MAX >> 10)).is_err());
+        assert!(parse("--frame-cache-mb lots").is_err());
+    }
+
+    #[test]
+    fn power() {
+        let power = parse("--power 20 pause").unwrap().power.unwrap();
+        assert_eq!((power.threshold, power.action), (20.0, BatteryAction::Pause));
+        assert_eq!(parse("--power 100 15").unwrap().power.unwrap().action, BatteryAction::CapFps(15.0));
+        assert!(parse("--power 20 slow").is_err());
+        assert!(parse("--power 20").is_err());
+    }
+
+    #[test]
+    fn input() {
+        let config = parse("--input window").unwrap();
+        assert_eq!(config.input, Some(InputCapture::Window));
+        assert_eq!(config.drive_metric, MetricKind::InputRate);
+        assert_eq!(parse("--input global").unwrap().input, Some(InputCapture::Global(Vec::new())));
+        let devices = parse("--input-device /dev/input/event3 --input-device /dev/input/event5").unwrap().input;
+        assert_eq!(
+            devices,
+            Some(InputCapture::Global(vec!["/dev/input/event3".into(), "/dev/input/event5".into()]))
+        );
+        assert_eq!(
+            parse("--input-fixture typing.txt").unwrap().input,
+            Some(InputCapture::Fixture("typing.txt".into()))
+        );
+        assert!(parse("--input everything").is_err());
+    }
+
+    #[test]
+    fn external_sources() {
+        let config = parse("--command ./gauge.sh").unwrap();
+        assert_eq!(config.command.map(|c| c.command).as_deref(), Some("./gauge.sh"));
+        assert_eq!(config.drive_metric, MetricKind::Command);
+        assert!(!config.stdin);
+        let config = parse("--stdin").unwrap();
+        assert!(config.stdin);
+        assert_eq!(config.drive_metric, MetricKind::Stdin);
+        assert!(parse("--command").is_err());
+    }
+
+    #[test]
+    fn log_tail() {
+        let config = parse("--log /var/log/syslog error|fail").unwrap();
+        let log_tail = config.log_tail.unwrap();
+        assert_eq!(log_tail.path, Path::new("/var/log/syslog"));
+        assert_eq!(log_tail.pattern, "error|fail");
+        assert_eq!(config.drive_metric, MetricKind::LogRate);
+        assert!(parse("--log /var/log/syslog").is_err());
+        assert!(parse("--log").is_err());
+    }
+
+    #[test]
+    fn replay() {
+        let config = parse("--replay day.trace --replay-once --replay-speed 2.5 --record out.trace").unwrap();
+        assert_eq!(
+            config.replay,
+            Some(TraceReplayConfig {
+                path: "day.trace".into(),
+                looping: false,
+                speed: 2.5
+            })
+        );
+        assert_eq!(config.record, Some("out.trace".into()));
+        let replay = parse("--replay day.trace").unwrap().replay.unwrap();
+        assert!(replay.looping);
+        assert_eq!(replay.speed, 1.0);
+        assert!(parse("--replay-once").is_err());
+        assert!(parse("--replay-speed 2").is_err());
+        assert!(parse("--replay day.trace --replay-speed 0").is_err());
+        assert!(parse("--replay day.trace --replay-speed fast").is_err());
+        assert!(parse("--record").is_err());
+    }
+
+    #[test]
+    fn script() {
+        assert_eq!(parse("--script policy.rhai").unwrap().script, Some("policy.rhai".into()));
+        assert_eq!(parse("").unwrap().script, None);
+        assert!(parse("--script").is_err());
+    }
+
+    #[test]
+    fn sparkline() {
+        let sparkline = parse("--sparkline Temperature").unwrap().sparkline.unwrap();
+        assert_eq!(
+            sparkline,
+            SparklineConfig {
+                metric: MetricKind::Temperature,
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            parse("--sparkline CpuCore(2)").unwrap().sparkline.map(|s| s.metric),
+            Some(MetricKind::CpuCore(2))
+        );
+        // only a source flag changes what drives the cat
+        assert_eq!(parse("--sparkline Fan").unwrap().drive_metric, MetricKind::Cpu);
+        assert!(parse("--sparkline Load").is_err());
+        assert!(parse("--sparkline").is_err());
+    }
+
+    #[test]
+    fn text() {
+        let text = parse("--text Cpu --text-label CPU").unwrap().text.unwrap();
+        assert_eq!((text.metric, text.label.as_deref()), (MetricKind::Cpu, Some("CPU")));
+        assert_eq!(text.style, TextStyle::default());
+        assert_eq!(parse("--text Battery").unwrap().text.and_then(|t| t.label), None);
+        assert!(parse("--text-label CPU").is_err());
+        assert!(parse("--text Cpu --text-label").is_err());
+        assert!(parse("--text Volts").is_err());
+    }
+
+    #[test]
+    fn alerts() {
+        let args = [
+            "--alert",
+            "Cpu > 90 for 30s",
+            "--alert-clip",
+            "panic",
+            "--alert-message",
+            "CPU at {value}",
+            "--alert",
+            "DiskFree < 5 for 1m cooldown 1h",
+            "--alert-recovery",
+            "Disk ok",
+            "--notify",
+            "--disk",
+            "/home",
+        ];
+        let config = Config::from_args(args.map(str::to_string)).unwrap();
+        assert_eq!(config.alerts.len(), 2);
+        let cpu = &config.alerts[0];
+        assert_eq!((cpu.metric, cpu.threshold, cpu.duration), (MetricKind::Cpu, 90.0, Duration::from_secs(30)));
+        assert_eq!((cpu.clip.as_deref(), cpu.message.as_deref()), (Some("panic"), Some("CPU at {value}")));
+        let disk = &config.alerts[1];
+        assert_eq!((disk.metric, disk.cooldown), (MetricKind::DiskFree, Duration::from_secs(3600)));
+        assert_eq!((disk.clip.as_deref(), disk.recovery.as_deref()), (None, Some("Disk ok")));
+        assert!(config.notify);
+        assert_eq!(config.disk, Some("/home".into()));
+        // watching a disk is for alerts and overlays, the cat stays on the CPU
+        assert_eq!(config.drive_metric, MetricKind::Cpu);
+
+        assert!(parse("--alert-clip panic").is_err());
+        assert!(Config::from_args(["--alert", "Cpu over 90"].map(str::to_string)).is_err());
+        assert!(Config::from_args(["--alert", "Cpu > 90", "--alert-message"].map(str::to_string)).is_err());
+        assert!(parse("--disk").is_err());
+    }
+
+    #[test]
+    fn recolor() {
+        let recolor = parse("--recolor Battery").unwrap().recolor.unwrap();
+        assert_eq!(
+            recolor,
+            RecolorConfig {
+                metric: MetricKind::Battery,
+                stops: ColorStops::default()
+            }
+        );
+        assert_eq!(parse("").unwrap().recolor, None);
+        assert!(parse("--recolor Heat").is_err());
+        assert!(parse("--recolor").is_err());
+    }
+
+    #[test]
+    fn transform() {
+        let config = Config::from_args(["--transform", "flip-h, rotate 270, pad 2, nearest"].map(str::to_string)).unwrap();
+        assert_eq!(
+            config.frame_transform,
+            FrameTransform {
+                ops: vec![
+                    FrameOp::FlipHorizontal,
+                    FrameOp::Rotate(3),
+                    FrameOp::Pad {
+                        left: 2,
+                        top: 2,
+                        right: 2,
+                        bottom: 2
+                    },
+                ],
+                filter: Filter::Nearest,
+            }
+        );
+        assert_eq!(parse("").unwrap().frame_transform, FrameTransform::default());
+        assert!(Config::from_args(["--transform", "rotate 45"].map(str::to_string)).is_err());
+        assert!(parse("--transform hq2x").is_err());
+        assert!(parse("--transform").is_err());
+    }
+}
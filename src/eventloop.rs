@@ -1,16 +1,27 @@
-use crate::ApplicationEventHandler;
+use crate::{
+    ApplicationEventHandler,
+    animation::PlaybackCommand,
+    metrics::{Sample, alert::AlertEvent, power::PowerState},
+};
 use std::sync::mpsc::{Receiver, Sender};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Event {
     AppCreate,
     AppDestory,
     AppRenderChange,
     Paint,
     Resize(u32, u32),
+    DpiChanged(u32),
     Close,
     KeyDown(u32),
+    /// Client coordinates from the window, relative motion from global input
     MouseMove(i32, i32),
+    Playback(PlaybackCommand),
+    Metric(Sample),
+    Power(PowerState),
+    Bubble,
+    Alert(AlertEvent),
 }
 
 pub struct EventLoop {
@@ -12,13 +12,35 @@ impl From<windows::core::Error> for AppError {
         Self(value.message())
     }
 }
+impl From<std::io::Error> for AppError {
+    fn from(value: std::io::Error) -> Self {
+        Self(value.to_string())
+    }
+}
+impl From<gif::DecodingError> for AppError {
+    fn from(value: gif::DecodingError) -> Self {
+        Self(value.to_string())
+    }
+}
+impl From<regex::Error> for AppError {
+    fn from(value: regex::Error) -> Self {
+        Self(value.to_string())
+    }
+}
 pub trait ApplicationEventHandler {
     fn resumed(&mut self, eventloop: &EventLoop);
     fn event(&mut self, eventloop: &EventLoop, event: Event);
 }
+pub mod animation;
 pub mod app;
+pub mod config;
 pub mod eventloop;
+pub mod metrics;
+pub mod notify;
+pub mod script;
 pub mod timer;
 pub mod window;
 pub mod render;
+#[cfg(test)]
+mod test_util;
 // pub mod my_error;
\ No newline at end of file
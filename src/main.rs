@@ -1,16 +1,135 @@
+use std::{sync::mpsc::Sender, time::Duration};
+
+use rust_zooming_cat_v2::AppError;
 use rust_zooming_cat_v2::app::App;
+use rust_zooming_cat_v2::config::{Config, Layout};
 use rust_zooming_cat_v2::eventloop::*;
+use rust_zooming_cat_v2::metrics::{
+    MetricSampler, MetricSource,
+    alert::AlertEngine,
+    cgroup::CgroupSource,
+    cpu::CpuSource,
+    disk::DiskSource,
+    external::{CommandSource, StdinSource},
+    hwmon::HwmonSource,
+    input::{self, InputActivity, InputActivitySource, InputCapture},
+    log_tail::LogTailSource,
+    power::PowerSupplySource,
+    process::ProcessSource,
+    trace::{TraceRecorder, TraceReplaySource},
+};
+use rust_zooming_cat_v2::notify::Notifier;
+use rust_zooming_cat_v2::script::ScriptPolicy;
 use rust_zooming_cat_v2::timer::TimerManager;
 
 fn main() {
     env_logger::builder().filter_level(log::LevelFilter::Debug).init();
     let (mut eventloop, sender) = EventLoop::new();
+    let config = match Config::from_args(std::env::args().skip(1)) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+    let metric_sampler = MetricSampler::new(sender.clone(), config.sample_interval);
+    let recorder = config
+        .record
+        .as_deref()
+        .and_then(|path| TraceRecorder::create(path).map_err(|e| log::warn!("Recording disabled: {}", e)).ok());
+    match config.replay.as_ref().map(TraceReplaySource::new) {
+        Some(Ok(source)) => add_source(&metric_sampler, recorder.as_ref(), source),
+        Some(Err(e)) => log::warn!("Replay disabled: {}", e),
+        None => add_source(
+            &metric_sampler,
+            recorder.as_ref(),
+            CpuSource::new().per_core(matches!(config.layout, Layout::PerCore { .. })),
+        ),
+    }
+    if let Some(filter) = config.process.clone() {
+        add_source(&metric_sampler, recorder.as_ref(), ProcessSource::new(filter));
+    }
+    if let Some(target) = config.cgroup.clone() {
+        match CgroupSource::new(target) {
+            Ok(source) => add_source(&metric_sampler, recorder.as_ref(), source),
+            Err(e) => log::warn!("Cgroup metrics disabled: {}", e),
+        }
+    }
+    if let Some(hwmon) = config.hwmon.clone() {
+        add_source(&metric_sampler, recorder.as_ref(), HwmonSource::new(hwmon.temperature, hwmon.fan));
+    }
+    if config.power.is_some() {
+        add_source(&metric_sampler, recorder.as_ref(), PowerSupplySource::new());
+    }
+    if let Some(command) = config.command.clone() {
+        add_source(&metric_sampler, recorder.as_ref(), CommandSource::new(command));
+    }
+    if config.stdin {
+        add_source(&metric_sampler, recorder.as_ref(), StdinSource::new(Duration::from_secs(30)));
+    }
+    if let Some(log_tail) = config.log_tail.clone() {
+        match LogTailSource::new(log_tail) {
+            Ok(source) => add_source(&metric_sampler, recorder.as_ref(), source),
+            Err(e) => log::warn!("Log metrics disabled: {}", e),
+        }
+    }
+    if let Some(path) = config.disk.clone() {
+        add_source(&metric_sampler, recorder.as_ref(), DiskSource::new(path));
+    }
+    if !config.alerts.is_empty() {
+        metric_sampler.set_alerts(AlertEngine::new(config.alerts.clone()));
+    }
+    let notifier = if config.notify {
+        Notifier::session("rust_zooming_cat").map_err(|e| log::warn!("Notifications disabled: {}", e)).ok()
+    } else {
+        None
+    };
+    let input_activity = config.input.clone().map(|capture| {
+        if let Err(e) = start_input(capture, &sender) {
+            log::warn!("Global input disabled: {}", e);
+        }
+        let activity = InputActivity::default();
+        add_source(&metric_sampler, recorder.as_ref(), InputActivitySource::new(activity.clone()));
+        activity
+    });
+    let script = config.script.as_deref().and_then(|path| ScriptPolicy::load(path).map_err(|e| log::warn!("Script disabled: {}", e)).ok());
     let timer_manager = TimerManager::new(sender);
     let mut app: App = App {
-        window: None,
-        render: None,
+        config,
         timer_manager: Some(timer_manager),
+        metric_sampler: Some(metric_sampler),
+        input_activity,
+        script,
+        notifier,
+        ..Default::default()
     };
 
     eventloop.run_app(&mut app);
 }
+
+/// Also written to the trace when recording
+fn add_source<S: MetricSource + 'static>(metric_sampler: &MetricSampler, recorder: Option<&TraceRecorder>, source: S) {
+    match recorder {
+        Some(recorder) => metric_sampler.add_source(recorder.record(source)),
+        None => metric_sampler.add_source(source),
+    }
+}
+
+fn start_input(capture: InputCapture, sender: &Sender<Event>) -> Result<(), AppError> {
+    match capture {
+        InputCapture::Window => {}
+        InputCapture::Global(devices) => {
+            let devices = if devices.is_empty() { input::event_devices()? } else { devices };
+            for device in devices {
+                // unreadable devices are skipped, the others still count
+                if let Err(e) = input::spawn_evdev_reader(&device, sender.clone()) {
+                    log::warn!("{}", e);
+                }
+            }
+        }
+        InputCapture::Fixture(path) => {
+            input::spawn_replay(input::parse_fixture(&std::fs::read_to_string(path)?)?, sender.clone())?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,188 @@
+use std::{
+    sync::{Arc, Mutex, mpsc::Sender},
+    thread,
+    time::Duration,
+};
+
+use log::warn;
+
+use crate::{AppError, eventloop::Event};
+
+pub mod alert;
+pub mod cgroup;
+pub mod cpu;
+pub mod disk;
+pub mod external;
+pub mod history;
+pub mod hwmon;
+pub mod input;
+pub mod log_tail;
+pub mod power;
+pub mod process;
+pub mod speed_curve;
+pub mod trace;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MetricKind {
+    /// Aggregate cpu utilisation in percent
+    Cpu,
+    /// Utilisation of one core in percent, numbered as in `/proc/stat`
+    CpuCore(u16),
+    /// Cpu share of the tracked processes in percent of the whole machine
+    ProcessCpu,
+    /// Resident memory of the tracked processes in MiB
+    ProcessMemory,
+    /// Cpu of a cgroup in percent of its `cpu.max` quota
+    CgroupCpu,
+    /// Memory of a cgroup in percent of its `memory.max` limit
+    CgroupMemory,
+    /// Hottest selected hwmon sensor in °C
+    Temperature,
+    /// Fastest selected fan in RPM
+    Fan,
+    /// Mean battery capacity in percent
+    Battery,
+    /// Key presses per second
+    KeyRate,
+    /// Pointer reports per second
+    PointerRate,
+    /// `KeyRate` plus `PointerRate`
+    InputRate,
+    /// First number printed by the configured command
+    Command,
+    /// Latest value read from stdin
+    Stdin,
+    /// Log lines matching the configured pattern per second
+    LogRate,
+    /// Free space left on the watched filesystem in percent
+    DiskFree,
+}
+
+impl MetricKind {
+    pub fn unit(&self) -> &'static str {
+        match self {
+            MetricKind::Cpu
+            | MetricKind::CpuCore(_)
+            | MetricKind::ProcessCpu
+            | MetricKind::CgroupCpu
+            | MetricKind::CgroupMemory
+            | MetricKind::Battery
+            | MetricKind::DiskFree => "%",
+            MetricKind::ProcessMemory => " MiB",
+            MetricKind::Temperature => "°C",
+            MetricKind::Fan => " RPM",
+            MetricKind::KeyRate | MetricKind::PointerRate | MetricKind::InputRate | MetricKind::LogRate => "/s",
+            MetricKind::Command | MetricKind::Stdin => "",
+        }
+    }
+}
+
+impl std::fmt::Display for MetricKind {
+    /// Variant name as in traces, `CpuCore(3)` for a core
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::str::FromStr for MetricKind {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(core) = s.strip_prefix("CpuCore(").and_then(|s| s.strip_suffix(')')) {
+            return core.parse().map(MetricKind::CpuCore).map_err(|_| AppError(format!("Bad core in {}", s)));
+        }
+        Ok(match s {
+            "Cpu" => MetricKind::Cpu,
+            "ProcessCpu" => MetricKind::ProcessCpu,
+            "ProcessMemory" => MetricKind::ProcessMemory,
+            "CgroupCpu" => MetricKind::CgroupCpu,
+            "CgroupMemory" => MetricKind::CgroupMemory,
+            "Temperature" => MetricKind::Temperature,
+            "Fan" => MetricKind::Fan,
+            "Battery" => MetricKind::Battery,
+            "KeyRate" => MetricKind::KeyRate,
+            "PointerRate" => MetricKind::PointerRate,
+            "InputRate" => MetricKind::InputRate,
+            "Command" => MetricKind::Command,
+            "Stdin" => MetricKind::Stdin,
+            "LogRate" => MetricKind::LogRate,
+            "DiskFree" => MetricKind::DiskFree,
+            _ => return Err(AppError(format!("Unknown metric {}", s))),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+    pub kind: MetricKind,
+    pub value: f32,
+}
+
+pub trait MetricSource: Send {
+    fn sample(&mut self) -> Result<Vec<Sample>, AppError>;
+
+    /// Events noticed while sampling, sent after the samples
+    fn events(&mut self) -> Vec<Event> {
+        Vec::new()
+    }
+}
+
+/// Polls every source on its own thread, each `Event::Metric` followed by the alerts it caused
+pub struct MetricSampler {
+    sources: Arc<Mutex<Vec<Box<dyn MetricSource>>>>,
+    alerts: Arc<Mutex<Option<alert::AlertEngine>>>,
+}
+
+impl MetricSampler {
+    pub fn new(sx: Sender<Event>, interval: Duration) -> Self {
+        let sources: Arc<Mutex<Vec<Box<dyn MetricSource>>>> = Arc::new(Mutex::new(Vec::new()));
+        let sources_clone = sources.clone();
+        let alerts: Arc<Mutex<Option<alert::AlertEngine>>> = Arc::new(Mutex::new(None));
+        let alerts_clone = alerts.clone();
+
+        thread::Builder::new()
+            .name("metric_thread".to_string())
+            .spawn(move || {
+                loop {
+                    let events = {
+                        let mut sources = sources_clone.lock().unwrap();
+                        let mut alerts = alerts_clone.lock().unwrap();
+                        let mut events = Vec::new();
+                        for source in sources.iter_mut() {
+                            match source.sample() {
+                                Ok(s) => {
+                                    for sample in s {
+                                        events.push(Event::Metric(sample));
+                                        if let Some(alerts) = alerts.as_mut() {
+                                            events.extend(alerts.observe(sample).into_iter().map(Event::Alert));
+                                        }
+                                    }
+                                }
+                                Err(e) => warn!("Metric source failed: {}", e),
+                            }
+                            events.extend(source.events());
+                        }
+                        events
+                    };
+
+                    for event in events {
+                        if sx.send(event).is_err() {
+                            return;
+                        }
+                    }
+                    thread::sleep(interval);
+                }
+            })
+            .expect("Failed to spawn metric thread");
+
+        Self { sources, alerts }
+    }
+
+    pub fn add_source<S: MetricSource + 'static>(&self, source: S) {
+        self.sources.lock().unwrap().push(Box::new(source));
+    }
+
+    pub fn set_alerts(&self, alerts: alert::AlertEngine) {
+        *self.alerts.lock().unwrap() = Some(alerts);
+    }
+}
@@ -0,0 +1,290 @@
+use std::{
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use super::{MetricKind, Sample};
+use crate::AppError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Above,
+    Below,
+}
+
+impl Comparison {
+    pub fn holds(self, value: f32, threshold: f32) -> bool {
+        match self {
+            Comparison::Above => value > threshold,
+            Comparison::Below => value < threshold,
+        }
+    }
+}
+
+/// Condition on one metric, written `Cpu > 90 for 30s` or `DiskFree < 5 for 1m cooldown 1h`
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlertRule {
+    pub metric: MetricKind,
+    pub comparison: Comparison,
+    pub threshold: f32,
+    /// How long the condition has to hold before the alert fires
+    pub duration: Duration,
+    /// Least time between two firings of the rule
+    pub cooldown: Duration,
+    pub clip: Option<String>,
+    /// Said and notified when firing, `{value}` is replaced by the value
+    pub message: Option<String>,
+    /// Said when the condition clears
+    pub recovery: Option<String>,
+}
+
+impl AlertRule {
+    /// `Cpu 93%!` unless the rule has its own message
+    pub fn fired_message(&self, value: f32) -> String {
+        self.fill(self.message.as_deref().unwrap_or("{metric} {value}!"), value)
+    }
+
+    /// `Cpu back to 40%` unless the rule has its own message
+    pub fn recovered_message(&self, value: f32) -> String {
+        self.fill(self.recovery.as_deref().unwrap_or("{metric} back to {value}"), value)
+    }
+
+    fn fill(&self, template: &str, value: f32) -> String {
+        template
+            .replace("{metric}", &self.metric.to_string())
+            .replace("{value}", &format!("{:.0}{}", value, self.metric.unit()))
+    }
+}
+
+/// `500ms`, `30s`, `5m`, `1h`, seconds without a unit
+pub fn parse_duration(text: &str) -> Result<Duration, AppError> {
+    let bad = || AppError(format!("Bad duration {}", text));
+    let (number, scale) = if let Some(ms) = text.strip_suffix("ms") {
+        (ms, 0.001)
+    } else if let Some(s) = text.strip_suffix('s') {
+        (s, 1.0)
+    } else if let Some(m) = text.strip_suffix('m') {
+        (m, 60.0)
+    } else if let Some(h) = text.strip_suffix('h') {
+        (h, 3600.0)
+    } else {
+        (text, 1.0)
+    };
+    let seconds: f32 = number.parse().map_err(|_| bad())?;
+    Duration::try_from_secs_f32(seconds * scale).map_err(|_| bad())
+}
+
+impl FromStr for AlertRule {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bad = || AppError(format!("Bad alert rule {:?}, expected e.g. \"Cpu > 90 for 30s\"", s));
+        let mut words = s.split_whitespace();
+        let (Some(metric), Some(comparison), Some(threshold)) = (words.next(), words.next(), words.next()) else {
+            return Err(bad());
+        };
+        let mut rule = AlertRule {
+            metric: metric.parse()?,
+            comparison: match comparison {
+                ">" => Comparison::Above,
+                "<" => Comparison::Below,
+                _ => return Err(bad()),
+            },
+            threshold: threshold.trim_end_matches('%').parse().map_err(|_| bad())?,
+            duration: Duration::ZERO,
+            cooldown: Duration::from_secs(300),
+            clip: None,
+            message: None,
+            recovery: None,
+        };
+        while let Some(word) = words.next() {
+            let value = parse_duration(words.next().ok_or_else(bad)?)?;
+            match word {
+                "for" => rule.duration = value,
+                "cooldown" => rule.cooldown = value,
+                _ => return Err(bad()),
+            }
+        }
+        Ok(rule)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertState {
+    Fired,
+    Recovered,
+}
+
+/// `rule` indexes the rules the engine was built with
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AlertEvent {
+    pub rule: usize,
+    pub state: AlertState,
+    pub value: f32,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct RuleState {
+    since: Option<Instant>,
+    firing: bool,
+    last_fired: Option<Instant>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AlertEngine {
+    rules: Vec<AlertRule>,
+    states: Vec<RuleState>,
+}
+
+impl AlertEngine {
+    pub fn new(rules: Vec<AlertRule>) -> Self {
+        Self {
+            states: vec![RuleState::default(); rules.len()],
+            rules,
+        }
+    }
+
+    pub fn rules(&self) -> &[AlertRule] {
+        &self.rules
+    }
+
+    pub fn observe(&mut self, sample: Sample) -> Vec<AlertEvent> {
+        self.observe_at(sample, Instant::now())
+    }
+
+    /// Fires once the condition held for the rule duration, outside its cooldown
+    pub fn observe_at(&mut self, sample: Sample, now: Instant) -> Vec<AlertEvent> {
+        let mut events = Vec::new();
+        for (rule, (rule_def, state)) in self.rules.iter().zip(self.states.iter_mut()).enumerate() {
+            if rule_def.metric != sample.kind {
+                continue;
+            }
+            let event = |state| AlertEvent {
+                rule,
+                state,
+                value: sample.value,
+            };
+            if !rule_def.comparison.holds(sample.value, rule_def.threshold) {
+                state.since = None;
+                if std::mem::take(&mut state.firing) {
+                    events.push(event(AlertState::Recovered));
+                }
+                continue;
+            }
+            let since = *state.since.get_or_insert(now);
+            let cooled_down = state.last_fired.is_none_or(|fired| now.duration_since(fired) >= rule_def.cooldown);
+            if !state.firing && now.duration_since(since) >= rule_def.duration && cooled_down {
+                state.firing = true;
+                state.last_fired = Some(now);
+                events.push(event(AlertState::Fired));
+            }
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(text: &str) -> AlertRule {
+        text.parse().unwrap()
+    }
+
+    fn error(text: &str) -> String {
+        text.parse::<AlertRule>().err().unwrap().0
+    }
+
+    #[test]
+    fn parses_rules() {
+        let cpu = rule("Cpu > 90 for 30s");
+        assert_eq!(
+            (cpu.metric, cpu.comparison, cpu.threshold, cpu.duration, cpu.cooldown),
+            (MetricKind::Cpu, Comparison::Above, 90.0, Duration::from_secs(30), Duration::from_secs(300))
+        );
+        let disk = rule("DiskFree < 5% cooldown 1h for 500ms");
+        assert_eq!(
+            (disk.comparison, disk.threshold, disk.duration, disk.cooldown),
+            (Comparison::Below, 5.0, Duration::from_millis(500), Duration::from_secs(3600))
+        );
+        assert_eq!(rule("CpuCore(2) > 50").duration, Duration::ZERO);
+
+        assert!(error("Cpu >= 90 for 30s").starts_with("Bad alert rule"));
+        assert!(error("Cpu > ninety").starts_with("Bad alert rule"));
+        assert_eq!(error("Cpu > 90 for 3x"), "Bad duration 3x");
+        assert_eq!(error("Cpu > 90 for -1s"), "Bad duration -1s");
+        assert!(error("Cpu > 90 for").starts_with("Bad alert rule"));
+        assert!(error("Cpu > 90 every 1s").starts_with("Bad alert rule"));
+        assert!("Gpu > 90".parse::<AlertRule>().is_err());
+        assert!("Cpu >".parse::<AlertRule>().is_err());
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("250ms").unwrap(), Duration::from_millis(250));
+        assert_eq!(parse_duration("1.5m").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("2h").unwrap(), Duration::from_secs(7200));
+        assert_eq!(parse_duration("7").unwrap(), Duration::from_secs(7));
+        assert!(parse_duration("m").is_err());
+    }
+
+    /// Feeds `(seconds, value)` Cpu samples, returns the events with the second they came at
+    fn run(rule: &str, samples: &[(u64, f32)]) -> Vec<(u64, AlertState, f32)> {
+        let mut engine = AlertEngine::new(vec![self::rule(rule)]);
+        let start = Instant::now();
+        samples
+            .iter()
+            .flat_map(|&(secs, value)| {
+                engine
+                    .observe_at(Sample { kind: MetricKind::Cpu, value }, start + Duration::from_secs(secs))
+                    .into_iter()
+                    .map(move |event| (secs, event.state, event.value))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fires_after_holding() {
+        let samples = [(0, 95.0), (10, 95.0), (20, 80.0), (25, 95.0), (50, 95.0), (55, 96.0), (60, 97.0)];
+        // the dip at 20 s starts the 30 s over
+        assert_eq!(run("Cpu > 90 for 30s", &samples), [(55, AlertState::Fired, 96.0)]);
+        // other metrics are ignored
+        let mut engine = AlertEngine::new(vec![rule("Cpu > 90")]);
+        assert!(
+            engine
+                .observe(Sample {
+                    kind: MetricKind::Fan,
+                    value: 100.0
+                })
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn recovers_then_waits_for_cooldown() {
+        let samples = [(0, 95.0), (5, 40.0), (10, 95.0), (30, 95.0), (60, 95.0), (61, 50.0)];
+        assert_eq!(
+            run("Cpu > 90 cooldown 1m", &samples),
+            [
+                (0, AlertState::Fired, 95.0),
+                (5, AlertState::Recovered, 40.0),
+                (60, AlertState::Fired, 95.0),
+                (61, AlertState::Recovered, 50.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn messages() {
+        let cpu = rule("Cpu > 90");
+        assert_eq!(cpu.fired_message(93.4), "Cpu 93%!");
+        assert_eq!(cpu.recovered_message(40.0), "Cpu back to 40%");
+        let custom = AlertRule {
+            message: Some("hot at {value}".into()),
+            recovery: Some("{metric} calm".into()),
+            ..rule("Temperature > 80")
+        };
+        assert_eq!(custom.fired_message(85.0), "hot at 85°C");
+        assert_eq!(custom.recovered_message(60.0), "Temperature calm");
+    }
+}
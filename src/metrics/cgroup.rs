@@ -0,0 +1,163 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use super::{MetricKind, MetricSource, Sample};
+use crate::AppError;
+
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CgroupTarget {
+    Own,
+    /// A cgroup directory, absolute or relative to `/sys/fs/cgroup`
+    Path(PathBuf),
+}
+
+/// Cpu and memory of a cgroup v2 in percent of its quota, or of the machine without one
+pub struct CgroupSource {
+    dir: PathBuf,
+    meminfo: PathBuf,
+    last: Option<(u64, Instant)>,
+}
+
+fn keyed_value(content: &str, key: &str) -> Option<u64> {
+    content.lines().find_map(|line| {
+        let mut parts = line.split_whitespace();
+        (parts.next()?.trim_end_matches(':') == key).then(|| parts.next()?.parse().ok())?
+    })
+}
+
+/// Cpus granted by `cpu.max` (`$MAX $PERIOD`), `None` when unlimited
+pub fn parse_cpu_max(content: &str) -> Option<f32> {
+    let mut parts = content.split_whitespace();
+    let quota: f32 = parts.next()?.parse().ok()?;
+    let period: f32 = parts.next().and_then(|p| p.parse().ok()).unwrap_or(100_000.0);
+    (period > 0.0).then_some(quota / period)
+}
+
+pub fn cpu_utilisation(usage_usec: u64, wall_usec: u64, limit_cpus: f32) -> f32 {
+    if wall_usec == 0 || limit_cpus <= 0.0 {
+        return 0.0;
+    }
+    usage_usec as f32 * 100.0 / (wall_usec as f32 * limit_cpus)
+}
+
+impl CgroupSource {
+    pub fn new(target: CgroupTarget) -> Result<Self, AppError> {
+        let dir = match target {
+            CgroupTarget::Own => {
+                let cgroup = fs::read_to_string("/proc/self/cgroup")?;
+                Self::own_dir(&cgroup, Path::new(CGROUP_ROOT))?
+            }
+            CgroupTarget::Path(path) => Path::new(CGROUP_ROOT).join(path),
+        };
+        Ok(Self::with_paths(dir, "/proc/meminfo"))
+    }
+
+    pub fn with_paths(dir: impl Into<PathBuf>, meminfo: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            meminfo: meminfo.into(),
+            last: None,
+        }
+    }
+
+    /// Resolves the unified hierarchy entry (`0::/path`) of a `/proc/<pid>/cgroup` file
+    pub fn own_dir(cgroup: &str, root: &Path) -> Result<PathBuf, AppError> {
+        let path = cgroup
+            .lines()
+            .find_map(|line| line.strip_prefix("0::"))
+            .ok_or_else(|| AppError("No cgroup v2 entry, is the unified hierarchy mounted?".into()))?;
+        Ok(root.join(path.trim().trim_start_matches('/')))
+    }
+
+    fn read(&self, file: &str) -> Result<String, AppError> {
+        fs::read_to_string(self.dir.join(file)).map_err(|e| AppError(format!("{}: {}", self.dir.join(file).display(), e)))
+    }
+
+    fn memory_limit(&self) -> Result<u64, AppError> {
+        if let Some(max) = self.read("memory.max").ok().and_then(|m| m.trim().parse().ok()) {
+            return Ok(max);
+        }
+        keyed_value(&fs::read_to_string(&self.meminfo)?, "MemTotal")
+            .map(|kb| kb * 1024)
+            .ok_or_else(|| AppError(format!("No MemTotal in {}", self.meminfo.display())))
+    }
+}
+
+impl MetricSource for CgroupSource {
+    fn sample(&mut self) -> Result<Vec<Sample>, AppError> {
+        let mut samples = Vec::new();
+
+        let memory: u64 = self.read("memory.current")?.trim().parse().map_err(|_| AppError("Bad memory.current".into()))?;
+        samples.push(Sample {
+            kind: MetricKind::CgroupMemory,
+            value: memory as f32 * 100.0 / self.memory_limit()?.max(1) as f32,
+        });
+
+        let usage = keyed_value(&self.read("cpu.stat")?, "usage_usec").ok_or_else(|| AppError("No usage_usec in cpu.stat".into()))?;
+        let now = Instant::now();
+        if let Some((prev_usage, prev_time)) = self.last.replace((usage, now)) {
+            let limit = self
+                .read("cpu.max")
+                .ok()
+                .and_then(|m| parse_cpu_max(&m))
+                .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()) as f32);
+            samples.push(Sample {
+                kind: MetricKind::CgroupCpu,
+                value: cpu_utilisation(usage.saturating_sub(prev_usage), now.duration_since(prev_time).as_micros() as u64, limit),
+            });
+        }
+        Ok(samples)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn cpu_max() {
+        assert_eq!(parse_cpu_max("150000 100000\n"), Some(1.5));
+        assert_eq!(parse_cpu_max("50000"), Some(0.5));
+        assert_eq!(parse_cpu_max("max 100000\n"), None);
+        assert_eq!(cpu_utilisation(500_000, 1_000_000, 0.5), 100.0);
+        assert_eq!(cpu_utilisation(500_000, 0, 0.5), 0.0);
+    }
+
+    #[test]
+    fn own_dir() {
+        let root = Path::new("/sys/fs/cgroup");
+        assert_eq!(
+            CgroupSource::own_dir("0::/user.slice/app.scope\n", root).unwrap(),
+            root.join("user.slice/app.scope")
+        );
+        assert!(CgroupSource::own_dir("1:name=systemd:/init.scope\n", root).is_err());
+    }
+
+    #[test]
+    fn memory_against_limit() {
+        let dir = TempDir::new("cgroup_fixture");
+        fs::write(dir.join("memory.current"), "268435456\n").unwrap();
+        fs::write(dir.join("memory.max"), "max\n").unwrap();
+        fs::write(dir.join("cpu.stat"), "usage_usec 1000\nuser_usec 800\n").unwrap();
+        fs::write(dir.join("meminfo"), "MemTotal:        1048576 kB\nMemFree:  1 kB\n").unwrap();
+        let mut source = CgroupSource::with_paths(&*dir, dir.join("meminfo"));
+        let first = source.sample().unwrap();
+        fs::write(dir.join("memory.max"), "536870912\n").unwrap();
+        let second = source.sample().unwrap();
+        assert_eq!(
+            first,
+            [Sample {
+                kind: MetricKind::CgroupMemory,
+                value: 25.0
+            }]
+        );
+        assert_eq!(second[0].value, 50.0);
+        assert_eq!(second[1].kind, MetricKind::CgroupCpu);
+    }
+}
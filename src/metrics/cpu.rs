@@ -0,0 +1,141 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use super::{MetricKind, MetricSource, Sample};
+use crate::AppError;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CpuTimes {
+    pub busy: u64,
+    pub idle: u64,
+}
+
+impl CpuTimes {
+    /// Idle includes iowait
+    pub fn parse(fields: &str) -> Option<Self> {
+        let values: Vec<u64> = fields.split_whitespace().map(|v| v.parse().ok()).collect::<Option<_>>()?;
+        if values.len() < 4 {
+            return None;
+        }
+        // guest and guest_nice are already counted in user and nice
+        let total: u64 = values.iter().take(8).sum();
+        let idle = values[3] + values.get(4).copied().unwrap_or(0);
+        Some(Self { busy: total - idle, idle })
+    }
+
+    pub fn usage_since(&self, prev: &CpuTimes) -> f32 {
+        let busy = self.busy.saturating_sub(prev.busy);
+        let total = busy + self.idle.saturating_sub(prev.idle);
+        if total == 0 {
+            return 0.0;
+        }
+        busy as f32 * 100.0 / total as f32
+    }
+}
+
+pub fn parse_stat(stat: &str) -> Vec<(MetricKind, CpuTimes)> {
+    stat.lines()
+        .filter_map(|line| {
+            let (label, fields) = line.split_once(char::is_whitespace)?;
+            let kind = match label.strip_prefix("cpu")? {
+                "" => MetricKind::Cpu,
+                core => MetricKind::CpuCore(core.parse().ok()?),
+            };
+            Some((kind, CpuTimes::parse(fields)?))
+        })
+        .collect()
+}
+
+pub struct CpuSource {
+    path: PathBuf,
+    per_core: bool,
+    last: HashMap<MetricKind, CpuTimes>,
+}
+
+impl CpuSource {
+    pub fn new() -> Self {
+        Self::with_path("/proc/stat")
+    }
+
+    pub fn with_path(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            per_core: false,
+            last: HashMap::new(),
+        }
+    }
+
+    pub fn per_core(mut self, per_core: bool) -> Self {
+        self.per_core = per_core;
+        self
+    }
+}
+
+impl Default for CpuSource {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MetricSource for CpuSource {
+    fn sample(&mut self) -> Result<Vec<Sample>, AppError> {
+        let stat = std::fs::read_to_string(&self.path)?;
+        let readings = parse_stat(&stat);
+        if readings.is_empty() {
+            return Err(AppError(format!("No cpu line in {}", self.path.display())));
+        }
+
+        let mut samples = Vec::new();
+        for (kind, times) in readings {
+            if kind != MetricKind::Cpu && !self.per_core {
+                continue;
+            }
+            // the first reading only sets the baseline
+            if let Some(prev) = self.last.insert(kind, times) {
+                samples.push(Sample {
+                    kind,
+                    value: times.usage_since(&prev),
+                });
+            }
+        }
+        Ok(samples)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    const STAT: &str = "cpu  100 0 100 700 100 0 0 0 0 0\ncpu0 50 0 50 300 100 0 0 0 0 0\ncpu1 50 0 50 400 0 0 0 0 0 0\nintr 12345 1 2\ncpufreq 1 2 3 4\n";
+
+    #[test]
+    fn per_core_lines() {
+        let readings = parse_stat(STAT);
+        let kinds: Vec<MetricKind> = readings.iter().map(|(kind, _)| *kind).collect();
+        assert_eq!(kinds, [MetricKind::Cpu, MetricKind::CpuCore(0), MetricKind::CpuCore(1)]);
+        assert_eq!(readings[1].1, CpuTimes { busy: 100, idle: 400 });
+        assert_eq!(CpuTimes::parse("1 2 3"), None);
+    }
+
+    #[test]
+    fn usage_between_readings() {
+        let prev = CpuTimes { busy: 100, idle: 400 };
+        assert_eq!(CpuTimes { busy: 150, idle: 450 }.usage_since(&prev), 50.0);
+        assert_eq!(prev.usage_since(&prev), 0.0);
+        // counters going backwards, e.g. after a cpu went offline
+        assert_eq!(CpuTimes { busy: 50, idle: 500 }.usage_since(&prev), 0.0);
+    }
+
+    #[test]
+    fn source_reports_cores_from_second_sample() {
+        let dir = TempDir::new("cpu_stat");
+        let path = dir.write("stat", STAT);
+        let mut source = CpuSource::with_path(&path).per_core(true);
+        assert!(source.sample().unwrap().is_empty());
+        std::fs::write(&path, STAT.replace("cpu0 50 0 50 300", "cpu0 150 0 50 300")).unwrap();
+        let samples = source.sample().unwrap();
+        let core0 = samples.iter().find(|s| s.kind == MetricKind::CpuCore(0)).unwrap();
+        assert_eq!(core0.value, 100.0);
+        assert_eq!(samples.len(), 3);
+    }
+}
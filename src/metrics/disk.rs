@@ -0,0 +1,80 @@
+use std::path::{Path, PathBuf};
+
+use super::{MetricKind, MetricSource, Sample};
+use crate::AppError;
+
+/// Free as available to this user
+#[cfg(unix)]
+pub fn disk_space(path: &Path) -> Result<(u64, u64), AppError> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let c_path = CString::new(path.as_os_str().as_bytes()).map_err(|_| AppError(format!("Bad path {}", path.display())))?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return Err(AppError(format!("statvfs {}: {}", path.display(), std::io::Error::last_os_error())));
+    }
+    let block = stat.f_frsize as u64;
+    Ok((stat.f_bavail as u64 * block, stat.f_blocks as u64 * block))
+}
+
+#[cfg(windows)]
+pub fn disk_space(path: &Path) -> Result<(u64, u64), AppError> {
+    use windows::{Win32::Storage::FileSystem::GetDiskFreeSpaceExW, core::HSTRING};
+
+    let (mut free, mut total) = (0u64, 0u64);
+    unsafe { GetDiskFreeSpaceExW(&HSTRING::from(path), Some(&raw mut free), Some(&raw mut total), None) }
+        .map_err(|e| AppError(format!("GetDiskFreeSpaceEx {}: {}", path.display(), e)))?;
+    Ok((free, total))
+}
+
+pub struct DiskSource {
+    path: PathBuf,
+}
+
+impl DiskSource {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl MetricSource for DiskSource {
+    fn sample(&mut self) -> Result<Vec<Sample>, AppError> {
+        let (free, total) = disk_space(&self.path)?;
+        Ok(free_percent(free, total)
+            .map(|value| Sample {
+                kind: MetricKind::DiskFree,
+                value,
+            })
+            .into_iter()
+            .collect())
+    }
+}
+
+/// `None` for pseudo filesystems without blocks
+fn free_percent(free: u64, total: u64) -> Option<f32> {
+    (total > 0).then(|| (free as f64 / total as f64 * 100.0) as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn percent_free() {
+        assert_eq!(free_percent(25, 100), Some(25.0));
+        assert_eq!(free_percent(0, 0), None);
+        // terabyte disks keep their precision
+        assert_eq!(free_percent(3 << 40, 4 << 40), Some(75.0));
+    }
+
+    #[test]
+    fn samples_a_real_filesystem() {
+        let dir = TempDir::new("disk");
+        let (free, total) = disk_space(&dir).unwrap();
+        assert!(total > 0 && free <= total);
+        let samples = DiskSource::new(&*dir).sample().unwrap();
+        assert!(samples[0].kind == MetricKind::DiskFree && (0.0..=100.0).contains(&samples[0].value));
+        assert!(DiskSource::new(dir.join("missing")).sample().is_err());
+    }
+}
@@ -0,0 +1,248 @@
+use std::{
+    collections::VecDeque,
+    io::{BufRead, BufReader, Read},
+    process::{Command, Stdio},
+    sync::{
+        Arc, Mutex, Weak,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::warn;
+
+use super::{MetricKind, MetricSource, Sample};
+use crate::AppError;
+
+/// First number found in `output`, e.g. `42`, `queue depth: 17` or `98.5%`
+pub fn parse_number(output: &str) -> Result<f32, AppError> {
+    output
+        .split(|c: char| !(c.is_ascii_digit() || matches!(c, '.' | '-' | '+' | 'e' | 'E')))
+        // longest prefix that parses, so `1.2.3` gives 1.2 and a sentence's final '.' is dropped
+        .find_map(|token| (1..=token.len()).rev().find_map(|end| token[..end].parse::<f32>().ok()))
+        .ok_or_else(|| AppError(format!("No number in {:?}", output.trim())))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandConfig {
+    /// Run through the platform shell
+    pub command: String,
+    pub interval: Duration,
+    pub timeout: Duration,
+}
+
+/// Runs a shell command every `interval` on its own thread
+pub struct CommandSource {
+    last: Arc<Mutex<Option<Result<f32, AppError>>>>,
+}
+
+impl CommandSource {
+    pub fn new(config: CommandConfig) -> Self {
+        let last = Arc::new(Mutex::new(None));
+        let weak = Arc::downgrade(&last);
+        thread::Builder::new()
+            .name("command_thread".into())
+            .spawn(move || Self::run_every(config, weak))
+            .expect("Failed to spawn command thread");
+        Self { last }
+    }
+
+    /// Ends once the source is dropped
+    fn run_every(config: CommandConfig, last: Weak<Mutex<Option<Result<f32, AppError>>>>) {
+        loop {
+            let start = Instant::now();
+            let value = run(&config).and_then(|output| parse_number(&output));
+            let Some(last) = last.upgrade() else {
+                return;
+            };
+            *last.lock().unwrap() = Some(value);
+            drop(last);
+            thread::sleep(config.interval.saturating_sub(start.elapsed()));
+        }
+    }
+}
+
+fn run(config: &CommandConfig) -> Result<String, AppError> {
+    let mut command = if cfg!(windows) {
+        let mut command = Command::new("cmd");
+        command.arg("/C");
+        command
+    } else {
+        let mut command = Command::new("sh");
+        command.arg("-c");
+        command
+    };
+    let mut child = command
+        .arg(&config.command)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| AppError(format!("{}: {}", config.command, e)))?;
+
+    // read on another thread so a chatty command can't fill the pipe and stall
+    let mut stdout = child.stdout.take().ok_or_else(|| AppError("No stdout".into()))?;
+    let reader = thread::spawn(move || {
+        let mut output = String::new();
+        let _ = stdout.read_to_string(&mut output);
+        output
+    });
+
+    let start = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            let output = reader.join().unwrap_or_default();
+            if !status.success() {
+                return Err(AppError(format!("{} exited with {}", config.command, status)));
+            }
+            return Ok(output);
+        }
+        if start.elapsed() >= config.timeout {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(AppError(format!("{} timed out after {:?}", config.command, config.timeout)));
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+impl MetricSource for CommandSource {
+    fn sample(&mut self) -> Result<Vec<Sample>, AppError> {
+        match self.last.lock().unwrap().take() {
+            Some(value) => Ok(vec![Sample {
+                kind: MetricKind::Command,
+                value: value?,
+            }]),
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+pub struct StdinSource {
+    lines: Arc<Mutex<VecDeque<Result<f32, AppError>>>>,
+    closed: Arc<AtomicBool>,
+    timeout: Duration,
+    last_value: Instant,
+}
+
+impl StdinSource {
+    /// Reports an error once no value arrived for `timeout`
+    pub fn new(timeout: Duration) -> Self {
+        Self::from_reader(BufReader::new(std::io::stdin()), timeout)
+    }
+
+    pub fn from_reader<R: BufRead + Send + 'static>(reader: R, timeout: Duration) -> Self {
+        let lines = Arc::new(Mutex::new(VecDeque::new()));
+        let lines_clone = lines.clone();
+        let closed = Arc::new(AtomicBool::new(false));
+        let closed_clone = closed.clone();
+        thread::Builder::new()
+            .name("stdin_thread".into())
+            .spawn(move || {
+                for line in reader.lines() {
+                    let value = line.map_err(AppError::from).and_then(|line| parse_number(&line));
+                    lines_clone.lock().unwrap().push_back(value);
+                }
+                closed_clone.store(true, Ordering::Relaxed);
+            })
+            .expect("Failed to spawn stdin thread");
+        Self {
+            lines,
+            closed,
+            timeout,
+            last_value: Instant::now(),
+        }
+    }
+}
+
+impl MetricSource for StdinSource {
+    fn sample(&mut self) -> Result<Vec<Sample>, AppError> {
+        let mut samples = Vec::new();
+        let mut error = None;
+        for line in self.lines.lock().unwrap().drain(..) {
+            match line {
+                Ok(value) => samples.push(Sample { kind: MetricKind::Stdin, value }),
+                Err(e) => error = Some(e),
+            }
+        }
+        if !samples.is_empty() {
+            if let Some(e) = error {
+                warn!("Skipped stdin line: {}", e);
+            }
+            self.last_value = Instant::now();
+            return Ok(samples);
+        }
+        if let Some(e) = error {
+            return Err(e);
+        }
+        // reported once, later samples fall back to the timeout warning
+        if self.closed.swap(false, Ordering::Relaxed) {
+            return Err(AppError("stdin closed".into()));
+        }
+        if self.last_value.elapsed() >= self.timeout {
+            self.last_value = Instant::now();
+            return Err(AppError(format!("No value on stdin for {:?}", self.timeout)));
+        }
+        Ok(samples)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers() {
+        assert_eq!(parse_number("42\n").unwrap(), 42.0);
+        assert_eq!(parse_number("queue depth: 17").unwrap(), 17.0);
+        assert_eq!(parse_number("load is 98.5%.").unwrap(), 98.5);
+        assert_eq!(parse_number("version 1.2.3").unwrap(), 1.2);
+        assert_eq!(parse_number("-3 degrees").unwrap(), -3.0);
+        assert!(parse_number("nothing here").is_err());
+    }
+
+    fn next(source: &mut CommandSource) -> Result<Vec<Sample>, AppError> {
+        let start = Instant::now();
+        loop {
+            let samples = source.sample();
+            if samples.as_ref().map_or(true, |s| !s.is_empty()) || start.elapsed() > Duration::from_secs(5) {
+                return samples;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    fn command(command: &str, timeout: Duration) -> CommandSource {
+        CommandSource::new(CommandConfig {
+            command: command.into(),
+            interval: Duration::from_millis(20),
+            timeout,
+        })
+    }
+
+    #[test]
+    fn reports_output() {
+        let mut source = command("echo 'depth: 7'", Duration::from_secs(5));
+        assert_eq!(
+            next(&mut source).unwrap(),
+            [Sample {
+                kind: MetricKind::Command,
+                value: 7.0
+            }]
+        );
+        assert!(next(&mut source).is_ok());
+        assert!(next(&mut command("exit 3", Duration::from_secs(5))).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn slow_command_does_not_block() {
+        let mut source = command("sleep 1; echo 1", Duration::from_millis(50));
+        let start = Instant::now();
+        assert!(source.sample().unwrap().is_empty());
+        assert!(start.elapsed() < Duration::from_millis(50));
+        // killed at the timeout
+        assert!(next(&mut source).unwrap_err().0.contains("timed out"));
+    }
+}
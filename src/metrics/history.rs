@@ -0,0 +1,284 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+use super::{MetricKind, Sample};
+
+/// Samples merged into buckets of `resolution`, the last `capacity` kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tier {
+    pub resolution: Duration,
+    pub capacity: usize,
+}
+
+impl Tier {
+    pub fn span(&self) -> Duration {
+        self.resolution * self.capacity as u32
+    }
+}
+
+/// 1 s buckets for 10 minutes, 1 min buckets for 24 hours
+pub const DEFAULT_TIERS: [Tier; 2] = [
+    Tier {
+        resolution: Duration::from_secs(1),
+        capacity: 600,
+    },
+    Tier {
+        resolution: Duration::from_secs(60),
+        capacity: 1440,
+    },
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Bucket {
+    index: u64,
+    min: f32,
+    max: f32,
+    sum: f32,
+    count: u32,
+}
+
+impl Bucket {
+    fn mean(&self) -> f32 {
+        self.sum / self.count as f32
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stats {
+    pub min: f32,
+    pub max: f32,
+    pub avg: f32,
+    /// Nearest rank over bucket means, exact while samples are no denser than the tier
+    pub p95: f32,
+    pub count: u32,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Series {
+    latest: Option<f32>,
+    tiers: Vec<VecDeque<Bucket>>,
+}
+
+/// Time series of every metric, downsampled as it ages
+#[derive(Debug, Clone)]
+pub struct MetricHistory {
+    origin: Instant,
+    tiers: Vec<Tier>,
+    series: HashMap<MetricKind, Series>,
+}
+
+impl Default for MetricHistory {
+    fn default() -> Self {
+        Self::new(DEFAULT_TIERS.to_vec())
+    }
+}
+
+impl MetricHistory {
+    pub fn new(mut tiers: Vec<Tier>) -> Self {
+        tiers.retain(|t| !t.resolution.is_zero() && t.capacity > 0);
+        tiers.sort_by_key(|t| t.resolution);
+        Self {
+            origin: Instant::now(),
+            tiers,
+            series: HashMap::new(),
+        }
+    }
+
+    pub fn record(&mut self, sample: Sample) {
+        self.record_at(sample, Instant::now());
+    }
+
+    pub fn record_at(&mut self, sample: Sample, at: Instant) {
+        let since = at.saturating_duration_since(self.origin);
+        let series = self.series.entry(sample.kind).or_default();
+        series.latest = Some(sample.value);
+        series.tiers.resize_with(self.tiers.len(), VecDeque::new);
+        for (tier, buckets) in self.tiers.iter().zip(series.tiers.iter_mut()) {
+            let index = (since.as_nanos() / tier.resolution.as_nanos()) as u64;
+            match buckets.back_mut() {
+                // late samples land in the newest bucket rather than reordering the ring
+                Some(bucket) if bucket.index >= index => {
+                    bucket.min = bucket.min.min(sample.value);
+                    bucket.max = bucket.max.max(sample.value);
+                    bucket.sum += sample.value;
+                    bucket.count += 1;
+                }
+                _ => {
+                    buckets.push_back(Bucket {
+                        index,
+                        min: sample.value,
+                        max: sample.value,
+                        sum: sample.value,
+                        count: 1,
+                    });
+                    if buckets.len() > tier.capacity {
+                        buckets.pop_front();
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn latest(&self, kind: MetricKind) -> Option<f32> {
+        self.series.get(&kind)?.latest
+    }
+
+    pub fn stats(&self, kind: MetricKind, window: Duration) -> Option<Stats> {
+        self.stats_at(kind, window, Instant::now())
+    }
+
+    pub fn stats_at(&self, kind: MetricKind, window: Duration, now: Instant) -> Option<Stats> {
+        let buckets = self.window(kind, window, now);
+        if buckets.is_empty() {
+            return None;
+        }
+        let count = buckets.iter().map(|b| b.count).sum();
+        let mut means: Vec<f32> = buckets.iter().map(Bucket::mean).collect();
+        means.sort_by(f32::total_cmp);
+        let rank = ((means.len() as f32 * 0.95).ceil() as usize).clamp(1, means.len());
+        Some(Stats {
+            min: buckets.iter().map(|b| b.min).fold(f32::INFINITY, f32::min),
+            max: buckets.iter().map(|b| b.max).fold(f32::NEG_INFINITY, f32::max),
+            avg: buckets.iter().map(|b| b.sum).sum::<f32>() / count as f32,
+            p95: means[rank - 1],
+            count,
+        })
+    }
+
+    pub fn values(&self, kind: MetricKind, window: Duration) -> Vec<f32> {
+        self.values_at(kind, window, Instant::now())
+    }
+
+    /// Bucket means, oldest first, at the finest tier spanning `window`
+    pub fn values_at(&self, kind: MetricKind, window: Duration, now: Instant) -> Vec<f32> {
+        self.window(kind, window, now).iter().map(Bucket::mean).collect()
+    }
+
+    fn window(&self, kind: MetricKind, window: Duration, now: Instant) -> Vec<Bucket> {
+        let Some(series) = self.series.get(&kind) else {
+            return Vec::new();
+        };
+        // the finest tier reaching back far enough, else the longest one
+        let Some(tier) = self.tiers.iter().position(|t| t.span() >= window).or(self.tiers.len().checked_sub(1)) else {
+            return Vec::new();
+        };
+        let resolution = self.tiers[tier].resolution.as_nanos();
+        let since = now.saturating_duration_since(self.origin);
+        let end = (since.as_nanos() / resolution) as u64;
+        // the bucket holding `now - window` is left out so a window of n resolutions gives n buckets
+        let start = since.checked_sub(window).map(|start| (start.as_nanos() / resolution) as u64);
+        series.tiers[tier]
+            .iter()
+            .filter(|b| start.is_none_or(|start| b.index > start) && b.index <= end)
+            .copied()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history() -> MetricHistory {
+        MetricHistory::new(vec![
+            Tier {
+                resolution: Duration::from_secs(10),
+                capacity: 3,
+            },
+            Tier {
+                resolution: Duration::from_secs(1),
+                capacity: 5,
+            },
+        ])
+    }
+
+    fn at(history: &MetricHistory, secs: f32) -> Instant {
+        history.origin + Duration::from_secs_f32(secs)
+    }
+
+    fn record(history: &mut MetricHistory, secs: f32, value: f32) {
+        let at = at(history, secs);
+        history.record_at(Sample { kind: MetricKind::Cpu, value }, at);
+    }
+
+    fn values(history: &MetricHistory, window: u64, now: f32) -> Vec<f32> {
+        history.values_at(MetricKind::Cpu, Duration::from_secs(window), at(history, now))
+    }
+
+    #[test]
+    fn buckets_per_tier() {
+        let mut history = history();
+        for (secs, value) in [(0.2, 10.0), (0.7, 20.0), (1.5, 30.0), (12.0, 40.0)] {
+            record(&mut history, secs, value);
+        }
+        // fine tier for windows it spans, merged into 10 s buckets beyond
+        assert_eq!(values(&history, 5, 2.0), [15.0, 30.0]);
+        assert_eq!(values(&history, 3, 12.5), [40.0]);
+        assert_eq!(values(&history, 20, 12.5), [20.0, 40.0]);
+        let stats = history.stats_at(MetricKind::Cpu, Duration::from_secs(20), at(&history, 12.5)).unwrap();
+        assert_eq!((stats.min, stats.max, stats.avg, stats.count), (10.0, 40.0, 25.0, 4));
+        assert_eq!(history.latest(MetricKind::Cpu), Some(40.0));
+        assert!(history.stats(MetricKind::Fan, Duration::from_secs(20)).is_none());
+    }
+
+    #[test]
+    fn window_edges() {
+        let mut history = history();
+        for (secs, value) in [(0.5, 1.0), (1.5, 2.0), (2.5, 3.0), (5.5, 6.0)] {
+            record(&mut history, secs, value);
+        }
+        // the bucket holding `now - window` is out, so is anything after `now`
+        assert_eq!(values(&history, 2, 2.9), [2.0, 3.0]);
+        assert_eq!(values(&history, 2, 3.0), [3.0]);
+        assert_eq!(values(&history, 3, 3.0), [2.0, 3.0]);
+        assert_eq!(values(&history, 5, 4.0), [1.0, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn p95_nearest_rank() {
+        let p95 = |values: &[f32]| {
+            let mut history = history();
+            for (i, value) in values.iter().enumerate() {
+                record(&mut history, i as f32, *value);
+            }
+            history.stats_at(MetricKind::Cpu, Duration::from_secs(5), at(&history, 4.5)).unwrap().p95
+        };
+        assert_eq!(p95(&[7.0]), 7.0);
+        assert_eq!(p95(&[3.0, 1.0, 2.0]), 3.0);
+        assert_eq!(p95(&[4.0, 1.0, 3.0, 2.0]), 4.0);
+        // rank 19 of 20
+        let mut history = MetricHistory::new(vec![Tier {
+            resolution: Duration::from_secs(1),
+            capacity: 20,
+        }]);
+        for i in 0..20 {
+            record(&mut history, i as f32, i as f32 + 1.0);
+        }
+        let stats = history.stats_at(MetricKind::Cpu, Duration::from_secs(20), at(&history, 19.5)).unwrap();
+        assert_eq!(stats.p95, 19.0);
+    }
+
+    #[test]
+    fn oldest_buckets_roll_off() {
+        let mut history = history();
+        for i in 0..9 {
+            record(&mut history, i as f32, i as f32);
+        }
+        let fine = &history.series[&MetricKind::Cpu].tiers[0];
+        assert_eq!((fine.len(), fine[0].index), (5, 4));
+        assert_eq!(values(&history, 5, 8.5), [4.0, 5.0, 6.0, 7.0, 8.0]);
+        // the coarse tier still holds them
+        assert_eq!(values(&history, 30, 8.5), [4.0]);
+    }
+
+    #[test]
+    fn late_samples_join_newest_bucket() {
+        let mut history = history();
+        record(&mut history, 3.5, 10.0);
+        record(&mut history, 1.2, 20.0);
+        assert_eq!(values(&history, 5, 4.0), [15.0]);
+        assert_eq!(history.latest(MetricKind::Cpu), Some(20.0));
+    }
+}
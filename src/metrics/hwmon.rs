@@ -0,0 +1,244 @@
+use std::{fs, path::PathBuf, str::FromStr};
+
+use super::{MetricKind, MetricSource, Sample, process::glob_match};
+use crate::{AppError, render::Tint};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SensorKind {
+    /// `temp*_input`, millidegrees Celsius
+    Temperature,
+    /// `fan*_input`, RPM
+    Fan,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sensor {
+    pub kind: SensorKind,
+    pub chip: String,
+    /// Or the channel name (`temp1`) without a label
+    pub label: String,
+    pub input: PathBuf,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SensorSelector {
+    pub chip: Option<String>,
+    pub label: Option<String>,
+}
+
+impl SensorSelector {
+    pub fn matches(&self, sensor: &Sensor) -> bool {
+        self.chip.as_ref().is_none_or(|chip| glob_match(chip, &sensor.chip)) && self.label.as_ref().is_none_or(|label| glob_match(label, &sensor.label))
+    }
+}
+
+impl FromStr for SensorSelector {
+    type Err = AppError;
+
+    /// `<chip>:<label>` or just `<chip>`, `*` or nothing for any
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (chip, label) = s.split_once(':').unwrap_or((s, ""));
+        let glob = |part: &str| (!part.is_empty() && part != "*").then(|| part.to_string());
+        Ok(Self {
+            chip: glob(chip),
+            label: glob(label),
+        })
+    }
+}
+
+pub fn enumerate(root: &std::path::Path) -> Result<Vec<Sensor>, AppError> {
+    let mut chips: Vec<PathBuf> = fs::read_dir(root)?.filter_map(|entry| Some(entry.ok()?.path())).collect();
+    chips.sort();
+
+    let mut sensors = Vec::new();
+    for dir in chips {
+        let chip = fs::read_to_string(dir.join("name")).map(|n| n.trim().to_string()).unwrap_or_default();
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        let mut inputs: Vec<String> = entries.filter_map(|entry| entry.ok()?.file_name().into_string().ok()).filter(|name| name.ends_with("_input")).collect();
+        inputs.sort();
+        for input in inputs {
+            let channel = input.trim_end_matches("_input");
+            let kind = if channel.starts_with("temp") {
+                SensorKind::Temperature
+            } else if channel.starts_with("fan") {
+                SensorKind::Fan
+            } else {
+                continue;
+            };
+            let label = fs::read_to_string(dir.join(format!("{}_label", channel))).map(|l| l.trim().to_string()).unwrap_or_else(|_| channel.to_string());
+            sensors.push(Sensor {
+                kind,
+                chip: chip.clone(),
+                label,
+                input: dir.join(&input),
+            });
+        }
+    }
+    Ok(sensors)
+}
+
+pub struct HwmonSource {
+    root: PathBuf,
+    temperature: SensorSelector,
+    fan: Option<SensorSelector>,
+    sensors: Vec<Sensor>,
+}
+
+impl HwmonSource {
+    pub fn new(temperature: SensorSelector, fan: Option<SensorSelector>) -> Self {
+        Self::with_root("/sys/class/hwmon", temperature, fan)
+    }
+
+    pub fn with_root(root: impl Into<PathBuf>, temperature: SensorSelector, fan: Option<SensorSelector>) -> Self {
+        Self {
+            root: root.into(),
+            temperature,
+            fan,
+            sensors: Vec::new(),
+        }
+    }
+
+    fn max_of(&self, kind: SensorKind, selector: &SensorSelector) -> Option<f32> {
+        self.sensors
+            .iter()
+            .filter(|s| s.kind == kind && selector.matches(s))
+            .filter_map(|s| fs::read_to_string(&s.input).ok()?.trim().parse::<f32>().ok())
+            .reduce(f32::max)
+    }
+}
+
+impl MetricSource for HwmonSource {
+    fn sample(&mut self) -> Result<Vec<Sample>, AppError> {
+        // hwmon numbering changes across boots and driver reloads, so sensors are listed lazily
+        if self.sensors.is_empty() {
+            self.sensors = enumerate(&self.root)?;
+        }
+
+        let mut samples = Vec::new();
+        if let Some(millis) = self.max_of(SensorKind::Temperature, &self.temperature) {
+            samples.push(Sample {
+                kind: MetricKind::Temperature,
+                value: millis / 1000.0,
+            });
+        }
+        if let Some(rpm) = self.fan.as_ref().and_then(|fan| self.max_of(SensorKind::Fan, fan)) {
+            samples.push(Sample { kind: MetricKind::Fan, value: rpm });
+        }
+        if samples.is_empty() {
+            // a chip may have gone away, list again next time
+            self.sensors.clear();
+            return Err(AppError(format!("No hwmon sensor matches {:?}", self.temperature)));
+        }
+        Ok(samples)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HeatTint {
+    pub cool: f32,
+    pub hot: f32,
+}
+
+impl HeatTint {
+    pub fn tint(&self, celsius: f32) -> Option<Tint> {
+        let span = self.hot - self.cool;
+        let heat = if span > 0.0 { ((celsius - self.cool) / span).clamp(0.0, 1.0) } else { 0.0 };
+        (heat > 0.0).then_some(Tint {
+            r: 1.0,
+            g: 0.25,
+            b: 0.1,
+            strength: heat * 0.6,
+        })
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct HwmonConfig {
+    pub temperature: SensorSelector,
+    pub fan: Option<SensorSelector>,
+    /// Set `drive_metric` to `MetricKind::Temperature` to drive speed instead
+    pub heat_tint: Option<HeatTint>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    fn fixture() -> TempDir {
+        let root = TempDir::new("hwmon_fixture");
+        let files = [
+            ("hwmon0/name", "coretemp\n"),
+            ("hwmon0/temp1_input", "45000\n"),
+            ("hwmon0/temp1_label", "Package id 0\n"),
+            ("hwmon0/temp2_input", "52000\n"),
+            ("hwmon0/temp2_label", "Core 0\n"),
+            ("hwmon0/temp2_max", "100000\n"),
+            ("hwmon1/name", "nct6775\n"),
+            ("hwmon1/temp1_input", "30000\n"),
+            ("hwmon1/fan1_input", "1200\n"),
+            ("hwmon1/fan2_input", "900\n"),
+            ("hwmon1/in0_input", "1100\n"),
+        ];
+        for (path, content) in files {
+            root.write(path, content);
+        }
+        root
+    }
+
+    #[test]
+    fn sysfs_tree() {
+        let root = fixture();
+        let sensors = enumerate(&root).unwrap();
+        let names: Vec<(&str, &str, SensorKind)> = sensors.iter().map(|s| (s.chip.as_str(), s.label.as_str(), s.kind)).collect();
+        assert_eq!(
+            names,
+            [
+                ("coretemp", "Package id 0", SensorKind::Temperature),
+                ("coretemp", "Core 0", SensorKind::Temperature),
+                ("nct6775", "fan1", SensorKind::Fan),
+                ("nct6775", "fan2", SensorKind::Fan),
+                ("nct6775", "temp1", SensorKind::Temperature),
+            ]
+        );
+
+        let sample =
+            |temperature: &str, fan: Option<&str>| HwmonSource::with_root(&*root, temperature.parse().unwrap(), fan.map(|f| f.parse().unwrap())).sample();
+        let samples = sample("*", Some("nct*")).unwrap();
+        assert_eq!(
+            samples[0],
+            Sample {
+                kind: MetricKind::Temperature,
+                value: 52.0
+            }
+        );
+        assert_eq!(
+            samples[1],
+            Sample {
+                kind: MetricKind::Fan,
+                value: 1200.0
+            }
+        );
+        assert_eq!(sample("coretemp:Package*", None).unwrap()[0].value, 45.0);
+        assert_eq!(sample("nct6775", Some("nct6775:fan2")).unwrap()[1].value, 900.0);
+        assert!(sample("k10temp", None).is_err());
+    }
+
+    #[test]
+    fn selectors() {
+        assert_eq!("".parse::<SensorSelector>().unwrap(), SensorSelector::default());
+        assert_eq!("*:Tctl".parse::<SensorSelector>().unwrap().label.as_deref(), Some("Tctl"));
+        let selector: SensorSelector = "k10temp".parse().unwrap();
+        assert_eq!((selector.chip.as_deref(), selector.label), (Some("k10temp"), None));
+    }
+
+    #[test]
+    fn heat_tint() {
+        let heat = HeatTint { cool: 50.0, hot: 90.0 };
+        assert_eq!(heat.tint(40.0), None);
+        assert_eq!(heat.tint(70.0).map(|t| t.strength), Some(0.3));
+        assert_eq!(heat.tint(120.0).map(|t| t.strength), Some(0.6));
+    }
+}
@@ -0,0 +1,366 @@
+use std::{
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicU32, Ordering},
+        mpsc::Sender,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use super::{MetricKind, MetricSource, Sample};
+use crate::{AppError, eventloop::Event};
+
+const EV_SYN: u16 = 0;
+const EV_KEY: u16 = 1;
+const EV_REL: u16 = 2;
+const EV_ABS: u16 = 3;
+const REL_X: u16 = 0;
+const REL_Y: u16 = 1;
+/// `struct input_event`: timeval, type, code, value
+#[cfg(target_os = "linux")]
+const EVENT_SIZE: usize = size_of::<libc::input_event>();
+#[cfg(not(target_os = "linux"))]
+const EVENT_SIZE: usize = 2 * size_of::<usize>() + 8;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputCapture {
+    /// Only `KeyDown` and `MouseMove` reaching the window
+    Window,
+    /// Window input plus the given evdev devices, every `/dev/input/event*` when empty
+    Global(Vec<PathBuf>),
+    /// Window input plus a recorded fixture replayed in a loop
+    Fixture(PathBuf),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawInputEvent {
+    pub kind: u16,
+    pub code: u16,
+    pub value: i32,
+}
+
+/// A trailing partial event is ignored
+pub fn decode(buf: &[u8]) -> Vec<RawInputEvent> {
+    buf.chunks_exact(EVENT_SIZE)
+        .map(|e| {
+            // the timeval before is 8 or 16 bytes depending on the target
+            let e = &e[EVENT_SIZE - 8..];
+            RawInputEvent {
+                kind: u16::from_ne_bytes([e[0], e[1]]),
+                code: u16::from_ne_bytes([e[2], e[3]]),
+                value: i32::from_ne_bytes([e[4], e[5], e[6], e[7]]),
+            }
+        })
+        .collect()
+}
+
+/// Bytes from successive reads, a partial event waits for the rest of it
+#[derive(Debug, Default)]
+pub struct EvdevBuffer {
+    pending: Vec<u8>,
+}
+
+impl EvdevBuffer {
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<RawInputEvent> {
+        self.pending.extend_from_slice(bytes);
+        let whole = self.pending.len() / EVENT_SIZE * EVENT_SIZE;
+        let events = decode(&self.pending[..whole]);
+        self.pending.drain(..whole);
+        events
+    }
+}
+
+/// One `KeyDown` per key press and one `MouseMove` per report
+#[derive(Debug, Default)]
+pub struct EvdevTranslator {
+    dx: i32,
+    dy: i32,
+    moved: bool,
+}
+
+impl EvdevTranslator {
+    pub fn feed(&mut self, event: RawInputEvent) -> Option<Event> {
+        match (event.kind, event.code) {
+            // 0 is release and 2 autorepeat, only presses are typing
+            (EV_KEY, code) if event.value == 1 => Some(Event::KeyDown(code as u32)),
+            (EV_REL, REL_X) => {
+                self.dx += event.value;
+                self.moved = true;
+                None
+            }
+            (EV_REL, REL_Y) => {
+                self.dy += event.value;
+                self.moved = true;
+                None
+            }
+            (EV_ABS, _) => {
+                self.moved = true;
+                None
+            }
+            (EV_SYN, _) if self.moved => {
+                let event = Event::MouseMove(self.dx, self.dy);
+                *self = Self::default();
+                Some(event)
+            }
+            _ => None,
+        }
+    }
+}
+
+pub fn event_devices() -> Result<Vec<PathBuf>, AppError> {
+    let mut devices: Vec<PathBuf> = std::fs::read_dir("/dev/input")?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.file_name().and_then(|n| n.to_str()).is_some_and(|n| n.starts_with("event")))
+        .collect();
+    devices.sort();
+    Ok(devices)
+}
+
+/// Opening needs read access to the device, usually the `input` group
+pub fn spawn_evdev_reader(path: &Path, sx: Sender<Event>) -> Result<JoinHandle<()>, AppError> {
+    let mut device = File::open(path).map_err(|e| AppError(format!("{}: {}", path.display(), e)))?;
+    let name = format!("evdev_{}", path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default());
+    let handle = thread::Builder::new()
+        .name(name)
+        .spawn(move || {
+            let mut translator = EvdevTranslator::default();
+            let mut pending = EvdevBuffer::default();
+            let mut buf = [0u8; EVENT_SIZE * 64];
+            while let Ok(n) = device.read(&mut buf) {
+                if n == 0 {
+                    break;
+                }
+                for event in pending.feed(&buf[..n]).into_iter().filter_map(|e| translator.feed(e)) {
+                    if sx.send(event).is_err() {
+                        return;
+                    }
+                }
+            }
+        })
+        .map_err(|e| AppError(e.to_string()))?;
+    Ok(handle)
+}
+
+/// Parses a fixture, one `<ms offset> key <code>` or `<ms offset> move <dx> <dy>` per line, `#` comments
+pub fn parse_fixture(text: &str) -> Result<Vec<(Duration, Event)>, AppError> {
+    let mut events = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let bad = || AppError(format!("Bad input fixture line {}: {}", number + 1, line));
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let offset = Duration::from_millis(parts.first().and_then(|p| p.parse().ok()).ok_or_else(bad)?);
+        let event = match parts.get(1..) {
+            Some(["key", code]) => Event::KeyDown(code.parse().map_err(|_| bad())?),
+            Some(["move", dx, dy]) => Event::MouseMove(dx.parse().map_err(|_| bad())?, dy.parse().map_err(|_| bad())?),
+            _ => return Err(bad()),
+        };
+        events.push((offset, event));
+    }
+    Ok(events)
+}
+
+pub fn fixture_line(offset: Duration, event: Event) -> Option<String> {
+    match event {
+        Event::KeyDown(code) => Some(format!("{} key {}", offset.as_millis(), code)),
+        Event::MouseMove(dx, dy) => Some(format!("{} move {} {}", offset.as_millis(), dx, dy)),
+        _ => None,
+    }
+}
+
+/// Loops until the event loop goes away
+pub fn spawn_replay(events: Vec<(Duration, Event)>, sx: Sender<Event>) -> Result<JoinHandle<()>, AppError> {
+    let length = events.iter().map(|(offset, _)| *offset).max().unwrap_or_default() + Duration::from_millis(1);
+    thread::Builder::new()
+        .name("input_replay".into())
+        .spawn(move || {
+            loop {
+                let start = Instant::now();
+                for (offset, event) in &events {
+                    thread::sleep(offset.saturating_sub(start.elapsed()));
+                    if sx.send(*event).is_err() {
+                        return;
+                    }
+                }
+                thread::sleep(length.saturating_sub(start.elapsed()));
+            }
+        })
+        .map_err(|e| AppError(e.to_string()))
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct InputActivity {
+    keys: Arc<AtomicU32>,
+    pointer: Arc<AtomicU32>,
+}
+
+impl InputActivity {
+    pub fn record(&self, event: &Event) {
+        match event {
+            Event::KeyDown(_) => self.keys.fetch_add(1, Ordering::Relaxed),
+            Event::MouseMove(..) => self.pointer.fetch_add(1, Ordering::Relaxed),
+            _ => return,
+        };
+    }
+}
+
+/// Events per second since the previous sample
+pub struct InputActivitySource {
+    activity: InputActivity,
+    last: Instant,
+}
+
+impl InputActivitySource {
+    pub fn new(activity: InputActivity) -> Self {
+        Self { activity, last: Instant::now() }
+    }
+}
+
+impl MetricSource for InputActivitySource {
+    fn sample(&mut self) -> Result<Vec<Sample>, AppError> {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last).as_secs_f32();
+        self.last = now;
+        if elapsed <= 0.0 {
+            return Ok(Vec::new());
+        }
+        let keys = self.activity.keys.swap(0, Ordering::Relaxed) as f32 / elapsed;
+        let pointer = self.activity.pointer.swap(0, Ordering::Relaxed) as f32 / elapsed;
+        Ok(vec![
+            Sample { kind: MetricKind::KeyRate, value: keys },
+            Sample {
+                kind: MetricKind::PointerRate,
+                value: pointer,
+            },
+            Sample {
+                kind: MetricKind::InputRate,
+                value: keys + pointer,
+            },
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_os = "linux")]
+    fn stream(events: &[(u16, u16, i32)]) -> Vec<u8> {
+        events
+            .iter()
+            .flat_map(|&(type_, code, value)| {
+                let event = libc::input_event {
+                    time: libc::timeval {
+                        tv_sec: 1700000000,
+                        tv_usec: 5,
+                    },
+                    type_,
+                    code,
+                    value,
+                };
+                unsafe { std::slice::from_raw_parts(&event as *const libc::input_event as *const u8, size_of::<libc::input_event>()) }.to_vec()
+            })
+            .collect()
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn kernel_byte_stream() {
+        let events = [
+            (EV_KEY, 30, 1),
+            (EV_SYN, 0, 0),
+            (EV_KEY, 30, 2),
+            (EV_KEY, 30, 0),
+            (EV_REL, REL_X, 5),
+            (EV_REL, REL_Y, -3),
+            (EV_REL, REL_X, 2),
+            (EV_SYN, 0, 0),
+        ];
+        let mut bytes = stream(&events);
+        let tail = stream(&[(EV_KEY, 48, 1)]);
+        bytes.extend_from_slice(&tail[..5]);
+        // a partial event at the end is left for the next read
+        let mut pending = EvdevBuffer::default();
+        let mut decoded = pending.feed(&bytes);
+        assert_eq!(decoded.len(), events.len());
+        assert_eq!(
+            decoded[5],
+            RawInputEvent {
+                kind: EV_REL,
+                code: REL_Y,
+                value: -3
+            }
+        );
+        decoded.extend(pending.feed(&tail[5..]));
+        assert_eq!(decoded.len(), events.len() + 1);
+        assert_eq!(
+            decoded[events.len()],
+            RawInputEvent {
+                kind: EV_KEY,
+                code: 48,
+                value: 1
+            }
+        );
+
+        let mut translator = EvdevTranslator::default();
+        let translated: Vec<Event> = decoded.into_iter().filter_map(|e| translator.feed(e)).collect();
+        assert_eq!(translated, [Event::KeyDown(30), Event::MouseMove(7, -3), Event::KeyDown(48)]);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn short_reads_stay_in_step() {
+        let events = [(EV_REL, REL_X, 1), (EV_REL, REL_Y, -1), (EV_SYN, 0, 0), (EV_KEY, 57, 1)];
+        let bytes = stream(&events);
+        let whole = decode(&bytes);
+        // every split point, including ones inside the type, code and value fields
+        for split in 0..bytes.len() {
+            let mut pending = EvdevBuffer::default();
+            let mut decoded = pending.feed(&bytes[..split]);
+            decoded.extend(pending.feed(&bytes[split..]));
+            assert_eq!(decoded, whole, "split at {split}");
+        }
+        // and one byte at a time
+        let mut pending = EvdevBuffer::default();
+        let decoded: Vec<RawInputEvent> = bytes.chunks(1).flat_map(|b| pending.feed(b)).collect();
+        assert_eq!(decoded, whole);
+    }
+
+    #[test]
+    fn fixture_round_trip() {
+        let text = "# typing then a flick\n0 key 30\n120 key 31 # s\n\n250 move -4 9\n";
+        let events = parse_fixture(text).unwrap();
+        assert_eq!(
+            events,
+            [
+                (Duration::from_millis(0), Event::KeyDown(30)),
+                (Duration::from_millis(120), Event::KeyDown(31)),
+                (Duration::from_millis(250), Event::MouseMove(-4, 9)),
+            ]
+        );
+        let lines: Vec<String> = events.iter().filter_map(|(offset, event)| fixture_line(*offset, *event)).collect();
+        assert_eq!(parse_fixture(&lines.join("\n")).unwrap(), events);
+        assert!(parse_fixture("10 jump").is_err());
+        assert!(parse_fixture("later key 1").is_err());
+    }
+
+    #[test]
+    fn activity_rates() {
+        let activity = InputActivity::default();
+        let mut source = InputActivitySource::new(activity.clone());
+        for event in [Event::KeyDown(1), Event::KeyDown(2), Event::MouseMove(1, 1), Event::Paint] {
+            activity.record(&event);
+        }
+        std::thread::sleep(Duration::from_millis(10));
+        let samples = source.sample().unwrap();
+        assert!(samples[0].value > 0.0 && samples[1].value > 0.0);
+        assert_eq!(samples[2].value, samples[0].value + samples[1].value);
+        assert_eq!(samples[0].value, 2.0 * samples[1].value);
+    }
+}
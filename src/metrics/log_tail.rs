@@ -0,0 +1,177 @@
+use std::{
+    collections::VecDeque,
+    fs::{self, File, Metadata},
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use regex::Regex;
+
+use super::{MetricKind, MetricSource, Sample};
+use crate::AppError;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogTailConfig {
+    pub path: PathBuf,
+    pub pattern: String,
+    pub window: Duration,
+}
+
+#[cfg(unix)]
+fn file_id(path: &Path, meta: &Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    let _ = path;
+    Some(meta.ino())
+}
+
+/// NTFS file index, kept by renames and new for a file created in its place
+#[cfg(windows)]
+fn file_id(path: &Path, meta: &Metadata) -> Option<u64> {
+    use std::os::windows::io::AsRawHandle;
+    use windows::Win32::{
+        Foundation::HANDLE,
+        Storage::FileSystem::{BY_HANDLE_FILE_INFORMATION, GetFileInformationByHandle},
+    };
+    let _ = meta;
+    let file = File::open(path).ok()?;
+    let mut info = BY_HANDLE_FILE_INFORMATION::default();
+    unsafe { GetFileInformationByHandle(HANDLE(file.as_raw_handle()), &mut info) }.ok()?;
+    Some(((info.nFileIndexHigh as u64) << 32) | info.nFileIndexLow as u64)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn file_id(path: &Path, meta: &Metadata) -> Option<u64> {
+    let _ = (path, meta);
+    None
+}
+
+/// Follows a log like `tail -F`, a rotated or truncated file is read again from the start
+pub struct LogTailSource {
+    config: LogTailConfig,
+    regex: Regex,
+    /// `None` until the first sample, which starts at the end of the file
+    offset: Option<u64>,
+    file_id: Option<u64>,
+    partial: Vec<u8>,
+    hits: VecDeque<(Instant, u32)>,
+}
+
+impl LogTailSource {
+    pub fn new(config: LogTailConfig) -> Result<Self, AppError> {
+        Ok(Self {
+            regex: Regex::new(&config.pattern)?,
+            config,
+            offset: None,
+            file_id: None,
+            partial: Vec::new(),
+            hits: VecDeque::new(),
+        })
+    }
+
+    pub fn count_matches(&mut self, chunk: &[u8]) -> u32 {
+        self.partial.extend_from_slice(chunk);
+        let Some(end) = self.partial.iter().rposition(|b| *b == b'\n') else {
+            return 0;
+        };
+        let complete: Vec<u8> = self.partial.drain(..=end).collect();
+        String::from_utf8_lossy(&complete).lines().filter(|line| self.regex.is_match(line)).count() as u32
+    }
+
+    fn read_new(&mut self) -> Result<u32, AppError> {
+        let meta = fs::metadata(&self.config.path)?;
+        let id = file_id(&self.config.path, &meta);
+        let offset = match self.offset {
+            None => meta.len(),
+            Some(offset) if id != self.file_id || meta.len() < offset => {
+                self.partial.clear();
+                0
+            }
+            Some(offset) => offset,
+        };
+        self.file_id = id;
+
+        let mut file = File::open(&self.config.path)?;
+        file.seek(SeekFrom::Start(offset))?;
+        let mut chunk = Vec::new();
+        file.read_to_end(&mut chunk)?;
+        self.offset = Some(offset + chunk.len() as u64);
+        Ok(self.count_matches(&chunk))
+    }
+
+    pub fn rate_at(&mut self, now: Instant, matches: u32) -> f32 {
+        self.hits.push_back((now, matches));
+        while self.hits.front().is_some_and(|(at, _)| now.duration_since(*at) > self.config.window) {
+            self.hits.pop_front();
+        }
+        let window = self.config.window.as_secs_f32();
+        if window <= 0.0 {
+            return 0.0;
+        }
+        self.hits.iter().map(|(_, n)| *n).sum::<u32>() as f32 / window
+    }
+}
+
+impl MetricSource for LogTailSource {
+    fn sample(&mut self) -> Result<Vec<Sample>, AppError> {
+        // a log missing during rotation counts as quiet
+        let matches = match self.read_new() {
+            Ok(matches) => matches,
+            Err(_) if !self.config.path.exists() => 0,
+            Err(e) => return Err(e),
+        };
+        Ok(vec![Sample {
+            kind: MetricKind::LogRate,
+            value: self.rate_at(Instant::now(), matches),
+        }])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn follows_rotation() {
+        let dir = TempDir::new("log_tail");
+        let path = dir.join("app.log");
+        fs::write(&path, "old\n").unwrap();
+        let mut source = LogTailSource::new(LogTailConfig {
+            path: path.clone(),
+            pattern: "^ERROR".into(),
+            window: Duration::from_secs(1),
+        })
+        .unwrap();
+        // starts at the end
+        assert_eq!(source.read_new().unwrap(), 0);
+
+        fs::write(&path, "old\nERROR a\nERR").unwrap();
+        assert_eq!(source.read_new().unwrap(), 1);
+        fs::write(&path, "old\nERROR a\nERROR b\n").unwrap();
+        assert_eq!(source.read_new().unwrap(), 1);
+
+        // a new file longer than the old offset is only told apart by its id
+        fs::rename(&path, dir.join("app.log.1")).unwrap();
+        fs::write(&path, "ERROR c\nERROR d\nERROR e\nERROR f\nok\n").unwrap();
+        assert_eq!(source.read_new().unwrap(), 4);
+
+        // truncated in place
+        fs::write(&path, "ERROR g\n").unwrap();
+        assert_eq!(source.read_new().unwrap(), 1);
+    }
+
+    #[test]
+    fn rate() {
+        let mut source = LogTailSource::new(LogTailConfig {
+            path: "unused".into(),
+            pattern: "x".into(),
+            window: Duration::from_secs(2),
+        })
+        .unwrap();
+        let start = Instant::now();
+        assert_eq!(source.rate_at(start, 4), 2.0);
+        assert_eq!(source.rate_at(start + Duration::from_secs(1), 2), 3.0);
+        assert_eq!(source.rate_at(start + Duration::from_secs(3), 0), 1.0);
+    }
+}
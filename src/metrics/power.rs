@@ -0,0 +1,195 @@
+use std::{fs, path::PathBuf};
+
+use super::{MetricKind, MetricSource, Sample};
+use crate::{AppError, eventloop::Event};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerState {
+    /// Mains online, or no battery at all
+    Ac,
+    Battery,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Supply {
+    pub name: String,
+    /// `Battery`, `Mains`, `USB`...
+    pub kind: String,
+    pub online: Option<bool>,
+    pub capacity: Option<f32>,
+    pub status: Option<String>,
+}
+
+impl Supply {
+    fn is_battery(&self) -> bool {
+        self.kind == "Battery"
+    }
+}
+
+pub fn read_supplies(root: &std::path::Path) -> Result<Vec<Supply>, AppError> {
+    let read = |dir: &std::path::Path, file: &str| fs::read_to_string(dir.join(file)).ok().map(|v| v.trim().to_string());
+    let mut dirs: Vec<PathBuf> = fs::read_dir(root)?.filter_map(|entry| Some(entry.ok()?.path())).collect();
+    dirs.sort();
+    Ok(dirs
+        .iter()
+        .map(|dir| Supply {
+            name: dir.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default(),
+            kind: read(dir, "type").unwrap_or_default(),
+            online: read(dir, "online").map(|v| v == "1"),
+            capacity: read(dir, "capacity").and_then(|v| v.parse().ok()),
+            status: read(dir, "status"),
+        })
+        .collect())
+}
+
+pub fn power_state(supplies: &[Supply]) -> PowerState {
+    let mains_online = supplies.iter().any(|s| !s.is_battery() && s.online == Some(true));
+    let discharging = supplies.iter().any(|s| s.is_battery() && s.status.as_deref() == Some("Discharging"));
+    if discharging && !mains_online { PowerState::Battery } else { PowerState::Ac }
+}
+
+/// Also sends `Event::Power` whenever the power source changes
+pub struct PowerSupplySource {
+    root: PathBuf,
+    state: Option<PowerState>,
+    events: Vec<Event>,
+}
+
+impl PowerSupplySource {
+    pub fn new() -> Self {
+        Self::with_root("/sys/class/power_supply")
+    }
+
+    pub fn with_root(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            state: None,
+            events: Vec::new(),
+        }
+    }
+}
+
+impl Default for PowerSupplySource {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MetricSource for PowerSupplySource {
+    fn sample(&mut self) -> Result<Vec<Sample>, AppError> {
+        let supplies = read_supplies(&self.root)?;
+
+        let state = power_state(&supplies);
+        if self.state.replace(state) != Some(state) {
+            self.events.push(Event::Power(state));
+        }
+
+        let capacities: Vec<f32> = supplies.iter().filter(|s| s.is_battery()).filter_map(|s| s.capacity).collect();
+        if capacities.is_empty() {
+            return Ok(Vec::new());
+        }
+        Ok(vec![Sample {
+            kind: MetricKind::Battery,
+            value: capacities.iter().sum::<f32>() / capacities.len() as f32,
+        }])
+    }
+
+    fn events(&mut self) -> Vec<Event> {
+        std::mem::take(&mut self.events)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum BatteryAction {
+    #[default]
+    None,
+    /// Caps the Paint rate
+    CapFps(f32),
+    /// Stops the Paint timer until power comes back
+    Pause,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct PowerPolicy {
+    /// Applies at or under this battery percent, 100 as soon as on battery
+    pub threshold: f32,
+    pub action: BatteryAction,
+}
+
+impl PowerPolicy {
+    pub fn action(&self, state: PowerState, capacity: Option<f32>) -> BatteryAction {
+        match state {
+            PowerState::Battery if capacity.is_none_or(|c| c <= self.threshold) => self.action,
+            _ => BatteryAction::None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    fn supply(name: &str, kind: &str, online: Option<bool>, status: Option<&str>) -> Supply {
+        Supply {
+            name: name.into(),
+            kind: kind.into(),
+            online,
+            capacity: None,
+            status: status.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn state() {
+        let battery = supply("BAT0", "Battery", None, Some("Discharging"));
+        let mains = supply("AC", "Mains", Some(true), None);
+        assert_eq!(power_state(std::slice::from_ref(&battery)), PowerState::Battery);
+        assert_eq!(power_state(&[battery, mains]), PowerState::Ac);
+        assert_eq!(power_state(&[supply("BAT0", "Battery", None, Some("Full"))]), PowerState::Ac);
+        assert_eq!(power_state(&[]), PowerState::Ac);
+    }
+
+    #[test]
+    fn policy() {
+        let policy = PowerPolicy {
+            threshold: 20.0,
+            action: BatteryAction::Pause,
+        };
+        assert_eq!(policy.action(PowerState::Battery, Some(15.0)), BatteryAction::Pause);
+        assert_eq!(policy.action(PowerState::Battery, Some(50.0)), BatteryAction::None);
+        assert_eq!(policy.action(PowerState::Battery, None), BatteryAction::Pause);
+        assert_eq!(policy.action(PowerState::Ac, Some(5.0)), BatteryAction::None);
+    }
+
+    #[test]
+    fn source_from_sysfs_fixture() {
+        let root = TempDir::new("power_fixture");
+        let files = [
+            ("AC/type", "Mains"),
+            ("AC/online", "0"),
+            ("BAT0/type", "Battery"),
+            ("BAT0/capacity", "40"),
+            ("BAT0/status", "Discharging"),
+            ("BAT1/type", "Battery"),
+            ("BAT1/capacity", "60"),
+        ];
+        for (path, content) in files {
+            root.write(path, format!("{}\n", content));
+        }
+        let mut source = PowerSupplySource::with_root(&*root);
+        assert_eq!(
+            source.sample().unwrap(),
+            [Sample {
+                kind: MetricKind::Battery,
+                value: 50.0
+            }]
+        );
+        assert_eq!(source.events(), [Event::Power(PowerState::Battery)]);
+        source.sample().unwrap();
+        assert!(source.events().is_empty());
+        fs::write(root.join("AC/online"), "1\n").unwrap();
+        source.sample().unwrap();
+        assert_eq!(source.events(), [Event::Power(PowerState::Ac)]);
+    }
+}
@@ -0,0 +1,224 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use super::{MetricKind, MetricSource, Sample, cpu::parse_stat};
+use crate::AppError;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProcessTarget {
+    Pid(u32),
+    /// Glob (`*`, `?`) on the executable name, re-resolved on every sample
+    Name(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessFilter {
+    pub target: ProcessTarget,
+    pub tree: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcStat {
+    pub pid: u32,
+    pub comm: String,
+    pub ppid: u32,
+    /// utime + stime in jiffies
+    pub cpu_time: u64,
+    /// Tells a respawned process apart from the previous one with the same pid
+    pub start_time: u64,
+}
+
+impl ProcStat {
+    pub fn parse(stat: &str) -> Option<Self> {
+        // comm may contain spaces and parentheses, it ends at the last ')'
+        let open = stat.find('(')?;
+        let close = stat.rfind(')')?;
+        let pid = stat[..open].trim().parse().ok()?;
+        let comm = stat.get(open + 1..close)?.to_string();
+        let fields: Vec<&str> = stat[close + 1..].split_whitespace().collect();
+        let field = |i: usize| fields.get(i).and_then(|f| f.parse::<u64>().ok());
+        Some(Self {
+            pid,
+            comm,
+            ppid: field(1)? as u32,
+            cpu_time: field(11)? + field(12)?,
+            start_time: field(19)?,
+        })
+    }
+}
+
+/// `*` matches any run of characters, `?` exactly one
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let (p, t): (Vec<char>, Vec<char>) = (pattern.chars().collect(), text.chars().collect());
+    let (mut pi, mut ti) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while ti < t.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == t[ti]) {
+            pi += 1;
+            ti += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, ti));
+            pi += 1;
+        } else if let Some((sp, st)) = star {
+            pi = sp + 1;
+            ti = st + 1;
+            star = Some((sp, st + 1));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|c| *c == '*')
+}
+
+/// Cpu in percent of the machine and resident memory in MiB, zero while nothing matches
+pub struct ProcessSource {
+    proc_root: PathBuf,
+    filter: ProcessFilter,
+    last: HashMap<(u32, u64), u64>,
+    last_total: Option<u64>,
+}
+
+impl ProcessSource {
+    pub fn new(filter: ProcessFilter) -> Self {
+        Self::with_root("/proc", filter)
+    }
+
+    pub fn with_root(proc_root: impl Into<PathBuf>, filter: ProcessFilter) -> Self {
+        Self {
+            proc_root: proc_root.into(),
+            filter,
+            last: HashMap::new(),
+            last_total: None,
+        }
+    }
+
+    fn read_stat(&self, pid: u32) -> Option<ProcStat> {
+        ProcStat::parse(&fs::read_to_string(self.proc_root.join(pid.to_string()).join("stat")).ok()?)
+    }
+
+    fn all_processes(&self) -> Result<Vec<ProcStat>, AppError> {
+        Ok(fs::read_dir(&self.proc_root)?
+            .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse::<u32>().ok())
+            .filter_map(|pid| self.read_stat(pid))
+            .collect())
+    }
+
+    /// Name from argv[0] of `cmdline`, kernel threads and zombies fall back to comm
+    fn exe_name(&self, process: &ProcStat) -> String {
+        let cmdline = fs::read(self.proc_root.join(process.pid.to_string()).join("cmdline")).unwrap_or_default();
+        let argv0 = cmdline.split(|b| *b == 0).next().unwrap_or_default();
+        match Path::new(&*String::from_utf8_lossy(argv0)).file_name() {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => process.comm.clone(),
+        }
+    }
+
+    fn matching(&self) -> Result<Vec<ProcStat>, AppError> {
+        if let (ProcessTarget::Pid(pid), false) = (&self.filter.target, self.filter.tree) {
+            return Ok(self.read_stat(*pid).into_iter().collect());
+        }
+        let processes = self.all_processes()?;
+        let mut selected: HashSet<u32> = processes
+            .iter()
+            .filter(|p| match &self.filter.target {
+                ProcessTarget::Pid(pid) => p.pid == *pid,
+                ProcessTarget::Name(pattern) => glob_match(pattern, &self.exe_name(p)),
+            })
+            .map(|p| p.pid)
+            .collect();
+        if self.filter.tree {
+            // parents may be listed after their children, repeat until nothing is added
+            loop {
+                let before = selected.len();
+                for p in &processes {
+                    if selected.contains(&p.ppid) {
+                        selected.insert(p.pid);
+                    }
+                }
+                if selected.len() == before {
+                    break;
+                }
+            }
+        }
+        Ok(processes.into_iter().filter(|p| selected.contains(&p.pid)).collect())
+    }
+
+    fn resident_mib(&self, pid: u32) -> f32 {
+        let status = fs::read_to_string(self.proc_root.join(pid.to_string()).join("status")).unwrap_or_default();
+        status
+            .lines()
+            .find_map(|line| line.strip_prefix("VmRSS:"))
+            .and_then(|kb| kb.trim().trim_end_matches("kB").trim().parse::<f32>().ok())
+            .map_or(0.0, |kb| kb / 1024.0)
+    }
+}
+
+impl MetricSource for ProcessSource {
+    fn sample(&mut self) -> Result<Vec<Sample>, AppError> {
+        let stat = fs::read_to_string(self.proc_root.join("stat"))?;
+        let total = parse_stat(&stat)
+            .into_iter()
+            .find(|(kind, _)| *kind == MetricKind::Cpu)
+            .map(|(_, times)| times.busy + times.idle)
+            .ok_or_else(|| AppError(format!("No cpu line in {}", self.proc_root.join("stat").display())))?;
+
+        let processes = self.matching()?;
+        let mut busy = 0;
+        let mut current = HashMap::with_capacity(processes.len());
+        for p in &processes {
+            let key = (p.pid, p.start_time);
+            // a process first seen now only counts from the next sample
+            busy += self.last.get(&key).map_or(0, |prev| p.cpu_time.saturating_sub(*prev));
+            current.insert(key, p.cpu_time);
+        }
+        self.last = current;
+        let memory: f32 = processes.iter().map(|p| self.resident_mib(p.pid)).sum();
+
+        let mut samples = vec![Sample {
+            kind: MetricKind::ProcessMemory,
+            value: memory,
+        }];
+        if let Some(prev_total) = self.last_total.replace(total) {
+            let elapsed = total.saturating_sub(prev_total);
+            samples.push(Sample {
+                kind: MetricKind::ProcessCpu,
+                value: if elapsed == 0 { 0.0 } else { busy as f32 * 100.0 / elapsed as f32 },
+            });
+        }
+        Ok(samples)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stat_with_odd_comm() {
+        let stat = "1234 (tmux: server) (x)) S 1 1234 1234 0 -1 4194560 1 0 0 0 150 50 0 0 20 0 1 0 98765 0 0";
+        let parsed = ProcStat::parse(stat).unwrap();
+        assert_eq!(parsed.comm, "tmux: server) (x)");
+        assert_eq!((parsed.pid, parsed.ppid, parsed.cpu_time, parsed.start_time), (1234, 1, 200, 98765));
+        assert_eq!(ProcStat::parse("1234 (short) S 1"), None);
+    }
+
+    #[test]
+    fn globs() {
+        let table = [
+            ("firefox", "firefox", true),
+            ("fire*", "firefox", true),
+            ("*fox", "firefox", true),
+            ("f*r*x", "firefox", true),
+            ("fire?ox", "firefox", true),
+            ("fire?", "firefox", false),
+            ("*", "", true),
+            ("chrome", "firefox", false),
+        ];
+        for (pattern, text, expected) in table {
+            assert_eq!(glob_match(pattern, text), expected, "{} on {}", pattern, text);
+        }
+    }
+}
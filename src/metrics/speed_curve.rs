@@ -0,0 +1,236 @@
+use std::{collections::VecDeque, time::Duration};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Smoothing {
+    None,
+    /// Exponential moving average, `alpha` in `0..=1` is the weight of the newest sample
+    Ema { alpha: f32 },
+    /// Mean of the last `n` samples
+    Window(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Curve {
+    Linear,
+    /// Rises fast at low load then flattens, `log10(1 + 9t)` over the input range
+    Logarithmic,
+    /// `(input, fps)` steps sorted by input, the last step reached wins
+    Stepped(Vec<(f32, f32)>),
+    /// `(input, fps)` points sorted by input, linearly interpolated between them
+    Points(Vec<(f32, f32)>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpeedCurveConfig {
+    pub smoothing: Smoothing,
+    pub curve: Curve,
+    /// Input values mapped to the ends of `Linear` and `Logarithmic` curves
+    pub input_min: f32,
+    pub input_max: f32,
+    pub min_fps: f32,
+    pub max_fps: f32,
+    /// The smoothed input has to move this far from the last mapped value before fps changes
+    pub hysteresis: f32,
+}
+
+impl Default for SpeedCurveConfig {
+    fn default() -> Self {
+        Self {
+            smoothing: Smoothing::Ema { alpha: 0.3 },
+            curve: Curve::Linear,
+            input_min: 0.0,
+            input_max: 100.0,
+            min_fps: 10.0,
+            max_fps: 50.0,
+            hysteresis: 2.0,
+        }
+    }
+}
+
+impl SpeedCurveConfig {
+    pub fn map(&self, input: f32) -> f32 {
+        let span = self.input_max - self.input_min;
+        let t = if span > 0.0 { ((input - self.input_min) / span).clamp(0.0, 1.0) } else { 0.0 };
+        let fps = match &self.curve {
+            Curve::Linear => self.min_fps + t * (self.max_fps - self.min_fps),
+            Curve::Logarithmic => self.min_fps + (1.0 + 9.0 * t).log10() * (self.max_fps - self.min_fps),
+            Curve::Stepped(steps) => steps.iter().take_while(|(x, _)| input >= *x).last().map_or(self.min_fps, |(_, fps)| *fps),
+            Curve::Points(points) => interpolate(points, input).unwrap_or(self.min_fps),
+        };
+        fps.clamp(self.min_fps.min(self.max_fps), self.max_fps.max(self.min_fps))
+    }
+}
+
+fn interpolate(points: &[(f32, f32)], input: f32) -> Option<f32> {
+    let (first, last) = (points.first()?, points.last()?);
+    if input <= first.0 {
+        return Some(first.1);
+    }
+    if input >= last.0 {
+        return Some(last.1);
+    }
+    points.windows(2).find(|w| input < w[1].0).map(|w| {
+        let (x0, y0) = w[0];
+        let (x1, y1) = w[1];
+        if x1 > x0 { y0 + (input - x0) / (x1 - x0) * (y1 - y0) } else { y1 }
+    })
+}
+
+#[derive(Debug, Clone)]
+pub struct SpeedCurve {
+    pub config: SpeedCurveConfig,
+    window: VecDeque<f32>,
+    smoothed: Option<f32>,
+    anchor: Option<f32>,
+    fps: f32,
+}
+
+impl SpeedCurve {
+    pub fn new(config: SpeedCurveConfig) -> Self {
+        let fps = config.map(config.input_min);
+        Self {
+            config,
+            window: VecDeque::new(),
+            smoothed: None,
+            anchor: None,
+            fps,
+        }
+    }
+
+    pub fn value(&self) -> Option<f32> {
+        self.smoothed
+    }
+
+    pub fn fps(&self) -> f32 {
+        self.fps
+    }
+
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs_f32(1.0 / self.fps.max(0.1))
+    }
+
+    /// Returns the new fps when it changed
+    pub fn feed(&mut self, value: f32) -> Option<f32> {
+        if !value.is_finite() {
+            return None;
+        }
+        let smoothed = match self.config.smoothing {
+            Smoothing::None => value,
+            Smoothing::Ema { alpha } => {
+                let alpha = alpha.clamp(0.0, 1.0);
+                self.smoothed.map_or(value, |prev| prev + alpha * (value - prev))
+            }
+            Smoothing::Window(n) => {
+                self.window.push_back(value);
+                while self.window.len() > n.max(1) {
+                    self.window.pop_front();
+                }
+                self.window.iter().sum::<f32>() / self.window.len() as f32
+            }
+        };
+        self.smoothed = Some(smoothed);
+
+        if let Some(anchor) = self.anchor
+            && (smoothed - anchor).abs() < self.config.hysteresis
+        {
+            return None;
+        }
+        self.anchor = Some(smoothed);
+        let fps = self.config.map(smoothed);
+        if fps == self.fps {
+            return None;
+        }
+        self.fps = fps;
+        Some(fps)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(curve: Curve) -> SpeedCurveConfig {
+        SpeedCurveConfig {
+            smoothing: Smoothing::None,
+            curve,
+            hysteresis: 0.0,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn curves() {
+        let stepped = Curve::Stepped(vec![(20.0, 15.0), (50.0, 30.0), (80.0, 45.0)]);
+        let points = Curve::Points(vec![(0.0, 10.0), (50.0, 40.0), (100.0, 20.0)]);
+        let table = [
+            (Curve::Linear, 0.0, 10.0),
+            (Curve::Linear, 50.0, 30.0),
+            (Curve::Linear, 100.0, 50.0),
+            (Curve::Linear, -20.0, 10.0),
+            (Curve::Linear, 250.0, 50.0),
+            (Curve::Logarithmic, 0.0, 10.0),
+            (Curve::Logarithmic, 100.0, 50.0),
+            // log10(1 + 9 * 0.5) of the way up
+            (Curve::Logarithmic, 50.0, 10.0 + 5.5f32.log10() * 40.0),
+            (stepped.clone(), 10.0, 10.0),
+            (stepped.clone(), 20.0, 15.0),
+            (stepped.clone(), 79.9, 30.0),
+            (stepped.clone(), 100.0, 45.0),
+            (points.clone(), 25.0, 25.0),
+            (points.clone(), 75.0, 30.0),
+            (points.clone(), -5.0, 10.0),
+            (points, 120.0, 20.0),
+            // steps outside the fps range are clamped
+            (Curve::Stepped(vec![(0.0, 1.0), (90.0, 200.0)]), 50.0, 10.0),
+            (Curve::Stepped(vec![(0.0, 1.0), (90.0, 200.0)]), 95.0, 50.0),
+        ];
+        for (curve, input, fps) in table {
+            let mapped = config(curve.clone()).map(input);
+            assert!((mapped - fps).abs() < 1e-4, "{:?} at {}: {} != {}", curve, input, mapped, fps);
+        }
+    }
+
+    #[test]
+    fn hysteresis() {
+        let mut curve = SpeedCurve::new(SpeedCurveConfig {
+            hysteresis: 5.0,
+            ..config(Curve::Linear)
+        });
+        // (sample, fps reported), a change needs the input to move 5 from where fps last changed
+        let table = [
+            (50.0, Some(30.0)),
+            (54.9, None),
+            (45.1, None),
+            (55.0, Some(32.0)),
+            (50.1, None),
+            (50.0, Some(30.0)),
+            (f32::NAN, None),
+        ];
+        for (sample, expected) in table {
+            assert_eq!(curve.feed(sample), expected, "at {}", sample);
+        }
+        assert_eq!(curve.fps(), 30.0);
+        assert_eq!(curve.interval(), Duration::from_secs_f32(1.0 / 30.0));
+    }
+
+    #[test]
+    fn smoothing() {
+        let table = [
+            (Smoothing::None, [0.0, 100.0, 100.0], 100.0),
+            (Smoothing::Ema { alpha: 0.5 }, [0.0, 100.0, 100.0], 75.0),
+            (Smoothing::Ema { alpha: 7.0 }, [0.0, 100.0, 40.0], 40.0),
+            (Smoothing::Window(2), [0.0, 100.0, 40.0], 70.0),
+            (Smoothing::Window(0), [0.0, 100.0, 40.0], 40.0),
+        ];
+        for (smoothing, samples, value) in table {
+            let mut curve = SpeedCurve::new(SpeedCurveConfig {
+                smoothing: smoothing.clone(),
+                ..config(Curve::Linear)
+            });
+            samples.into_iter().for_each(|sample| {
+                curve.feed(sample);
+            });
+            assert_eq!(curve.value(), Some(value), "{:?}", smoothing);
+        }
+    }
+}
@@ -0,0 +1,300 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use log::warn;
+
+use super::{MetricSource, Sample};
+use crate::{AppError, eventloop::Event};
+
+/// `<ms>,<metric>,<value>` lines or `{"ms":..,"metric":"..","value":..}` lines, `#` comments in csv
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceFormat {
+    Csv,
+    JsonLines,
+}
+
+impl TraceFormat {
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("jsonl" | "json") => TraceFormat::JsonLines,
+            _ => TraceFormat::Csv,
+        }
+    }
+}
+
+fn parse_line(line: &str, format: TraceFormat) -> Option<(Duration, Sample)> {
+    let (ms, kind, value) = match format {
+        TraceFormat::Csv => {
+            let mut fields = line.split(',').map(str::trim);
+            (fields.next()?.parse().ok()?, fields.next()?.parse().ok()?, fields.next()?.parse().ok()?)
+        }
+        TraceFormat::JsonLines => {
+            let json: serde_json::Value = serde_json::from_str(line).ok()?;
+            (json["ms"].as_u64()?, json["metric"].as_str()?.parse().ok()?, json["value"].as_f64()? as f32)
+        }
+    };
+    Some((Duration::from_millis(ms), Sample { kind, value }))
+}
+
+pub fn parse_trace(text: &str, format: TraceFormat) -> Result<Vec<(Duration, Sample)>, AppError> {
+    let mut points = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || (format == TraceFormat::Csv && line.starts_with('#')) {
+            continue;
+        }
+        points.push(parse_line(line, format).ok_or_else(|| AppError(format!("Bad trace line {}: {}", number + 1, line)))?);
+    }
+    points.sort_by_key(|(offset, _)| *offset);
+    Ok(points)
+}
+
+pub fn trace_line(offset: Duration, sample: Sample, format: TraceFormat) -> String {
+    match format {
+        TraceFormat::Csv => format!("{},{},{}", offset.as_millis(), sample.kind, sample.value),
+        TraceFormat::JsonLines => serde_json::json!({ "ms": offset.as_millis() as u64, "metric": sample.kind.to_string(), "value": sample.value }).to_string(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceReplayConfig {
+    pub path: PathBuf,
+    pub looping: bool,
+    /// Playback rate, 2.0 replays a minute of trace in 30 seconds
+    pub speed: f32,
+}
+
+/// Plays a trace back, samples due since the previous poll arrive together
+pub struct TraceReplaySource {
+    points: Vec<(Duration, Sample)>,
+    looping: bool,
+    speed: f32,
+    start: Instant,
+    next: usize,
+    pass_start: Duration,
+}
+
+impl TraceReplaySource {
+    pub fn new(config: &TraceReplayConfig) -> Result<Self, AppError> {
+        let text = std::fs::read_to_string(&config.path).map_err(|e| AppError(format!("{}: {}", config.path.display(), e)))?;
+        let points = parse_trace(&text, TraceFormat::from_path(&config.path))?;
+        Ok(Self::from_points(points, config.looping, config.speed))
+    }
+
+    pub fn from_points(points: Vec<(Duration, Sample)>, looping: bool, speed: f32) -> Self {
+        Self {
+            points,
+            looping,
+            speed: speed.max(0.01),
+            start: Instant::now(),
+            next: 0,
+            pass_start: Duration::ZERO,
+        }
+    }
+
+    pub fn due(&mut self, trace_time: Duration) -> Vec<Sample> {
+        let mut samples = Vec::new();
+        let Some(last) = self.points.last().map(|(offset, _)| *offset) else {
+            return samples;
+        };
+        let length = last + Duration::from_millis(1);
+        loop {
+            while let Some((offset, sample)) = self.points.get(self.next)
+                && self.pass_start + *offset <= trace_time
+            {
+                samples.push(*sample);
+                self.next += 1;
+            }
+            if self.next < self.points.len() || !self.looping {
+                return samples;
+            }
+            self.next = 0;
+            self.pass_start += length;
+            // skip whole passes an accelerated replay overran instead of flooding the loop
+            while self.pass_start + length <= trace_time {
+                self.pass_start += length;
+            }
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        !self.looping && self.next >= self.points.len()
+    }
+}
+
+impl MetricSource for TraceReplaySource {
+    fn sample(&mut self) -> Result<Vec<Sample>, AppError> {
+        let trace_time = self.start.elapsed().mul_f32(self.speed);
+        Ok(self.due(trace_time))
+    }
+}
+
+/// Samples are timed from the recorder creation
+#[derive(Clone)]
+pub struct TraceRecorder {
+    writer: Arc<Mutex<Box<dyn Write + Send>>>,
+    format: TraceFormat,
+    start: Instant,
+}
+
+impl TraceRecorder {
+    pub fn create(path: &Path) -> Result<Self, AppError> {
+        let file = File::create(path).map_err(|e| AppError(format!("{}: {}", path.display(), e)))?;
+        let recorder = Self::new(Box::new(BufWriter::new(file)), TraceFormat::from_path(path));
+        if recorder.format == TraceFormat::Csv {
+            recorder.write(|w| writeln!(w, "# ms,metric,value"));
+        }
+        Ok(recorder)
+    }
+
+    pub fn new(writer: Box<dyn Write + Send>, format: TraceFormat) -> Self {
+        Self {
+            writer: Arc::new(Mutex::new(writer)),
+            format,
+            start: Instant::now(),
+        }
+    }
+
+    pub fn record<S: MetricSource>(&self, source: S) -> Recorded<S> {
+        Recorded {
+            source,
+            recorder: self.clone(),
+        }
+    }
+
+    fn write(&self, f: impl FnOnce(&mut dyn Write) -> std::io::Result<()>) {
+        let mut writer = self.writer.lock().unwrap();
+        // a full disk loses the recording, not the samples
+        if let Err(e) = f(&mut *writer).and_then(|_| writer.flush()) {
+            warn!("Trace recording failed: {}", e);
+        }
+    }
+}
+
+pub struct Recorded<S> {
+    source: S,
+    recorder: TraceRecorder,
+}
+
+impl<S: MetricSource> MetricSource for Recorded<S> {
+    fn sample(&mut self) -> Result<Vec<Sample>, AppError> {
+        let samples = self.source.sample()?;
+        let offset = self.recorder.start.elapsed();
+        let format = self.recorder.format;
+        self.recorder
+            .write(|w| samples.iter().try_for_each(|s| writeln!(w, "{}", trace_line(offset, *s, format))));
+        Ok(samples)
+    }
+
+    fn events(&mut self) -> Vec<Event> {
+        self.source.events()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::MetricKind;
+
+    fn sample(kind: MetricKind, value: f32) -> Sample {
+        Sample { kind, value }
+    }
+
+    fn points() -> Vec<(Duration, Sample)> {
+        [(0, 1.0), (100, 2.0), (200, 3.0)]
+            .map(|(ms, value)| (Duration::from_millis(ms), sample(MetricKind::Cpu, value)))
+            .to_vec()
+    }
+
+    fn values(samples: &[Sample]) -> Vec<f32> {
+        samples.iter().map(|s| s.value).collect()
+    }
+
+    #[test]
+    fn parse_errors_name_the_line() {
+        let csv = "# ms,metric,value\n200,Cpu,2\n0,CpuCore(1),1.5\n";
+        let points = parse_trace(csv, TraceFormat::Csv).unwrap();
+        assert_eq!(points[0], (Duration::ZERO, sample(MetricKind::CpuCore(1), 1.5)));
+        assert_eq!(points.len(), 2);
+        let error = parse_trace("0,Cpu,1\n5,Nope,2", TraceFormat::Csv).err().unwrap();
+        assert_eq!(error.0, "Bad trace line 2: 5,Nope,2");
+        assert!(parse_trace("0,Cpu", TraceFormat::Csv).is_err());
+
+        let jsonl = "{\"ms\":0,\"metric\":\"Cpu\",\"value\":1}\n\n{\"ms\":\"x\",\"metric\":\"Cpu\",\"value\":1}";
+        assert!(parse_trace(jsonl, TraceFormat::JsonLines).err().unwrap().0.starts_with("Bad trace line 3:"));
+        // comments are csv only
+        assert!(parse_trace("# note", TraceFormat::JsonLines).is_err());
+    }
+
+    #[test]
+    fn due_by_trace_time() {
+        let mut replay = TraceReplaySource::from_points(points(), false, 1.0);
+        assert_eq!(values(&replay.due(Duration::from_millis(50))), [1.0]);
+        assert_eq!(values(&replay.due(Duration::from_millis(150))), [2.0]);
+        assert!(replay.due(Duration::from_millis(150)).is_empty());
+        assert!(!replay.is_finished());
+        assert_eq!(values(&replay.due(Duration::from_secs(1))), [3.0]);
+        assert!(replay.is_finished());
+        assert!(replay.due(Duration::from_secs(2)).is_empty());
+    }
+
+    #[test]
+    fn loops_and_skips_overrun_passes() {
+        // a pass lasts 201 ms
+        let mut replay = TraceReplaySource::from_points(points(), true, 1.0);
+        assert_eq!(values(&replay.due(Duration::from_millis(250))), [1.0, 2.0, 3.0, 1.0]);
+        assert_eq!(values(&replay.due(Duration::from_millis(410))), [2.0, 3.0, 1.0]);
+        assert!(!replay.is_finished());
+
+        // ten seconds in at once gives the first pass and the one under way, not fifty
+        let mut replay = TraceReplaySource::from_points(points(), true, 50.0);
+        assert_eq!(values(&replay.due(Duration::from_secs(10))), [1.0, 2.0, 3.0, 1.0, 2.0]);
+    }
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    struct Fixed(Vec<Vec<Sample>>);
+
+    impl MetricSource for Fixed {
+        fn sample(&mut self) -> Result<Vec<Sample>, AppError> {
+            Ok(self.0.remove(0))
+        }
+    }
+
+    #[test]
+    fn record_parse_replay_round_trip() {
+        let polls = vec![
+            vec![sample(MetricKind::Cpu, 12.5), sample(MetricKind::CpuCore(3), 0.1)],
+            vec![sample(MetricKind::Temperature, -4.25)],
+            vec![sample(MetricKind::DiskFree, 1.0 / 3.0)],
+        ];
+        let expected: Vec<Sample> = polls.concat();
+        for format in [TraceFormat::Csv, TraceFormat::JsonLines] {
+            let buffer = Buffer::default();
+            let mut recorded = TraceRecorder::new(Box::new(buffer.clone()), format).record(Fixed(polls.clone()));
+            for _ in 0..polls.len() {
+                recorded.sample().unwrap();
+            }
+            let text = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+            let points = parse_trace(&text, format).unwrap();
+            let mut replay = TraceReplaySource::from_points(points, false, 1.0);
+            assert_eq!(replay.due(Duration::from_secs(60)), expected, "{:?}", format);
+        }
+    }
+}
@@ -0,0 +1,597 @@
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+    sync::mpsc::{self, Sender},
+    thread,
+};
+
+use log::warn;
+
+use crate::AppError;
+
+const METHOD_CALL: u8 = 1;
+const METHOD_RETURN: u8 = 2;
+const ERROR: u8 = 3;
+
+/// Header field codes
+const PATH: u8 = 1;
+const INTERFACE: u8 = 2;
+const MEMBER: u8 = 3;
+const ERROR_NAME: u8 = 4;
+const REPLY_SERIAL: u8 = 5;
+const DESTINATION: u8 = 6;
+const SIGNATURE: u8 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Urgency {
+    Low = 0,
+    Normal = 1,
+    Critical = 2,
+}
+
+/// Little endian, offsets relative to the message start for alignment
+#[derive(Default)]
+struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    fn align(&mut self, n: usize) {
+        self.buf.resize(self.buf.len().next_multiple_of(n), 0);
+    }
+
+    fn byte(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.align(4);
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn i32(&mut self, value: i32) {
+        self.u32(value as u32);
+    }
+
+    fn string(&mut self, value: &str) {
+        self.u32(value.len() as u32);
+        self.buf.extend_from_slice(value.as_bytes());
+        self.buf.push(0);
+    }
+
+    fn signature(&mut self, value: &str) {
+        self.byte(value.len() as u8);
+        self.buf.extend_from_slice(value.as_bytes());
+        self.buf.push(0);
+    }
+
+    /// Writes the length, then the elements aligned to `element_align`
+    fn array(&mut self, element_align: usize, write: impl FnOnce(&mut Self)) {
+        self.u32(0);
+        let length_at = self.buf.len() - 4;
+        self.align(element_align);
+        let start = self.buf.len();
+        write(self);
+        let length = (self.buf.len() - start) as u32;
+        self.buf[length_at..length_at + 4].copy_from_slice(&length.to_le_bytes());
+    }
+
+    fn field(&mut self, code: u8, signature: &str, value: &str) {
+        self.align(8);
+        self.byte(code);
+        self.signature(signature);
+        match signature {
+            "g" => self.signature(value),
+            _ => self.string(value),
+        }
+    }
+}
+
+/// The body starts 8 aligned
+fn method_call(serial: u32, destination: &str, path: &str, interface: &str, member: &str, signature: &str, body: &[u8]) -> Vec<u8> {
+    let mut message = Writer::default();
+    message.buf.extend_from_slice(&[b'l', METHOD_CALL, 0, 1]);
+    message.u32(body.len() as u32);
+    message.u32(serial);
+    message.array(8, |fields| {
+        fields.field(PATH, "o", path);
+        fields.field(INTERFACE, "s", interface);
+        fields.field(MEMBER, "s", member);
+        fields.field(DESTINATION, "s", destination);
+        if !signature.is_empty() {
+            fields.field(SIGNATURE, "g", signature);
+        }
+    });
+    message.align(8);
+    message.buf.extend_from_slice(body);
+    message.buf
+}
+
+/// Arguments of `org.freedesktop.Notifications.Notify`, signature `susssasa{sv}i`
+fn notify_body(app_name: &str, replaces_id: u32, summary: &str, body: &str, urgency: Urgency, timeout_ms: i32) -> Vec<u8> {
+    let mut w = Writer::default();
+    w.string(app_name);
+    w.u32(replaces_id);
+    w.string("");
+    w.string(summary);
+    w.string(body);
+    w.array(4, |_| {});
+    w.array(8, |hints| {
+        hints.align(8);
+        hints.string("urgency");
+        hints.signature("y");
+        hints.byte(urgency as u8);
+    });
+    w.i32(timeout_ms);
+    w.buf
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Reply {
+    kind: u8,
+    reply_serial: Option<u32>,
+    error_name: Option<String>,
+    /// A method return's id or an error's text
+    first_u32: Option<u32>,
+    first_string: Option<String>,
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    little: bool,
+}
+
+impl Reader<'_> {
+    fn align(&mut self, n: usize) {
+        self.pos = self.pos.next_multiple_of(n);
+    }
+
+    fn byte(&mut self) -> Option<u8> {
+        let value = *self.buf.get(self.pos)?;
+        self.pos += 1;
+        Some(value)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.align(4);
+        let bytes: [u8; 4] = self.buf.get(self.pos..self.pos + 4)?.try_into().ok()?;
+        self.pos += 4;
+        Some(if self.little { u32::from_le_bytes(bytes) } else { u32::from_be_bytes(bytes) })
+    }
+
+    fn bytes(&mut self, len: usize) -> Option<String> {
+        let text = String::from_utf8_lossy(self.buf.get(self.pos..self.pos + len)?).into_owned();
+        self.pos += len + 1;
+        Some(text)
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let len = self.u32()? as usize;
+        self.bytes(len)
+    }
+
+    fn signature(&mut self) -> Option<String> {
+        let len = self.byte()? as usize;
+        self.bytes(len)
+    }
+}
+
+fn message_len(fixed: &[u8; 16]) -> usize {
+    let read = |at: usize| {
+        let bytes = [fixed[at], fixed[at + 1], fixed[at + 2], fixed[at + 3]];
+        let value = if fixed[0] == b'l' {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        };
+        value as usize
+    };
+    (16 + read(12)).next_multiple_of(8) + read(4)
+}
+
+fn parse_reply(message: &[u8]) -> Option<Reply> {
+    let mut r = Reader {
+        buf: message,
+        pos: 1,
+        little: message.first() == Some(&b'l'),
+    };
+    let mut reply = Reply {
+        kind: r.byte()?,
+        ..Default::default()
+    };
+    r.pos = 12;
+    let fields_end = 16 + r.u32()? as usize;
+    let mut signature = String::new();
+    while r.pos < fields_end {
+        r.align(8);
+        let code = r.byte()?;
+        match (code, r.signature()?.as_str()) {
+            (_, "u") => {
+                let value = r.u32()?;
+                if code == REPLY_SERIAL {
+                    reply.reply_serial = Some(value);
+                }
+            }
+            (_, "s" | "o") => {
+                let value = r.string()?;
+                if code == ERROR_NAME {
+                    reply.error_name = Some(value);
+                }
+            }
+            (_, "g") => {
+                let value = r.signature()?;
+                if code == SIGNATURE {
+                    signature = value;
+                }
+            }
+            _ => return None,
+        }
+    }
+    r.pos = fields_end;
+    r.align(8);
+    match signature.chars().next() {
+        Some('u') => reply.first_u32 = r.u32(),
+        Some('s') => reply.first_string = r.string(),
+        _ => {}
+    }
+    Some(reply)
+}
+
+pub trait Stream: Read + Write + Send {}
+
+impl<T: Read + Write + Send> Stream for T {}
+
+struct Notification {
+    key: usize,
+    summary: String,
+    body: String,
+    urgency: Urgency,
+}
+
+/// Sent from a worker thread, so a slow notification daemon never holds up the caller
+pub struct Notifier {
+    sender: Sender<Notification>,
+}
+
+impl Notifier {
+    pub fn session(app_name: &str) -> Result<Self, AppError> {
+        let address = std::env::var("DBUS_SESSION_BUS_ADDRESS").map_err(|_| AppError("DBUS_SESSION_BUS_ADDRESS is not set".into()))?;
+        Ok(Self::spawn(Connection::connect(&address, app_name)?))
+    }
+
+    pub fn spawn(mut connection: Connection) -> Self {
+        let (sender, receiver) = mpsc::channel::<Notification>();
+        thread::Builder::new()
+            .name("notify_thread".to_string())
+            .spawn(move || {
+                // last id of each key
+                let mut ids = HashMap::new();
+                for notification in receiver {
+                    let replaces = ids.get(&notification.key).copied().unwrap_or(0);
+                    match connection.notify(replaces, &notification.summary, &notification.body, notification.urgency) {
+                        Ok(id) => {
+                            ids.insert(notification.key, id);
+                        }
+                        Err(e) => warn!("Notification failed: {}", e),
+                    }
+                }
+            })
+            .expect("Failed to spawn notify thread");
+        Self { sender }
+    }
+
+    /// Replaces the last notification sent with the same `key`
+    pub fn notify(&self, key: usize, summary: &str, body: &str, urgency: Urgency) {
+        let _ = self.sender.send(Notification {
+            key,
+            summary: summary.into(),
+            body: body.into(),
+            urgency,
+        });
+    }
+}
+
+/// Session bus connection, each call waits for its reply
+pub struct Connection {
+    stream: Box<dyn Stream>,
+    app_name: String,
+    serial: u32,
+}
+
+impl Connection {
+    /// First usable `unix:path=` or `unix:abstract=` entry
+    #[cfg(unix)]
+    pub fn connect(address: &str, app_name: &str) -> Result<Self, AppError> {
+        use std::os::unix::net::UnixStream;
+
+        for entry in address.split(';') {
+            let Some(params) = entry.strip_prefix("unix:") else {
+                continue;
+            };
+            for (key, value) in params.split(',').filter_map(|param| param.split_once('=')) {
+                let stream = match key {
+                    "path" => UnixStream::connect(unescape(value)),
+                    #[cfg(target_os = "linux")]
+                    "abstract" => {
+                        use std::os::linux::net::SocketAddrExt;
+                        std::os::unix::net::SocketAddr::from_abstract_name(unescape(value)).and_then(|addr| UnixStream::connect_addr(&addr))
+                    }
+                    _ => continue,
+                };
+                let stream = stream.map_err(|e| AppError(format!("D-Bus {}: {}", entry, e)))?;
+                // a notification daemon that hangs should not freeze the window
+                stream.set_read_timeout(Some(std::time::Duration::from_secs(2)))?;
+                return Self::new(Box::new(stream), app_name, unsafe { libc::getuid() });
+            }
+        }
+        Err(AppError(format!("No unix socket in D-Bus address {}", address)))
+    }
+
+    #[cfg(not(unix))]
+    pub fn connect(address: &str, app_name: &str) -> Result<Self, AppError> {
+        let _ = app_name;
+        Err(AppError(format!("D-Bus address {} needs unix sockets", address)))
+    }
+
+    pub fn new(mut stream: Box<dyn Stream>, app_name: &str, uid: u32) -> Result<Self, AppError> {
+        let hex_uid: String = uid.to_string().bytes().map(|b| format!("{:02x}", b)).collect();
+        stream.write_all(format!("\0AUTH EXTERNAL {}\r\n", hex_uid).as_bytes())?;
+        let line = read_line(&mut stream)?;
+        if !line.starts_with("OK ") {
+            return Err(AppError(format!("D-Bus authentication refused: {}", line.trim_end())));
+        }
+        stream.write_all(b"BEGIN\r\n")?;
+
+        let mut connection = Self {
+            stream,
+            app_name: app_name.into(),
+            serial: 0,
+        };
+        connection.call("org.freedesktop.DBus", "/org/freedesktop/DBus", "org.freedesktop.DBus", "Hello", "", &[])?;
+        Ok(connection)
+    }
+
+    /// Replaces `replaces_id` when not 0, returns the new id
+    pub fn notify(&mut self, replaces_id: u32, summary: &str, body: &str, urgency: Urgency) -> Result<u32, AppError> {
+        let args = notify_body(&self.app_name, replaces_id, summary, body, urgency, -1);
+        let reply = self.call(
+            "org.freedesktop.Notifications",
+            "/org/freedesktop/Notifications",
+            "org.freedesktop.Notifications",
+            "Notify",
+            "susssasa{sv}i",
+            &args,
+        )?;
+        reply.first_u32.ok_or_else(|| AppError("Notify returned no id".into()))
+    }
+
+    fn call(&mut self, destination: &str, path: &str, interface: &str, member: &str, signature: &str, body: &[u8]) -> Result<Reply, AppError> {
+        self.serial += 1;
+        let serial = self.serial;
+        let message = method_call(serial, destination, path, interface, member, signature, body);
+        self.stream.write_all(&message)?;
+        // signals such as `NameAcquired` may come first
+        loop {
+            let message = self.read_message()?;
+            let reply = parse_reply(&message).ok_or_else(|| AppError("Malformed D-Bus message".into()))?;
+            match reply.kind {
+                METHOD_RETURN if reply.reply_serial == Some(serial) => return Ok(reply),
+                ERROR if reply.reply_serial == Some(serial) => {
+                    return Err(AppError(format!(
+                        "{}.{}: {} {}",
+                        interface,
+                        member,
+                        reply.error_name.unwrap_or_default(),
+                        reply.first_string.unwrap_or_default()
+                    )));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn read_message(&mut self) -> Result<Vec<u8>, AppError> {
+        let mut fixed = [0u8; 16];
+        self.stream.read_exact(&mut fixed)?;
+        let mut message = fixed.to_vec();
+        message.resize(message_len(&fixed), 0);
+        self.stream.read_exact(&mut message[16..])?;
+        Ok(message)
+    }
+}
+
+fn read_line(stream: &mut dyn Stream) -> Result<String, AppError> {
+    let mut line = Vec::new();
+    let mut byte = [0u8];
+    while !line.ends_with(b"\r\n") {
+        stream.read_exact(&mut byte)?;
+        line.push(byte[0]);
+    }
+    Ok(String::from_utf8_lossy(&line).into_owned())
+}
+
+fn unescape(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match (bytes[i], value.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(hex, 16).ok())) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{BufRead, BufReader},
+        process::{Child, Command, Stdio},
+        time::{Duration, Instant},
+    };
+
+    const SENDER: u8 = 7;
+
+    #[test]
+    fn marshalling() {
+        let body = notify_body("cat", 3, "Hot", "", Urgency::Critical, -1);
+        let message = method_call(9, "org.example", "/org/example", "org.example.Iface", "Ping", "susssasa{sv}i", &body);
+        assert_eq!(message_len(message[..16].try_into().unwrap()), message.len());
+        assert_eq!(message.len() % 8, body.len() % 8);
+        let parsed = parse_reply(&message).unwrap();
+        assert_eq!(parsed.kind, METHOD_CALL);
+        assert_eq!(parsed.reply_serial, None);
+        // the body's first string is the app name
+        assert_eq!(parsed.first_string.as_deref(), Some("cat"));
+        assert_eq!(unescape("/tmp/dbus%2dsocket%zz"), "/tmp/dbus-socket%zz");
+    }
+
+    /// Killed when dropped
+    struct Bus {
+        daemon: Child,
+        address: String,
+    }
+
+    impl Drop for Bus {
+        fn drop(&mut self) {
+            let _ = self.daemon.kill();
+            let _ = self.daemon.wait();
+        }
+    }
+
+    fn bus() -> Option<Bus> {
+        let mut daemon = Command::new("dbus-daemon")
+            .args(["--session", "--print-address", "--nofork"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| eprintln!("skipped, no dbus-daemon: {}", e))
+            .ok()?;
+        let mut address = String::new();
+        BufReader::new(daemon.stdout.take()?).read_line(&mut address).ok()?;
+        Some(Bus {
+            daemon,
+            address: address.trim().to_string(),
+        })
+    }
+
+    fn method(message: &[u8]) -> (u32, String, String, &[u8]) {
+        let mut r = Reader {
+            buf: message,
+            pos: 8,
+            little: message[0] == b'l',
+        };
+        let serial = r.u32().unwrap();
+        let fields_end = 16 + r.u32().unwrap() as usize;
+        let (mut sender, mut member) = (String::new(), String::new());
+        while r.pos < fields_end {
+            r.align(8);
+            let code = r.byte().unwrap();
+            let value = match r.signature().unwrap().as_str() {
+                "u" => r.u32().map(|value| value.to_string()),
+                "g" => r.signature(),
+                _ => r.string(),
+            };
+            match code {
+                SENDER => sender = value.unwrap(),
+                MEMBER => member = value.unwrap(),
+                _ => {}
+            }
+        }
+        (serial, sender, member, &message[fields_end.next_multiple_of(8)..])
+    }
+
+    /// Answers `count` Notify calls with ids from 1 up, passing on each replaces id and summary
+    fn notification_daemon(address: &str, count: u32) -> mpsc::Receiver<(u32, String)> {
+        let mut server = Connection::connect(address, "daemon").unwrap();
+        let mut name = Writer::default();
+        name.string("org.freedesktop.Notifications");
+        name.u32(0);
+        server
+            .call(
+                "org.freedesktop.DBus",
+                "/org/freedesktop/DBus",
+                "org.freedesktop.DBus",
+                "RequestName",
+                "su",
+                &name.buf,
+            )
+            .unwrap();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut id = 0;
+            while id < count {
+                let message = server.read_message().unwrap();
+                let (serial, caller, member, body) = method(&message);
+                if message[1] != METHOD_CALL || member != "Notify" {
+                    continue;
+                }
+                let mut args = Reader {
+                    buf: body,
+                    pos: 0,
+                    little: true,
+                };
+                let (_app, replaces, _icon, summary) = (args.string(), args.u32().unwrap(), args.string(), args.string().unwrap());
+                sender.send((replaces, summary)).unwrap();
+
+                id += 1;
+                let mut reply = Writer::default();
+                reply.buf.extend_from_slice(&[b'l', METHOD_RETURN, 0, 1]);
+                reply.u32(4);
+                reply.u32(1000 + id);
+                reply.array(8, |fields| {
+                    fields.align(8);
+                    fields.byte(REPLY_SERIAL);
+                    fields.signature("u");
+                    fields.u32(serial);
+                    fields.field(DESTINATION, "s", &caller);
+                    fields.field(SIGNATURE, "g", "u");
+                });
+                reply.align(8);
+                reply.u32(id);
+                server.stream.write_all(&reply.buf).unwrap();
+            }
+        });
+        receiver
+    }
+
+    #[test]
+    fn notifications_replace_by_key() {
+        let Some(bus) = bus() else {
+            return;
+        };
+        let calls = notification_daemon(&bus.address, 3);
+        let notifier = Notifier::spawn(Connection::connect(&bus.address, "cat").unwrap());
+        notifier.notify(1, "Hot", "", Urgency::Critical);
+        notifier.notify(1, "Cool again", "", Urgency::Normal);
+        notifier.notify(2, "Disk full", "", Urgency::Critical);
+        let calls: Vec<_> = (0..3).map(|_| calls.recv_timeout(Duration::from_secs(5)).unwrap()).collect();
+        assert_eq!(calls, [(0, "Hot".to_string()), (1, "Cool again".to_string()), (0, "Disk full".to_string())]);
+    }
+
+    #[test]
+    fn no_daemon() {
+        let Some(bus) = bus() else {
+            return;
+        };
+        let mut connection = Connection::connect(&bus.address, "cat").unwrap();
+        let error = connection.notify(0, "Hot", "", Urgency::Critical).err().unwrap();
+        assert!(error.0.contains("ServiceUnknown"), "{}", error);
+
+        // failures stay on the worker
+        let notifier = Notifier::spawn(connection);
+        let start = Instant::now();
+        notifier.notify(0, "Hot", "", Urgency::Critical);
+        assert!(start.elapsed() < Duration::from_millis(50));
+    }
+}
@@ -1,4 +1,14 @@
 pub mod traits;
+pub mod color;
 pub mod dx_render;
+pub mod image;
+pub mod layout;
+pub mod resample;
+pub mod scaled;
+pub mod software;
+pub mod stream;
+pub mod text;
+pub mod transform;
+pub mod upscale;
 
-pub use traits::Render;
\ No newline at end of file
+pub use traits::{Bubble, BubbleStyle, Color, Render, Sparkline, Text, TextStyle, Tint};
\ No newline at end of file
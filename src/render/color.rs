@@ -0,0 +1,210 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    convert::Infallible,
+};
+
+use super::{Tint, image::Image};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColorTransform {
+    /// Degrees
+    HueShift(f32),
+    Tint(Tint),
+    /// Replaces exact colours, as found in GIF palettes
+    Palette(Vec<([u8; 3], [u8; 3])>),
+    Chain(Vec<ColorTransform>),
+}
+
+impl ColorTransform {
+    pub fn apply(&self, image: &Image) -> Image {
+        match self {
+            ColorTransform::Palette(pairs) => {
+                let map: HashMap<[u8; 3], [u8; 3]> = pairs.iter().copied().collect();
+                map_pixels(image, |[r, g, b, a]| {
+                    let [r, g, b] = map.get(&[r, g, b]).copied().unwrap_or([r, g, b]);
+                    [r, g, b, a]
+                })
+            }
+            ColorTransform::Chain(transforms) => transforms.iter().fold(image.clone(), |image, transform| transform.apply(&image)),
+            _ => map_pixels(image, |pixel| self.pixel(pixel)),
+        }
+    }
+
+    fn pixel(&self, [r, g, b, a]: [u8; 4]) -> [u8; 4] {
+        match self {
+            ColorTransform::HueShift(degrees) => {
+                let (h, s, v) = rgb_to_hsv([r, g, b].map(|c| c as f32 / 255.0));
+                let [r, g, b] = hsv_to_rgb((h + degrees).rem_euclid(360.0), s, v).map(|c| (c * 255.0).round() as u8);
+                [r, g, b, a]
+            }
+            ColorTransform::Tint(tint) => {
+                let strength = tint.strength.clamp(0.0, 1.0);
+                let blend = |c: u8, t: f32| (c as f32 + (t.clamp(0.0, 1.0) * 255.0 - c as f32) * strength).round() as u8;
+                [blend(r, tint.r), blend(g, tint.g), blend(b, tint.b), a]
+            }
+            ColorTransform::Palette(_) | ColorTransform::Chain(_) => [r, g, b, a],
+        }
+    }
+}
+
+fn map_pixels(image: &Image, f: impl Fn([u8; 4]) -> [u8; 4]) -> Image {
+    Image {
+        pixels: image.pixels.iter().map(|pixel| if pixel[3] == 0 { *pixel } else { f(*pixel) }).collect(),
+        ..*image
+    }
+}
+
+/// Hue in degrees, saturation and value in 0..=1
+pub fn rgb_to_hsv([r, g, b]: [f32; 3]) -> (f32, f32, f32) {
+    let max = r.max(g).max(b);
+    let delta = max - r.min(g).min(b);
+    let hue = if delta == 0.0 {
+        0.0
+    } else if max == r {
+        60.0 * ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+    (hue, if max == 0.0 { 0.0 } else { delta / max }, max)
+}
+
+pub fn hsv_to_rgb(hue: f32, saturation: f32, value: f32) -> [f32; 3] {
+    let chroma = value * saturation;
+    let x = chroma * (1.0 - ((hue / 60.0).rem_euclid(2.0) - 1.0).abs());
+    let [r, g, b] = match (hue.rem_euclid(360.0) / 60.0) as u32 {
+        0 => [chroma, x, 0.0],
+        1 => [x, chroma, 0.0],
+        2 => [0.0, chroma, x],
+        3 => [0.0, x, chroma],
+        4 => [x, 0.0, chroma],
+        _ => [chroma, 0.0, x],
+    };
+    [r, g, b].map(|c| c + value - chroma)
+}
+
+/// The transform of the highest threshold at or under the value
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorStops {
+    stops: Vec<(f32, ColorTransform)>,
+}
+
+impl ColorStops {
+    pub fn new(mut stops: Vec<(f32, ColorTransform)>) -> Self {
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Self { stops }
+    }
+
+    pub fn pick(&self, value: f32) -> Option<&ColorTransform> {
+        self.stops
+            .iter()
+            .rev()
+            .find(|(threshold, _)| value >= *threshold)
+            .map(|(_, transform)| transform)
+    }
+}
+
+impl Default for ColorStops {
+    /// Green at idle, orange from 60, red from 90
+    fn default() -> Self {
+        let tint = |r, g, b| ColorTransform::Tint(Tint { r, g, b, strength: 0.5 });
+        Self::new(vec![
+            (f32::NEG_INFINITY, tint(0.2, 0.8, 0.2)),
+            (60.0, tint(1.0, 0.55, 0.0)),
+            (90.0, tint(1.0, 0.1, 0.1)),
+        ])
+    }
+}
+
+/// Frames made with the last few transforms
+#[derive(Debug)]
+pub struct TransformCache<T> {
+    entries: VecDeque<(ColorTransform, Vec<T>)>,
+    capacity: usize,
+}
+
+impl<T> TransformCache<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: VecDeque::new(),
+            capacity: capacity.max(1),
+        }
+    }
+
+    pub fn get(&self, transform: &ColorTransform) -> Option<&[T]> {
+        self.entries.iter().find(|(t, _)| t == transform).map(|(_, frames)| frames.as_slice())
+    }
+
+    pub fn get_or_try_insert<E>(&mut self, transform: &ColorTransform, make: impl FnOnce() -> Result<Vec<T>, E>) -> Result<&[T], E> {
+        let frames = match self.entries.iter().position(|(t, _)| t == transform) {
+            Some(i) => self.entries.remove(i).expect("Index is in range"),
+            None => (transform.clone(), make()?),
+        };
+        self.entries.push_back(frames);
+        if self.entries.len() > self.capacity {
+            self.entries.pop_front();
+        }
+        Ok(&self.entries.back().expect("Just pushed").1)
+    }
+
+    pub fn get_or_insert_with(&mut self, transform: &ColorTransform, make: impl FnOnce() -> Vec<T>) -> &[T] {
+        match self.get_or_try_insert(transform, || Ok::<_, Infallible>(make())) {
+            Ok(frames) => frames,
+            Err(never) => match never {},
+        }
+    }
+
+    /// Drops the frames of every other transform
+    pub fn only(&mut self, transform: &ColorTransform) -> Option<&mut Vec<T>> {
+        self.entries.retain(|(t, _)| t == transform);
+        self.entries.back_mut().map(|(_, frames)| frames)
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+impl<T> Default for TransformCache<T> {
+    fn default() -> Self {
+        Self::new(4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transform_cache() {
+        let (a, b, c) = (ColorTransform::HueShift(90.0), ColorTransform::HueShift(180.0), ColorTransform::HueShift(270.0));
+        let mut cache = TransformCache::new(2);
+        cache.get_or_insert_with(&a, || vec![1]);
+        cache.get_or_insert_with(&b, || vec![2]);
+        // a was used last, so b makes way for c
+        cache.get_or_insert_with(&a, || unreachable!());
+        cache.get_or_insert_with(&c, || vec![3]);
+        assert!(cache.get(&b).is_none());
+        assert_eq!(cache.get(&a), Some(&[1][..]));
+
+        cache.only(&c).unwrap().push(4);
+        assert!(cache.get(&a).is_none());
+        assert_eq!(cache.get(&c), Some(&[3, 4][..]));
+        assert!(cache.only(&a).is_none());
+    }
+
+    #[test]
+    fn transforms() {
+        let image = Image {
+            width: 3,
+            height: 1,
+            pixels: vec![[255, 0, 0, 255], [10, 20, 30, 255], [255, 0, 0, 0]],
+        };
+        let palette = ColorTransform::Palette(vec![([255, 0, 0], [0, 255, 0])]);
+        assert_eq!(palette.apply(&image).pixels, [[0, 255, 0, 255], [10, 20, 30, 255], [255, 0, 0, 0]]);
+        assert_eq!(ColorTransform::HueShift(120.0).apply(&image).pixels[0], [0, 255, 0, 255]);
+        let chain = ColorTransform::Chain(vec![palette, ColorTransform::HueShift(120.0)]);
+        assert_eq!(chain.apply(&image).pixels[0], [0, 0, 255, 255]);
+    }
+}
@@ -1,103 +1,372 @@
-use super::Render;
-use crate::{AppError, window::WindowHandle};
+use std::{cell::RefCell, fs::File, io::BufReader};
+
+use super::{
+    Bubble, Color, Render, Sparkline, Text, Tint,
+    color::{ColorTransform, TransformCache},
+    image::{Image, decode_gif, decode_gif_each},
+    layout::{self, Rect},
+    resample,
+    scaled::{self, ScaledCache},
+    stream::FrameStream,
+    text,
+    transform::{Filter, FrameTransform},
+};
+use crate::{
+    AppError,
+    animation::AnimationInfo,
+    window::{BubbleWindow, WindowHandle},
+};
 use log::debug;
-use windows::{
-    Win32::{
-        Foundation::{GENERIC_READ, RECT},
-        Graphics::{
-            Direct2D::{Common::*, *},
-            Dxgi::Common::DXGI_FORMAT_B8G8R8A8_UNORM,
-            Imaging::*,
-        },
-        System::Com::{CLSCTX_INPROC_SERVER, COINIT_APARTMENTTHREADED, CoCreateInstance, CoInitializeEx, CoUninitialize},
-        UI::{HiDpi::GetDpiForWindow, WindowsAndMessaging::GetClientRect},
+use windows::Win32::{
+    Foundation::RECT,
+    Graphics::{
+        Direct2D::{Common::*, *},
+        Dxgi::Common::DXGI_FORMAT_B8G8R8A8_UNORM,
     },
-    core::HSTRING,
+    UI::{HiDpi::GetDpiForWindow, WindowsAndMessaging::GetClientRect},
 };
 
+/// Frames unpacked ahead of the one drawn when streaming
+const STREAM_PREFETCH: usize = 8;
+const STREAM_SCALED_SHARE: usize = 4;
+
 struct GifFrame {
     bitmap: ID2D1Bitmap,
 }
 
 pub struct DxRender {
     frames: Vec<GifFrame>,
-    pub current_frame: usize,
+    /// Kept for recolouring and the resampling filters
+    images: Vec<Option<Image>>,
+    keep_images: bool,
+    recolor: Option<ColorTransform>,
+    /// Only the current transform's bitmaps, the others are made again from `images`
+    recolored: TransformCache<ID2D1Bitmap>,
+    transform: FrameTransform,
+    filters: Vec<Filter>,
+    stream: Option<FrameStream>,
+    /// Last streamed frame uploaded, so a still frame is not uploaded on every paint
+    streamed: RefCell<Option<(usize, ID2D1Bitmap)>>,
+    scaled: RefCell<ScaledCache<ID2D1Bitmap>>,
+    size: (u32, u32),
+    dpi: f32,
     render_target: ID2D1HwndRenderTarget,
+    tint_brush: Option<ID2D1SolidColorBrush>,
+    sparkline: Option<(Sparkline, ID2D1SolidColorBrush)>,
+    text: Option<(Text, ID2D1Bitmap)>,
+    bubble: Option<Bubble>,
+    bubble_window: BubbleWindow,
 }
 
 unsafe impl Send for DxRender {}
 
 impl DxRender {
-    pub fn new(hwnd: WindowHandle) -> Result<Self, AppError> {
+    pub fn new(hwnd: WindowHandle, bubble_window: BubbleWindow) -> Result<Self, AppError> {
         let render_target = get_render_target(hwnd)?;
+        let mut rc = RECT::default();
+        unsafe { GetClientRect(hwnd.0, &mut rc)? };
+        let dpi = unsafe { GetDpiForWindow(hwnd.0) as f32 };
+        unsafe { render_target.SetDpi(dpi, dpi) };
         Ok(DxRender {
             frames: Vec::new(),
-            current_frame: 0,
+            images: Vec::new(),
+            keep_images: false,
+            recolor: None,
+            recolored: TransformCache::new(1),
+            transform: FrameTransform::default(),
+            filters: Vec::new(),
+            stream: None,
+            streamed: RefCell::default(),
+            scaled: RefCell::default(),
+            size: ((rc.right - rc.left) as u32, (rc.bottom - rc.top) as u32),
+            dpi,
             render_target,
+            tint_brush: None,
+            sparkline: None,
+            text: None,
+            bubble: None,
+            bubble_window,
         })
     }
-    fn create_d2d_bitmap_from_frame(&self, wic_factory: &IWICImagingFactory, decoder: &IWICBitmapDecoder, index: u32) -> Result<ID2D1Bitmap, AppError> {
-        let wic_frame = unsafe { decoder.GetFrame(index)? };
-        let converter = unsafe { wic_factory.CreateFormatConverter()? };
 
-        unsafe { converter.Initialize(&wic_frame, &GUID_WICPixelFormat32bppPBGRA, WICBitmapDitherTypeNone, None, 0.0, WICBitmapPaletteTypeCustom)? };
+    fn create_brush(&self, color: Color) -> Result<ID2D1SolidColorBrush, AppError> {
+        let color = D2D1_COLOR_F {
+            r: color.r,
+            g: color.g,
+            b: color.b,
+            a: color.a.clamp(0.0, 1.0),
+        };
+        Ok(unsafe { self.render_target.CreateSolidColorBrush(&color, None)? })
+    }
+
+
+    pub fn set_keep_images(&mut self, keep: bool) {
+        self.keep_images = keep;
+    }
+
+    /// With a `stream_cap`, files loaded from now on are streamed and scaled frames take a quarter of it at most
+    pub fn set_budgets(&mut self, scaled: usize, stream_cap: Option<usize>) {
+        let scaled = match stream_cap {
+            Some(cap) => scaled.min(cap / STREAM_SCALED_SHARE),
+            None => scaled,
+        };
+        self.scaled.get_mut().set_budget(scaled);
+        self.stream = stream_cap.map(|cap| {
+            let stream = FrameStream::new(cap - scaled, STREAM_PREFETCH);
+            stream.set_recolor(self.recolor.clone());
+            stream
+        });
+    }
+
+    fn frame_count(&self) -> usize {
+        self.stream.as_ref().map_or(self.frames.len(), FrameStream::len)
+    }
+
+    /// Frame bitmaps are 96 DPI, so DIPs are pixels
+    fn frame_size(&self, frame: usize) -> Option<(f32, f32)> {
+        match &self.stream {
+            Some(stream) => stream.size(frame).map(|(width, height)| (width as f32, height as f32)),
+            None => self.frames.get(frame).map(|frame| {
+                let size = unsafe { frame.bitmap.GetSize() };
+                (size.width, size.height)
+            }),
+        }
+    }
 
-        match unsafe { self.render_target.CreateBitmapFromWicBitmap(&converter, None) } {
-            Ok(bitmap) => Ok(bitmap),
-            _ => Err(AppError("Get bitmap error".into())),
+    /// Streamed frames come recoloured from the stream
+    fn frame_image(&self, frame: usize) -> Option<Image> {
+        if let Some(stream) = &self.stream {
+            return stream.frame(frame).map(|image| (*image).clone());
+        }
+        let image = self.images.get(frame)?.as_ref()?;
+        Some(match &self.recolor {
+            Some(transform) => transform.apply(image),
+            None => image.clone(),
+        })
+    }
+
+    /// Only the last streamed frame uploaded is kept
+    fn frame_bitmap(&self, frame: usize) -> Result<ID2D1Bitmap, AppError> {
+        let missing = || AppError(format!("No frame {}", frame));
+        if let Some(stream) = &self.stream {
+            if let Some((index, bitmap)) = &*self.streamed.borrow()
+                && *index == frame
+            {
+                return Ok(bitmap.clone());
+            }
+            let image = stream.frame(frame).ok_or_else(missing)?;
+            let bitmap = self.create_image_bitmap(&image, 96.0)?;
+            *self.streamed.borrow_mut() = Some((frame, bitmap.clone()));
+            return Ok(bitmap);
+        }
+        let recolored = self.recolor.as_ref().and_then(|transform| self.recolored.get(transform));
+        match recolored {
+            Some(bitmaps) => bitmaps.get(frame).cloned(),
+            None => self.frames.get(frame).map(|frame| frame.bitmap.clone()),
+        }
+        .ok_or_else(missing)
+    }
+
+    fn create_image_bitmap(&self, image: &Image, dpi: f32) -> Result<ID2D1Bitmap, AppError> {
+        let pixels = image.to_premultiplied_bgra();
+        let size = D2D_SIZE_U {
+            width: image.width as u32,
+            height: image.height as u32,
+        };
+        let properties = D2D1_BITMAP_PROPERTIES {
+            pixelFormat: D2D1_PIXEL_FORMAT {
+                format: DXGI_FORMAT_B8G8R8A8_UNORM,
+                alphaMode: D2D1_ALPHA_MODE_PREMULTIPLIED,
+            },
+            dpiX: dpi,
+            dpiY: dpi,
+        };
+        Ok(unsafe {
+            self.render_target
+                .CreateBitmap(size, Some(pixels.as_ptr() as *const _), size.width * 4, &properties)?
+        })
+    }
+
+    fn recolor_frames(&mut self) -> Result<(), AppError> {
+        let Some(transform) = self.recolor.as_ref().filter(|_| self.stream.is_none()) else {
+            return Ok(());
+        };
+        let mut recolored = std::mem::take(&mut self.recolored);
+        let result = recolored
+            .get_or_try_insert(transform, || {
+                // frames whose image was not kept stay as they are
+                self.images
+                    .iter()
+                    .zip(&self.frames)
+                    .map(|(image, frame)| match image {
+                        Some(image) => self.create_image_bitmap(&transform.apply(image), 96.0),
+                        None => Ok(frame.bitmap.clone()),
+                    })
+                    .collect()
+            })
+            .map(|_| ());
+        self.recolored = recolored;
+        result
+    }
+
+    fn filter(&self, frame: usize) -> Filter {
+        self.filters.get(frame).copied().unwrap_or_default()
+    }
+
+    fn interpolation(&self, frame: usize) -> D2D1_BITMAP_INTERPOLATION_MODE {
+        match self.filter(frame) {
+            Filter::Nearest => D2D1_BITMAP_INTERPOLATION_MODE_NEAREST_NEIGHBOR,
+            Filter::Linear | Filter::Lanczos3 | Filter::Mitchell => D2D1_BITMAP_INTERPOLATION_MODE_LINEAR,
+        }
+    }
+
+    /// Resampled on the cpu for the kernel filters, else drawn once by Direct2D into an offscreen bitmap
+    fn prescale(&self, frame: usize, width: usize, height: usize) -> Result<ID2D1Bitmap, AppError> {
+        if let Some(kernel) = self.filter(frame).kernel()
+            && let Some(image) = self.frame_image(frame)
+        {
+            return self.create_image_bitmap(&resample::resize(&image, width, height, kernel), self.dpi);
+        }
+        let source = self.frame_bitmap(frame)?;
+        let size = D2D_SIZE_U {
+            width: width as u32,
+            height: height as u32,
+        };
+        unsafe {
+            let target = self.render_target.CreateCompatibleRenderTarget(None, Some(&size), None, D2D1_COMPATIBLE_RENDER_TARGET_OPTIONS_NONE)?;
+            let dips = target.GetSize();
+            let dest_rect = D2D_RECT_F {
+                left: 0.0,
+                top: 0.0,
+                right: dips.width,
+                bottom: dips.height,
+            };
+            target.BeginDraw();
+            target.Clear(None);
+            target.DrawBitmap(&source, Some(&dest_rect), 1.0, self.interpolation(frame), None);
+            target.EndDraw(None, None)?;
+            Ok(target.GetBitmap()?)
         }
     }
 }
 
 impl Render for DxRender {
-    fn load_src_data(&mut self, path: &str) -> Result<(), crate::AppError> {
-        let _ = unsafe { CoInitializeEx(None, COINIT_APARTMENTTHREADED) };
-        let wic_factory: IWICImagingFactory = unsafe { CoCreateInstance(&CLSID_WICImagingFactory, None, CLSCTX_INPROC_SERVER)? };
-        let decoder = unsafe { wic_factory.CreateDecoderFromFilename(&HSTRING::from(path), None, GENERIC_READ, WICDecodeMetadataCacheOnLoad)? };
-        let frame_count = unsafe { decoder.GetFrameCount()? };
-        debug!("Get frame count: {:?}", frame_count);
-        for i in 0..frame_count {
-            let frame_bitmap = self.create_d2d_bitmap_from_frame(&wic_factory, &decoder, i)?;
-            let gif_frame = GifFrame { bitmap: frame_bitmap };
-            self.frames.push(gif_frame);
+    fn load_src_data(&mut self, path: &str) -> Result<AnimationInfo, crate::AppError> {
+        let info = AnimationInfo::from_gif(path, self.frame_count())?;
+        if let Some(stream) = &self.stream {
+            // decoded one frame at a time, the file is read again for each trim
+            let decode = |each: &mut dyn FnMut(Image)| decode_gif_each(BufReader::new(File::open(path)?), each);
+            let transform = self.transform.resolve_trims(decode)?;
+            let first = stream.len();
+            decode_gif_each(BufReader::new(File::open(path)?), |image| {
+                stream.push(&transform.apply_frame(image));
+            })?;
+            self.filters.resize(stream.len(), self.transform.filter);
+            debug!("Streaming {} frames, {:?} bytes packed and unpacked", stream.len() - first, stream.bytes());
+            return Ok(info);
         }
-        let _ = unsafe { CoUninitialize() };
-        Ok(())
+        // decoded on the cpu so frames can be recoloured before upload
+        let images = self.transform.apply(decode_gif(BufReader::new(File::open(path)?))?);
+        debug!("Get frame count: {:?}", images.len());
+        let keep = self.keep_images || self.transform.filter.kernel().is_some();
+        for image in images {
+            let frame_bitmap = self.create_image_bitmap(&image, 96.0)?;
+            self.frames.push(GifFrame { bitmap: frame_bitmap });
+            self.images.push(keep.then_some(image));
+            self.filters.push(self.transform.filter);
+        }
+        self.recolored.clear();
+        self.recolor_frames()?;
+        Ok(info)
+    }
+
+    fn render_frame(&self, frame: usize) -> Result<(), AppError> {
+        self.render_grid(&[frame], 1)
     }
 
-    fn render_frame(&self) -> Result<(), AppError> {
+    fn render_grid(&self, frames: &[usize], columns: usize) -> Result<(), AppError> {
         unsafe {
             self.render_target.BeginDraw();
             self.render_target.Clear(None);
 
-            if let Some(frame) = self.frames.get(self.current_frame) {
-                let mut rc = RECT::default();
-                GetClientRect(self.render_target.GetHwnd(), &mut rc)?;
-                let hwnd = self.render_target.GetHwnd();
-                let dpi = GetDpiForWindow(hwnd) as f32;
-                let scale = dpi / 96.0;
-
-                let win_width = (rc.right - rc.left) as f32 / scale;
-                let win_height = (rc.bottom - rc.top) as f32 / scale;
-
-                let bmp = &frame.bitmap;
-                let bmp_size = bmp.GetSize();
-                let bmp_width = bmp_size.width;
-                let bmp_height = bmp_size.height;
-
-                let scale = (win_width / bmp_width).min(win_height / bmp_height);
-                let draw_width = bmp_width * scale;
-                let draw_height = bmp_height * scale;
-                let left = (win_width - draw_width) / 2.0;
-                let top = (win_height - draw_height) / 2.0;
+            let scale = self.dpi / 96.0;
+            let window = Rect::new(0.0, 0.0, self.size.0 as f32 / scale, self.size.1 as f32 / scale);
+            let (area, strip) = match &self.sparkline {
+                Some((sparkline, _)) => {
+                    let (area, strip) = layout::split(window, sparkline.region);
+                    (area, Some(strip))
+                }
+                None => (window, None),
+            };
+
+            let mut cache = self.scaled.borrow_mut();
+            for (cell, frame) in layout::grid(area, frames.len(), columns).into_iter().zip(frames) {
+                let Some((frame_width, frame_height)) = self.frame_size(*frame) else {
+                    continue;
+                };
+                let (dest, width, height) = scaled::snap(layout::aspect_fit(frame_width, frame_height, cell), scale);
                 let dest_rect = D2D_RECT_F {
-                    left,
-                    top,
-                    right: left + draw_width,
-                    bottom: top + draw_height,
+                    left: dest.left,
+                    top: dest.top,
+                    right: dest.right(),
+                    bottom: dest.bottom(),
                 };
+                let (bmp, interpolation) = match cache.get_or_try_insert(*frame, width, height, || self.prescale(*frame, width, height)) {
+                    Ok(prescaled) => (prescaled.clone(), D2D1_BITMAP_INTERPOLATION_MODE_NEAREST_NEIGHBOR),
+                    // scaled on the fly rather than not drawn
+                    Err(e) => {
+                        debug!("Prescaling frame {} failed: {}", frame, e);
+                        let Ok(bmp) = self.frame_bitmap(*frame) else {
+                            continue;
+                        };
+                        (bmp, self.interpolation(*frame))
+                    }
+                };
+
+                self.render_target.DrawBitmap(&bmp, Some(&dest_rect), 1.0, interpolation, None);
+                if let Some(brush) = &self.tint_brush {
+                    // FillOpacityMask only works in aliased mode
+                    self.render_target.SetAntialiasMode(D2D1_ANTIALIAS_MODE_ALIASED);
+                    self.render_target.FillOpacityMask(&bmp, brush, D2D1_OPACITY_MASK_CONTENT_GRAPHICS, Some(&dest_rect), None);
+                    self.render_target.SetAntialiasMode(D2D1_ANTIALIAS_MODE_PER_PRIMITIVE);
+                }
+            }
+
+            if let (Some((sparkline, brush)), Some(strip)) = (&self.sparkline, strip) {
+                let points: Vec<D2D_POINT_2F> = layout::sparkline_points(&sparkline.values, sparkline.min, sparkline.max, strip)
+                    .into_iter()
+                    .map(|(x, y)| D2D_POINT_2F { x, y })
+                    .collect();
+                match points.as_slice() {
+                    // a zero length line has nothing to stroke, so a lone value is a 1 DIP square
+                    [point] => {
+                        let dot = D2D_RECT_F {
+                            left: point.x - 0.5,
+                            top: point.y - 0.5,
+                            right: point.x + 0.5,
+                            bottom: point.y + 0.5,
+                        };
+                        self.render_target.FillRectangle(&dot, brush);
+                    }
+                    points => {
+                        for pair in points.windows(2) {
+                            self.render_target.DrawLine(pair[0], pair[1], brush, 1.0, None);
+                        }
+                    }
+                }
+            }
 
-                self.render_target.DrawBitmap(bmp, Some(&dest_rect), 1.0, D2D1_BITMAP_INTERPOLATION_MODE_LINEAR, None);
+            if let Some((text, bitmap)) = &self.text {
+                // the bitmap carries the window DPI, so its size is already in DIPs
+                let size = bitmap.GetSize();
+                let dest = layout::align(size.width, size.height, window, text.style.horizontal, text.style.vertical);
+                let dest_rect = D2D_RECT_F {
+                    left: dest.left,
+                    top: dest.top,
+                    right: dest.right(),
+                    bottom: dest.bottom(),
+                };
+                self.render_target.DrawBitmap(bitmap, Some(&dest_rect), 1.0, D2D1_BITMAP_INTERPOLATION_MODE_NEAREST_NEIGHBOR, None);
             }
             let (mut t1, mut t2) = (0u64, 0u64);
             self.render_target
@@ -106,8 +375,87 @@ impl Render for DxRender {
         }
     }
 
-    fn next_frame(&mut self) -> Result<(), AppError> {
-        self.current_frame = (self.current_frame + 1) % self.frames.len();
+    fn set_tint(&mut self, tint: Option<Tint>) -> Result<(), AppError> {
+        self.tint_brush = match tint {
+            Some(tint) => Some(self.create_brush(Color {
+                r: tint.r,
+                g: tint.g,
+                b: tint.b,
+                a: tint.strength,
+            })?),
+            None => None,
+        };
+        Ok(())
+    }
+
+    fn set_sparkline(&mut self, sparkline: Option<Sparkline>) -> Result<(), AppError> {
+        self.sparkline = match sparkline {
+            Some(sparkline) => {
+                let brush = self.create_brush(sparkline.color)?;
+                Some((sparkline, brush))
+            }
+            None => None,
+        };
+        Ok(())
+    }
+
+    fn set_text(&mut self, text: Option<Text>) -> Result<(), AppError> {
+        self.text = match text {
+            Some(text) => {
+                let dpi = self.dpi;
+                let bitmap = self.create_image_bitmap(&text::rasterize(&text, dpi / 96.0), dpi)?;
+                Some((text, bitmap))
+            }
+            None => None,
+        };
+        Ok(())
+    }
+
+    fn set_bubble(&mut self, bubble: Option<Bubble>) -> Result<(), AppError> {
+        match (&bubble, &self.bubble) {
+            // fade steps only change the popup's opacity
+            (Some(new), Some(old)) if old.text == new.text && old.style == new.style => self.bubble_window.set_opacity(new.opacity)?,
+            (Some(new), _) => {
+                let image = text::rasterize_bubble(new, self.dpi / 96.0);
+                self.bubble_window.show(&image, new.style.horizontal, new.opacity)?
+            }
+            (None, _) => self.bubble_window.hide(),
+        }
+        self.bubble = bubble;
+        Ok(())
+    }
+
+    fn set_recolor(&mut self, transform: Option<ColorTransform>) -> Result<(), AppError> {
+        if let Some(stream) = &self.stream {
+            stream.set_recolor(transform.clone());
+        }
+        self.recolor = transform;
+        self.streamed.get_mut().take();
+        self.scaled.get_mut().clear();
+        self.recolor_frames()
+    }
+
+    fn set_frame_transform(&mut self, transform: FrameTransform) -> Result<(), AppError> {
+        self.transform = transform;
+        Ok(())
+    }
+
+    fn set_window_size(&mut self, width: u32, height: u32) -> Result<(), AppError> {
+        if (width, height) != self.size {
+            unsafe { self.render_target.Resize(&D2D_SIZE_U { width, height })? };
+            self.size = (width, height);
+            self.scaled.get_mut().clear();
+        }
+        Ok(())
+    }
+
+    fn set_dpi(&mut self, dpi: u32) -> Result<(), AppError> {
+        let dpi = dpi as f32;
+        if dpi != self.dpi {
+            unsafe { self.render_target.SetDpi(dpi, dpi) };
+            self.dpi = dpi;
+            self.scaled.get_mut().clear();
+        }
         Ok(())
     }
 }
@@ -0,0 +1,111 @@
+use std::io::Read;
+
+use crate::AppError;
+
+/// Straight alpha RGBA pixels, row by row
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<[u8; 4]>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![[0; 4]; width * height],
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> [u8; 4] {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, pixel: [u8; 4]) {
+        self.pixels[y * self.width + x] = pixel;
+    }
+
+    pub fn fill(&mut self, pixel: [u8; 4]) {
+        self.pixels.fill(pixel);
+    }
+
+    /// The layout Direct2D bitmaps and layered windows take
+    pub fn to_premultiplied_bgra(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|[r, g, b, a]| {
+                let premultiply = |c: u8| (c as u16 * *a as u16 / 255) as u8;
+                [premultiply(*b), premultiply(*g), premultiply(*r), *a]
+            })
+            .collect()
+    }
+
+    /// Ignored outside the image
+    pub fn blend(&mut self, x: usize, y: usize, pixel: [u8; 4]) {
+        if x < self.width && y < self.height {
+            let i = y * self.width + x;
+            self.pixels[i] = over(pixel, self.pixels[i]);
+        }
+    }
+}
+
+pub fn over(src: [u8; 4], dst: [u8; 4]) -> [u8; 4] {
+    match (src[3], dst[3]) {
+        (255, _) | (_, 0) => src,
+        (0, _) => dst,
+        (sa, da) => {
+            let (sa, da) = (sa as f32 / 255.0, da as f32 / 255.0);
+            let a = sa + da * (1.0 - sa);
+            let channel = |s: u8, d: u8| ((s as f32 * sa + d as f32 * da * (1.0 - sa)) / a).round() as u8;
+            [
+                channel(src[0], dst[0]),
+                channel(src[1], dst[1]),
+                channel(src[2], dst[2]),
+                (a * 255.0).round() as u8,
+            ]
+        }
+    }
+}
+
+/// Every frame as a full canvas, disposal applied
+pub fn decode_gif(reader: impl Read) -> Result<Vec<Image>, AppError> {
+    let mut frames = Vec::new();
+    decode_gif_each(reader, |frame| frames.push(frame))?;
+    Ok(frames)
+}
+
+pub fn decode_gif_each(reader: impl Read, mut each: impl FnMut(Image)) -> Result<(), AppError> {
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::RGBA);
+    let mut decoder = options.read_info(reader)?;
+    let mut canvas = Image::new(decoder.width() as usize, decoder.height() as usize);
+
+    while let Some(frame) = decoder.read_next_frame()? {
+        let previous = (frame.dispose == gif::DisposalMethod::Previous).then(|| canvas.clone());
+        let (left, top) = (frame.left as usize, frame.top as usize);
+        let (width, height) = (frame.width as usize, frame.height as usize);
+        for (i, pixel) in frame.buffer.chunks_exact(4).enumerate() {
+            let (x, y) = (left + i % width, top + i / width);
+            // transparent pixels let the previous frame show through
+            if pixel[3] != 0 && x < canvas.width && y < canvas.height {
+                canvas.set(x, y, [pixel[0], pixel[1], pixel[2], pixel[3]]);
+            }
+        }
+        each(canvas.clone());
+
+        match frame.dispose {
+            gif::DisposalMethod::Background => {
+                for y in top..(top + height).min(canvas.height) {
+                    for x in left..(left + width).min(canvas.width) {
+                        canvas.set(x, y, [0; 4]);
+                    }
+                }
+            }
+            gif::DisposalMethod::Previous => canvas = previous.unwrap_or(canvas),
+            _ => {}
+        }
+    }
+    Ok(())
+}
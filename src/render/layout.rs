@@ -0,0 +1,171 @@
+/// Window units, DIPs for `DxRender` and pixels for `SoftwareRender`
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub left: f32,
+    pub top: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Rect {
+    pub fn new(left: f32, top: f32, width: f32, height: f32) -> Self {
+        Self { left, top, width, height }
+    }
+
+    pub fn right(&self) -> f32 {
+        self.left + self.width
+    }
+
+    pub fn bottom(&self) -> f32 {
+        self.top + self.height
+    }
+
+    pub fn snapped(&self) -> Rect {
+        let (left, top) = (self.left.round(), self.top.round());
+        Rect::new(left, top, self.right().round() - left, self.bottom().round() - top)
+    }
+}
+
+pub fn aspect_fit(width: f32, height: f32, bounds: Rect) -> Rect {
+    if width <= 0.0 || height <= 0.0 {
+        return Rect::new(bounds.left + bounds.width / 2.0, bounds.top + bounds.height / 2.0, 0.0, 0.0);
+    }
+    let scale = (bounds.width / width).min(bounds.height / height);
+    let (draw_width, draw_height) = (width * scale, height * scale);
+    Rect::new(
+        bounds.left + (bounds.width - draw_width) / 2.0,
+        bounds.top + (bounds.height - draw_height) / 2.0,
+        draw_width,
+        draw_height,
+    )
+}
+
+/// 0 columns for a single row
+pub fn grid(bounds: Rect, count: usize, columns: usize) -> Vec<Rect> {
+    if count == 0 {
+        return Vec::new();
+    }
+    let columns = if columns == 0 { count } else { columns.min(count) };
+    let rows = count.div_ceil(columns);
+    let cell_width = bounds.width / columns as f32;
+    let cell_height = bounds.height / rows as f32;
+    (0..count)
+        .map(|i| {
+            Rect::new(
+                bounds.left + (i % columns) as f32 * cell_width,
+                bounds.top + (i / columns) as f32 * cell_height,
+                cell_width,
+                cell_height,
+            )
+        })
+        .collect()
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Start,
+    Center,
+    #[default]
+    End,
+}
+
+impl Align {
+    pub fn offset(self, size: f32, room: f32) -> f32 {
+        match self {
+            Align::Start => 0.0,
+            Align::Center => (room - size) / 2.0,
+            Align::End => room - size,
+        }
+    }
+}
+
+pub fn align(width: f32, height: f32, bounds: Rect, horizontal: Align, vertical: Align) -> Rect {
+    Rect::new(
+        bounds.left + horizontal.offset(width, bounds.width),
+        bounds.top + vertical.offset(height, bounds.height),
+        width,
+        height,
+    )
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Left,
+    #[default]
+    Right,
+    Top,
+    Bottom,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OverlayRegion {
+    pub side: Side,
+    /// Share of the window width or height
+    pub size: f32,
+}
+
+impl Default for OverlayRegion {
+    fn default() -> Self {
+        Self { side: Side::Right, size: 0.3 }
+    }
+}
+
+pub fn split(window: Rect, region: OverlayRegion) -> (Rect, Rect) {
+    let size = region.size.clamp(0.0, 1.0);
+    let (w, h) = (window.width * size, window.height * size);
+    match region.side {
+        Side::Left => (
+            Rect::new(window.left + w, window.top, window.width - w, window.height),
+            Rect::new(window.left, window.top, w, window.height),
+        ),
+        Side::Right => (
+            Rect::new(window.left, window.top, window.width - w, window.height),
+            Rect::new(window.right() - w, window.top, w, window.height),
+        ),
+        Side::Top => (
+            Rect::new(window.left, window.top + h, window.width, window.height - h),
+            Rect::new(window.left, window.top, window.width, h),
+        ),
+        Side::Bottom => (
+            Rect::new(window.left, window.top, window.width, window.height - h),
+            Rect::new(window.left, window.bottom() - h, window.width, h),
+        ),
+    }
+}
+
+/// `min` on the bottom edge, an empty range halfway up
+pub fn sparkline_points(values: &[f32], min: f32, max: f32, bounds: Rect) -> Vec<(f32, f32)> {
+    let step = if values.len() > 1 { bounds.width / (values.len() - 1) as f32 } else { 0.0 };
+    values
+        .iter()
+        .enumerate()
+        .map(|(i, value)| {
+            let level = if max > min { ((value - min) / (max - min)).clamp(0.0, 1.0) } else { 0.5 };
+            (bounds.left + i as f32 * step, bounds.bottom() - level * bounds.height)
+        })
+        .collect()
+}
+
+/// Above `anchor` on screen, or below when there is no room above
+pub fn beside(width: f32, height: f32, anchor: Rect, horizontal: Align, gap: f32) -> (f32, f32) {
+    let x = (anchor.left + horizontal.offset(width, anchor.width)).max(0.0);
+    let above = anchor.top - gap - height;
+    (x, if above >= 0.0 { above } else { anchor.bottom() + gap })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn popup_beside_anchor() {
+        // cat on a taskbar at the bottom of the screen
+        let anchor = Rect::new(1500.0, 1040.0, 120.0, 40.0);
+        assert_eq!(beside(100.0, 50.0, anchor, Align::End, 4.0), (1520.0, 986.0));
+        assert_eq!(beside(100.0, 50.0, anchor, Align::Start, 4.0), (1500.0, 986.0));
+        assert_eq!(beside(100.0, 50.0, anchor, Align::Center, 4.0), (1510.0, 986.0));
+        // taskbar at the top
+        let anchor = Rect::new(50.0, 0.0, 120.0, 40.0);
+        assert_eq!(beside(200.0, 50.0, anchor, Align::End, 4.0), (0.0, 44.0));
+    }
+}
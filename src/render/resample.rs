@@ -0,0 +1,183 @@
+use super::image::Image;
+
+/// Run in linear light on premultiplied alpha, so downscaled frames keep their brightness
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kernel {
+    /// Sharpest, with slight ringing on hard edges
+    Lanczos3,
+    /// Mitchell-Netravali with B = C = 1/3, softer without ringing
+    Mitchell,
+}
+
+impl Kernel {
+    fn support(self) -> f32 {
+        match self {
+            Kernel::Lanczos3 => 3.0,
+            Kernel::Mitchell => 2.0,
+        }
+    }
+
+    fn weight(self, x: f32) -> f32 {
+        let x = x.abs();
+        match self {
+            Kernel::Lanczos3 if x < 3.0 => sinc(x) * sinc(x / 3.0),
+            Kernel::Mitchell if x < 1.0 => (7.0 * x * x * x - 12.0 * x * x + 16.0 / 3.0) / 6.0,
+            Kernel::Mitchell if x < 2.0 => (-7.0 / 3.0 * x * x * x + 12.0 * x * x - 20.0 * x + 32.0 / 3.0) / 6.0,
+            _ => 0.0,
+        }
+    }
+}
+
+fn sinc(x: f32) -> f32 {
+    if x == 0.0 {
+        1.0
+    } else {
+        let x = x * std::f32::consts::PI;
+        x.sin() / x
+    }
+}
+
+struct Taps {
+    start: usize,
+    weights: Vec<f32>,
+}
+
+/// The kernel is widened when shrinking so every source pixel counts
+fn taps(kernel: Kernel, source: usize, destination: usize) -> Vec<Taps> {
+    let scale = source as f32 / destination as f32;
+    let stretch = scale.max(1.0);
+    let support = kernel.support() * stretch;
+    (0..destination)
+        .map(|i| {
+            let center = (i as f32 + 0.5) * scale;
+            let start = (center - support).floor().max(0.0) as usize;
+            let end = ((center + support).ceil() as usize).min(source);
+            let mut weights: Vec<f32> = (start..end).map(|j| kernel.weight((j as f32 + 0.5 - center) / stretch)).collect();
+            let sum: f32 = weights.iter().sum();
+            if sum != 0.0 {
+                weights.iter_mut().for_each(|w| *w /= sum);
+            }
+            Taps { start, weights }
+        })
+        .collect()
+}
+
+fn convolve(taps: &Taps, pixel: impl Fn(usize) -> [f32; 4]) -> [f32; 4] {
+    taps.weights.iter().enumerate().fold([0.0; 4], |mut sum, (i, weight)| {
+        let p = pixel(taps.start + i);
+        for c in 0..4 {
+            sum[c] += p[c] * weight;
+        }
+        sum
+    })
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
+
+fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 { c * 12.92 } else { 1.055 * c.powf(1.0 / 2.4) - 0.055 }
+}
+
+pub fn resize(image: &Image, width: usize, height: usize, kernel: Kernel) -> Image {
+    if image.width == 0 || image.height == 0 || width == 0 || height == 0 {
+        return Image::new(width, height);
+    }
+    let to_linear: Vec<f32> = (0..=255).map(|c| srgb_to_linear(c as f32 / 255.0)).collect();
+    let source: Vec<[f32; 4]> = image
+        .pixels
+        .iter()
+        .map(|&[r, g, b, a]| {
+            let a = a as f32 / 255.0;
+            [to_linear[r as usize] * a, to_linear[g as usize] * a, to_linear[b as usize] * a, a]
+        })
+        .collect();
+
+    let columns = taps(kernel, image.width, width);
+    let mut wide = Vec::with_capacity(width * image.height);
+    for y in 0..image.height {
+        let row = &source[y * image.width..(y + 1) * image.width];
+        wide.extend(columns.iter().map(|taps| convolve(taps, |x| row[x])));
+    }
+
+    let rows = taps(kernel, image.height, height);
+    let mut out = Image::new(width, height);
+    for (y, taps) in rows.iter().enumerate() {
+        for x in 0..width {
+            let [r, g, b, a] = convolve(taps, |sy| wide[sy * width + x]);
+            // ringing can overshoot, colour never exceeds its coverage
+            let a = a.clamp(0.0, 1.0);
+            if a == 0.0 {
+                continue;
+            }
+            let channel = |c: f32| (linear_to_srgb((c / a).clamp(0.0, 1.0)) * 255.0).round() as u8;
+            out.set(x, y, [channel(r), channel(g), channel(b), (a * 255.0).round() as u8]);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KERNELS: [Kernel; 2] = [Kernel::Lanczos3, Kernel::Mitchell];
+
+    #[test]
+    fn kernels_sum_to_one() {
+        for offset in [0.0, 0.25, 0.5] {
+            let sum = |kernel: Kernel| (-4..=4).map(|i| kernel.weight(i as f32 + offset)).sum::<f32>();
+            assert!((sum(Kernel::Mitchell) - 1.0).abs() < 1e-4);
+            // Lanczos is only close to a partition of unity
+            assert!((sum(Kernel::Lanczos3) - 1.0).abs() < 0.02);
+        }
+        for kernel in KERNELS {
+            for (source, destination) in [(10, 3), (3, 10), (7, 7)] {
+                for taps in taps(kernel, source, destination) {
+                    assert!((taps.weights.iter().sum::<f32>() - 1.0).abs() < 1e-4);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn checkerboard_keeps_linear_brightness() {
+        let mut image = Image::new(16, 16);
+        for y in 0..16 {
+            for x in 0..16 {
+                image.set(x, y, if (x + y) % 2 == 0 { [255; 4] } else { [0, 0, 0, 255] });
+            }
+        }
+        for kernel in KERNELS {
+            let small = resize(&image, 8, 8, kernel);
+            // half the light is 188 in sRGB, averaging the bytes would give 128
+            for y in 2..6 {
+                for x in 2..6 {
+                    let [r, g, b, a] = small.get(x, y);
+                    assert!([r, g, b].iter().all(|c| c.abs_diff(188) <= 1), "{:?} {:?}", kernel, small.get(x, y));
+                    assert_eq!(a, 255);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn no_dark_fringe() {
+        let mut image = Image::new(8, 8);
+        for y in 2..6 {
+            for x in 2..6 {
+                image.set(x, y, [255, 0, 0, 255]);
+            }
+        }
+        for kernel in KERNELS {
+            for size in [3, 5, 13] {
+                let scaled = resize(&image, size, size, kernel);
+                let edge: Vec<[u8; 4]> = scaled.pixels.iter().copied().filter(|p| p[3] > 0 && p[3] < 255).collect();
+                assert!(!edge.is_empty());
+                // partly covered pixels stay red instead of blending towards the transparent black around
+                assert!(edge.iter().all(|p| p[0] == 255 && p[1] == 0 && p[2] == 0), "{:?} {}: {:?}", kernel, size, edge);
+            }
+        }
+    }
+}
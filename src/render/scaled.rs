@@ -0,0 +1,217 @@
+use std::{collections::HashMap, convert::Infallible};
+
+use super::layout::Rect;
+
+#[derive(Debug)]
+struct Entry<T> {
+    value: T,
+    bytes: usize,
+    used_at: u64,
+}
+
+/// Frames scaled to the pixel size they are drawn at, the frames drawn longest ago go past `budget` bytes
+#[derive(Debug)]
+pub struct ScaledCache<T> {
+    entries: HashMap<(usize, usize, usize), Entry<T>>,
+    budget: usize,
+    used: usize,
+    clock: u64,
+}
+
+impl<T> ScaledCache<T> {
+    pub fn new(budget: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            budget,
+            used: 0,
+            clock: 0,
+        }
+    }
+
+    pub fn set_budget(&mut self, budget: usize) {
+        self.budget = budget;
+        self.evict(0);
+    }
+
+    pub fn used(&self) -> usize {
+        self.used
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get_or_try_insert<E>(&mut self, frame: usize, width: usize, height: usize, make: impl FnOnce() -> Result<T, E>) -> Result<&T, E> {
+        self.clock += 1;
+        let key = (frame, width, height);
+        if !self.entries.contains_key(&key) {
+            let value = make()?;
+            let bytes = width * height * 4;
+            self.evict(bytes);
+            self.used += bytes;
+            self.entries.insert(key, Entry { value, bytes, used_at: 0 });
+        }
+        let entry = self.entries.get_mut(&key).expect("Just inserted");
+        entry.used_at = self.clock;
+        Ok(&entry.value)
+    }
+
+    pub fn get_or_insert_with(&mut self, frame: usize, width: usize, height: usize, make: impl FnOnce() -> T) -> &T {
+        match self.get_or_try_insert(frame, width, height, || Ok::<_, Infallible>(make())) {
+            Ok(value) => value,
+            Err(never) => match never {},
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.used = 0;
+    }
+
+    /// A frame bigger than the budget still gets in alone
+    fn evict(&mut self, extra: usize) {
+        while self.used + extra > self.budget {
+            let Some(key) = self.entries.iter().min_by_key(|(_, entry)| entry.used_at).map(|(key, _)| *key) else {
+                break;
+            };
+            let entry = self.entries.remove(&key).expect("Key was just found");
+            self.used -= entry.bytes;
+        }
+    }
+}
+
+impl<T> Default for ScaledCache<T> {
+    /// A few hundred taskbar sized frames
+    fn default() -> Self {
+        Self::new(64 << 20)
+    }
+}
+
+/// `dest` moved onto whole pixels at `scale` pixels per unit, and its size in pixels
+pub fn snap(dest: Rect, scale: f32) -> (Rect, usize, usize) {
+    let pixels = Rect::new(dest.left * scale, dest.top * scale, dest.width * scale, dest.height * scale).snapped();
+    let (width, height) = (pixels.width.max(0.0) as usize, pixels.height.max(0.0) as usize);
+    (
+        Rect::new(pixels.left / scale, pixels.top / scale, width as f32 / scale, height as f32 / scale),
+        width,
+        height,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 10x10 frames take 400 bytes each
+    fn fill(cache: &mut ScaledCache<usize>, frames: &[usize]) {
+        for &frame in frames {
+            cache.get_or_insert_with(frame, 10, 10, || frame);
+        }
+    }
+
+    #[test]
+    fn used_counts_four_bytes_per_pixel() {
+        let mut cache = ScaledCache::new(1 << 20);
+        cache.get_or_insert_with(0, 10, 10, || 0);
+        cache.get_or_insert_with(1, 20, 5, || 1);
+        assert_eq!(cache.used(), 800);
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn stays_within_budget() {
+        let mut cache = ScaledCache::new(1000);
+        fill(&mut cache, &[0, 1, 2, 3, 4]);
+        assert_eq!(cache.len(), 2);
+        assert!(cache.used() <= 1000);
+    }
+
+    #[test]
+    fn evicts_least_recently_drawn() {
+        let mut cache = ScaledCache::new(1200);
+        fill(&mut cache, &[0, 1, 2]);
+        // drawing 0 again makes 1 the oldest
+        fill(&mut cache, &[0, 3]);
+        let mut made = Vec::new();
+        for frame in [0, 2, 3, 1] {
+            cache.get_or_insert_with(frame, 10, 10, || {
+                made.push(frame);
+                frame
+            });
+        }
+        // 1 was the one evicted, bringing it back pushed out 0 which was drawn longest ago by then
+        assert_eq!(made, [1]);
+        assert_eq!(cache.len(), 3);
+        assert_eq!(*cache.get_or_insert_with(0, 10, 10, || 100), 100);
+    }
+
+    #[test]
+    fn oversized_frame_gets_in_alone() {
+        let mut cache = ScaledCache::new(1000);
+        fill(&mut cache, &[0, 1]);
+        cache.get_or_insert_with(2, 100, 100, || 2);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.used(), 40000);
+    }
+
+    #[test]
+    fn shrinking_budget_evicts() {
+        let mut cache = ScaledCache::new(1 << 20);
+        fill(&mut cache, &[0, 1, 2, 3]);
+        cache.set_budget(800);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.used(), 800);
+        // the two drawn last are kept
+        let mut made = false;
+        cache.get_or_insert_with(3, 10, 10, || {
+            made = true;
+            3
+        });
+        assert!(!made);
+    }
+
+    #[test]
+    fn clear_resets_usage() {
+        let mut cache = ScaledCache::new(1 << 20);
+        fill(&mut cache, &[0, 1]);
+        cache.clear();
+        assert!(cache.is_empty());
+        assert_eq!(cache.used(), 0);
+    }
+
+    #[test]
+    fn scale_change_misses_the_cache() {
+        let dest = Rect::new(0.0, 0.0, 30.0, 20.0);
+        let (_, width, height) = snap(dest, 1.0);
+        let (_, wide, high) = snap(dest, 2.0);
+        assert_eq!((width, height), (30, 20));
+        assert_eq!((wide, high), (60, 40));
+
+        let mut cache = ScaledCache::new(1 << 20);
+        cache.get_or_insert_with(0, width, height, || 1);
+        assert_eq!(*cache.get_or_insert_with(0, wide, high, || 2), 2);
+        assert_eq!(*cache.get_or_insert_with(0, width, height, || 3), 1);
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn failed_make_leaves_cache_untouched() {
+        let mut cache = ScaledCache::<usize>::new(400);
+        fill(&mut cache, &[0]);
+        assert!(cache.get_or_try_insert(1, 10, 10, || Err(())).is_err());
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.used(), 400);
+    }
+
+    #[test]
+    fn snap_keeps_fractional_rects_on_pixels() {
+        let (rect, width, height) = snap(Rect::new(0.3, 0.6, 10.4, 5.2), 1.5);
+        assert_eq!((width, height), (16, 8));
+        assert_eq!(rect.left * 1.5, (0.3f32 * 1.5).round());
+        assert_eq!(rect.width * 1.5, 16.0);
+    }
+}
@@ -0,0 +1,431 @@
+use std::{
+    cell::{Ref, RefCell},
+    fs::File,
+    io::BufReader,
+};
+
+use super::{
+    Bubble, Render, Sparkline, Text, Tint,
+    color::{ColorTransform, TransformCache},
+    image::{Image, decode_gif},
+    layout::{self, Rect},
+    resample,
+    scaled::{self, ScaledCache},
+    text,
+    transform::{Filter, FrameTransform},
+};
+use crate::{AppError, animation::AnimationInfo};
+
+/// In-memory RGBA canvas, for tests and as the reference for the GPU path
+#[derive(Debug)]
+pub struct SoftwareRender {
+    frames: Vec<Image>,
+    canvas: RefCell<Image>,
+    /// Window DPI over 96
+    scale: f32,
+    tint: Option<Tint>,
+    sparkline: Option<Sparkline>,
+    text: Option<(Text, Image)>,
+    bubble: Option<(Bubble, Image)>,
+    recolor: Option<ColorTransform>,
+    recolored: TransformCache<Image>,
+    transform: FrameTransform,
+    filters: Vec<Filter>,
+    scaled: RefCell<ScaledCache<Image>>,
+}
+
+impl SoftwareRender {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            frames: Vec::new(),
+            canvas: RefCell::new(Image::new(width, height)),
+            scale: 1.0,
+            tint: None,
+            sparkline: None,
+            text: None,
+            bubble: None,
+            recolor: None,
+            recolored: TransformCache::default(),
+            transform: FrameTransform::default(),
+            filters: Vec::new(),
+            scaled: RefCell::default(),
+        }
+    }
+
+    pub fn set_cache_budget(&mut self, budget: usize) {
+        self.scaled.get_mut().set_budget(budget);
+    }
+
+    pub fn set_scale(&mut self, scale: f32) {
+        self.scale = scale;
+    }
+
+    pub fn push_frame(&mut self, frame: Image) -> usize {
+        // only the current transform's frames are extended, the others would be one short
+        match &self.recolor {
+            Some(transform) => {
+                if let Some(frames) = self.recolored.only(transform) {
+                    frames.push(transform.apply(&frame));
+                }
+            }
+            None => self.recolored.clear(),
+        }
+        self.frames.push(frame);
+        self.filters.push(self.transform.filter);
+        self.recolor_frames();
+        self.frames.len() - 1
+    }
+
+    fn recolor_frames(&mut self) {
+        if let Some(transform) = &self.recolor {
+            let frames = &self.frames;
+            self.recolored.get_or_insert_with(transform, || frames.iter().map(|frame| transform.apply(frame)).collect());
+        }
+    }
+
+    pub fn canvas(&self) -> Ref<'_, Image> {
+        self.canvas.borrow()
+    }
+
+    pub fn resize(&mut self, width: usize, height: usize) {
+        self.canvas = RefCell::new(Image::new(width, height));
+        self.scaled.get_mut().clear();
+    }
+}
+
+/// Kernel filters are resampled beforehand, here they fall back to bilinear
+fn draw_image(canvas: &mut Image, image: &Image, dest: Rect, filter: Filter, tint: Option<Tint>, opacity: f32) {
+    if image.width == 0 || image.height == 0 || dest.width <= 0.0 || dest.height <= 0.0 {
+        return;
+    }
+    let tint = tint.map(|t| ([t.r, t.g, t.b].map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8), t.strength.clamp(0.0, 1.0)));
+    let (x0, x1) = (dest.left.round().max(0.0) as usize, (dest.right().round().max(0.0) as usize).min(canvas.width));
+    let (y0, y1) = (dest.top.round().max(0.0) as usize, (dest.bottom().round().max(0.0) as usize).min(canvas.height));
+    for y in y0..y1 {
+        let sy = (y as f32 + 0.5 - dest.top) / dest.height * image.height as f32;
+        for x in x0..x1 {
+            let sx = (x as f32 + 0.5 - dest.left) / dest.width * image.width as f32;
+            let [r, g, b, a] = match filter {
+                Filter::Nearest => image.get((sx as usize).min(image.width - 1), (sy as usize).min(image.height - 1)),
+                Filter::Linear | Filter::Lanczos3 | Filter::Mitchell => bilinear(image, sx - 0.5, sy - 0.5),
+            };
+            let pixel = [r, g, b, (a as f32 * opacity.clamp(0.0, 1.0)).round() as u8];
+            canvas.blend(x, y, pixel);
+            // like the GPU path, the tint covers the frame's opaque pixels at `strength`
+            if let Some(([r, g, b], strength)) = tint {
+                canvas.blend(x, y, [r, g, b, (pixel[3] as f32 * strength).round() as u8]);
+            }
+        }
+    }
+}
+
+fn prescale(image: &Image, width: usize, height: usize, filter: Filter) -> Image {
+    match filter.kernel() {
+        Some(kernel) => resample::resize(image, width, height, kernel),
+        None => {
+            let mut scaled = Image::new(width, height);
+            draw_image(&mut scaled, image, Rect::new(0.0, 0.0, width as f32, height as f32), filter, None, 1.0);
+            scaled
+        }
+    }
+}
+
+/// Colours weighted by alpha so transparent neighbours don't darken edges
+fn bilinear(image: &Image, x: f32, y: f32) -> [u8; 4] {
+    let clamp = |v: f32, len: usize| v.clamp(0.0, (len - 1) as f32);
+    let (x, y) = (clamp(x, image.width), clamp(y, image.height));
+    let (x0, y0) = (x as usize, y as usize);
+    let (x1, y1) = ((x0 + 1).min(image.width - 1), (y0 + 1).min(image.height - 1));
+    let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+    let mut sum = [0.0f32; 4];
+    for (px, py, weight) in [
+        (x0, y0, (1.0 - fx) * (1.0 - fy)),
+        (x1, y0, fx * (1.0 - fy)),
+        (x0, y1, (1.0 - fx) * fy),
+        (x1, y1, fx * fy),
+    ] {
+        let [r, g, b, a] = image.get(px, py).map(|c| c as f32);
+        let alpha = a * weight;
+        sum = [sum[0] + r * alpha, sum[1] + g * alpha, sum[2] + b * alpha, sum[3] + alpha];
+    }
+    if sum[3] <= 0.0 {
+        return [0; 4];
+    }
+    [sum[0] / sum[3], sum[1] / sum[3], sum[2] / sum[3], sum[3]].map(|c| c.round().clamp(0.0, 255.0) as u8)
+}
+
+fn draw_line(canvas: &mut Image, from: (f32, f32), to: (f32, f32), pixel: [u8; 4]) {
+    if canvas.width == 0 || canvas.height == 0 {
+        return;
+    }
+    let steps = (to.0 - from.0).abs().max((to.1 - from.1).abs()).ceil().max(1.0) as usize;
+    let mut last = None;
+    for i in 0..=steps {
+        let t = i as f32 / steps as f32;
+        let x = ((from.0 + (to.0 - from.0) * t).max(0.0) as usize).min(canvas.width - 1);
+        let y = ((from.1 + (to.1 - from.1) * t).max(0.0) as usize).min(canvas.height - 1);
+        // translucent colours would darken where steps land on the same pixel twice
+        if last.replace((x, y)) != Some((x, y)) {
+            canvas.blend(x, y, pixel);
+        }
+    }
+}
+
+impl Render for SoftwareRender {
+    fn load_src_data(&mut self, path: &str) -> Result<AnimationInfo, AppError> {
+        let info = AnimationInfo::from_gif(path, self.frames.len())?;
+        self.frames.extend(self.transform.apply(decode_gif(BufReader::new(File::open(path)?))?));
+        self.filters.resize(self.frames.len(), self.transform.filter);
+        self.recolored.clear();
+        self.recolor_frames();
+        Ok(info)
+    }
+
+    fn render_frame(&self, frame: usize) -> Result<(), AppError> {
+        self.render_grid(&[frame], 1)
+    }
+
+    fn render_grid(&self, frames: &[usize], columns: usize) -> Result<(), AppError> {
+        let mut canvas = self.canvas.borrow_mut();
+        canvas.fill([0; 4]);
+        let window = Rect::new(0.0, 0.0, canvas.width as f32, canvas.height as f32);
+        let (area, strip) = match &self.sparkline {
+            Some(sparkline) => {
+                let (area, strip) = layout::split(window, sparkline.region);
+                (area, Some(strip))
+            }
+            None => (window, None),
+        };
+
+        let mut cache = self.scaled.borrow_mut();
+        let images = self.recolor.as_ref().and_then(|transform| self.recolored.get(transform)).unwrap_or(&self.frames);
+        for (cell, frame) in layout::grid(area, frames.len(), columns).into_iter().zip(frames) {
+            let Some(image) = images.get(*frame) else {
+                continue;
+            };
+            // drawn one to one from the frame scaled to the whole pixels it covers
+            let (dest, width, height) = scaled::snap(layout::aspect_fit(image.width as f32, image.height as f32, cell), 1.0);
+            let image = cache.get_or_insert_with(*frame, width, height, || prescale(image, width, height, self.filters[*frame]));
+            draw_image(&mut canvas, image, dest, Filter::Nearest, self.tint, 1.0);
+        }
+
+        if let (Some(sparkline), Some(strip)) = (&self.sparkline, strip) {
+            let points = layout::sparkline_points(&sparkline.values, sparkline.min, sparkline.max, strip);
+            let pixel = sparkline.color.to_rgba8();
+            match points.as_slice() {
+                [point] => draw_line(&mut canvas, *point, *point, pixel),
+                points => {
+                    for pair in points.windows(2) {
+                        draw_line(&mut canvas, pair[0], pair[1], pixel);
+                    }
+                }
+            }
+        }
+
+        if let Some((text, image)) = &self.text {
+            let dest = layout::align(image.width as f32, image.height as f32, window, text.style.horizontal, text.style.vertical);
+            draw_image(&mut canvas, image, dest, Filter::Nearest, None, 1.0);
+        }
+        if let Some((bubble, image)) = &self.bubble {
+            let dest = layout::align(image.width as f32, image.height as f32, window, bubble.style.horizontal, bubble.style.vertical);
+            draw_image(&mut canvas, image, dest, Filter::Nearest, None, bubble.opacity);
+        }
+        Ok(())
+    }
+
+    fn set_tint(&mut self, tint: Option<Tint>) -> Result<(), AppError> {
+        self.tint = tint;
+        Ok(())
+    }
+
+    fn set_sparkline(&mut self, sparkline: Option<Sparkline>) -> Result<(), AppError> {
+        self.sparkline = sparkline;
+        Ok(())
+    }
+
+    fn set_text(&mut self, text: Option<Text>) -> Result<(), AppError> {
+        self.text = text.map(|text| {
+            let image = text::rasterize(&text, self.scale);
+            (text, image)
+        });
+        Ok(())
+    }
+
+    fn set_bubble(&mut self, bubble: Option<Bubble>) -> Result<(), AppError> {
+        self.bubble = bubble.map(|bubble| {
+            // fade steps only change the opacity, the rasterised box is kept
+            let image = match self.bubble.take() {
+                Some((old, image)) if old.text == bubble.text && old.style == bubble.style => image,
+                _ => text::rasterize_bubble(&bubble, self.scale),
+            };
+            (bubble, image)
+        });
+        Ok(())
+    }
+
+    fn set_recolor(&mut self, transform: Option<ColorTransform>) -> Result<(), AppError> {
+        self.recolor = transform;
+        self.recolor_frames();
+        self.scaled.get_mut().clear();
+        Ok(())
+    }
+
+    fn set_frame_transform(&mut self, transform: FrameTransform) -> Result<(), AppError> {
+        self.transform = transform;
+        Ok(())
+    }
+
+    fn set_window_size(&mut self, width: u32, height: u32) -> Result<(), AppError> {
+        self.resize(width as usize, height as usize);
+        Ok(())
+    }
+
+    fn set_dpi(&mut self, dpi: u32) -> Result<(), AppError> {
+        self.set_scale(dpi as f32 / 96.0);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::{
+        Color,
+        layout::{OverlayRegion, Side},
+    };
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+    const CLEAR: [u8; 4] = [0; 4];
+
+    fn solid(width: usize, height: usize, pixel: [u8; 4]) -> Image {
+        let mut image = Image::new(width, height);
+        image.fill(pixel);
+        image
+    }
+
+    fn rows(render: &SoftwareRender) -> Vec<Vec<[u8; 4]>> {
+        let canvas = render.canvas();
+        canvas.pixels.chunks(canvas.width).map(<[_]>::to_vec).collect()
+    }
+
+    fn nearest(render: &mut SoftwareRender) {
+        render
+            .set_frame_transform(FrameTransform {
+                filter: Filter::Nearest,
+                ..Default::default()
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn scales_to_fit() {
+        let mut render = SoftwareRender::new(4, 4);
+        // wide frame, letterboxed top and bottom
+        let frame = render.push_frame(solid(2, 1, RED));
+        render.render_frame(frame).unwrap();
+        assert_eq!(rows(&render), [vec![CLEAR; 4], vec![RED; 4], vec![RED; 4], vec![CLEAR; 4]]);
+        assert!(render.render_frame(frame + 1).is_ok());
+        assert_eq!(rows(&render), vec![vec![CLEAR; 4]; 4]);
+    }
+
+    #[test]
+    fn nearest_keeps_pixels_crisp() {
+        let mut render = SoftwareRender::new(4, 4);
+        nearest(&mut render);
+        let mut checker = solid(2, 2, RED);
+        checker.set(1, 0, BLUE);
+        checker.set(0, 1, BLUE);
+        let frame = render.push_frame(checker);
+        render.render_frame(frame).unwrap();
+        let (r, b) = (vec![RED, RED, BLUE, BLUE], vec![BLUE, BLUE, RED, RED]);
+        assert_eq!(rows(&render), [r.clone(), r, b.clone(), b]);
+    }
+
+    #[test]
+    fn grid() {
+        let mut render = SoftwareRender::new(4, 2);
+        let red = render.push_frame(solid(1, 1, RED));
+        let blue = render.push_frame(solid(1, 1, BLUE));
+        render.render_grid(&[red, blue], 0).unwrap();
+        assert_eq!(rows(&render), vec![vec![RED, RED, BLUE, BLUE]; 2]);
+        render.resize(2, 4);
+        render.render_grid(&[blue, red], 1).unwrap();
+        assert_eq!(rows(&render), [vec![BLUE; 2], vec![BLUE; 2], vec![RED; 2], vec![RED; 2]]);
+    }
+
+    #[test]
+    fn tint_and_recolor() {
+        let mut render = SoftwareRender::new(2, 1);
+        nearest(&mut render);
+        let mut frame = solid(2, 1, RED);
+        frame.set(1, 0, CLEAR);
+        let frame = render.push_frame(frame);
+        let white = Tint {
+            r: 1.0,
+            g: 1.0,
+            b: 1.0,
+            strength: 1.0,
+        };
+        render.set_tint(Some(white)).unwrap();
+        render.render_frame(frame).unwrap();
+        // transparent pixels stay so
+        assert_eq!(render.canvas().get(0, 0), [255; 4]);
+        assert_eq!(render.canvas().get(1, 0)[3], 0);
+
+        render.set_tint(None).unwrap();
+        render.set_recolor(Some(ColorTransform::Palette(vec![([255, 0, 0], [0, 0, 255])]))).unwrap();
+        render.render_frame(frame).unwrap();
+        assert_eq!(rows(&render), [[BLUE, CLEAR]]);
+        // frames pushed later are recoloured too
+        let second = render.push_frame(solid(1, 1, RED));
+        render.render_frame(second).unwrap();
+        assert_eq!(rows(&render), [[CLEAR, BLUE]]);
+        render.set_recolor(None).unwrap();
+        render.render_frame(frame).unwrap();
+        assert_eq!(rows(&render), [[RED, CLEAR]]);
+    }
+
+    #[test]
+    fn push_while_recoloured() {
+        let mut render = SoftwareRender::new(1, 1);
+        let (to_blue, to_green) = (
+            ColorTransform::Palette(vec![([255, 0, 0], [0, 0, 255])]),
+            ColorTransform::Palette(vec![([255, 0, 0], [0, 255, 0])]),
+        );
+        render.set_recolor(Some(to_blue.clone())).unwrap();
+        let first = render.push_frame(solid(1, 1, RED));
+        render.set_recolor(Some(to_green)).unwrap();
+        let second = render.push_frame(solid(1, 1, RED));
+        // the blue frames cached before the push are one short and made again
+        render.set_recolor(Some(to_blue)).unwrap();
+        for frame in [first, second] {
+            render.render_frame(frame).unwrap();
+            assert_eq!(rows(&render), [[BLUE]]);
+        }
+    }
+
+    #[test]
+    fn sparkline_strip() {
+        let mut render = SoftwareRender::new(4, 3);
+        let frame = render.push_frame(solid(1, 1, RED));
+        render
+            .set_sparkline(Some(Sparkline {
+                values: vec![0.0, 0.0],
+                min: 0.0,
+                max: 1.0,
+                color: Color {
+                    r: 0.0,
+                    g: 1.0,
+                    b: 0.0,
+                    a: 1.0,
+                },
+                region: OverlayRegion { side: Side::Right, size: 0.25 },
+            }))
+            .unwrap();
+        render.render_frame(frame).unwrap();
+        let green = [0, 255, 0, 255];
+        // animation squeezed into the left 3 columns, flat line along the bottom of the strip
+        assert_eq!(rows(&render), [[RED, RED, RED, CLEAR], [RED, RED, RED, CLEAR], [RED, RED, RED, green]]);
+    }
+}
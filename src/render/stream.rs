@@ -0,0 +1,339 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc, Mutex, RwLock,
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, Sender},
+    },
+    thread,
+};
+
+use super::{color::ColorTransform, image::Image};
+
+/// Palette and indices, runs when over 256 colours, or raw when runs don't pay
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PackedFrame {
+    Palette {
+        width: usize,
+        height: usize,
+        palette: Vec<[u8; 4]>,
+        indices: Vec<u8>,
+    },
+    Runs {
+        width: usize,
+        height: usize,
+        runs: Vec<(u32, [u8; 4])>,
+    },
+    Raw(Image),
+}
+
+impl PackedFrame {
+    pub fn pack(image: &Image) -> Self {
+        let mut palette = Vec::new();
+        let mut lookup = HashMap::new();
+        let mut indices = Vec::with_capacity(image.pixels.len());
+        for pixel in &image.pixels {
+            let index = match lookup.get(pixel) {
+                Some(index) => *index,
+                None if palette.len() == 256 => return Self::runs(image),
+                None => {
+                    lookup.insert(*pixel, palette.len() as u8);
+                    palette.push(*pixel);
+                    (palette.len() - 1) as u8
+                }
+            };
+            indices.push(index);
+        }
+        PackedFrame::Palette {
+            width: image.width,
+            height: image.height,
+            palette,
+            indices,
+        }
+    }
+
+    fn runs(image: &Image) -> Self {
+        let mut runs: Vec<(u32, [u8; 4])> = Vec::new();
+        for pixel in &image.pixels {
+            match runs.last_mut() {
+                Some((count, last)) if last == pixel => *count += 1,
+                _ => runs.push((1, *pixel)),
+            }
+        }
+        // a run takes 8 bytes, a pixel 4
+        if runs.len() * 8 >= image.pixels.len() * 4 {
+            return PackedFrame::Raw(image.clone());
+        }
+        PackedFrame::Runs {
+            width: image.width,
+            height: image.height,
+            runs,
+        }
+    }
+
+    pub fn unpack(&self) -> Image {
+        let (width, height) = self.size();
+        let pixels = match self {
+            PackedFrame::Palette { palette, indices, .. } => indices.iter().map(|index| palette[*index as usize]).collect(),
+            PackedFrame::Runs { runs, .. } => runs.iter().flat_map(|(count, pixel)| std::iter::repeat_n(*pixel, *count as usize)).collect(),
+            PackedFrame::Raw(image) => return image.clone(),
+        };
+        Image { width, height, pixels }
+    }
+
+    pub fn size(&self) -> (usize, usize) {
+        match self {
+            PackedFrame::Palette { width, height, .. } | PackedFrame::Runs { width, height, .. } => (*width, *height),
+            PackedFrame::Raw(image) => (image.width, image.height),
+        }
+    }
+
+    pub fn bytes(&self) -> usize {
+        match self {
+            PackedFrame::Palette { palette, indices, .. } => palette.len() * 4 + indices.len(),
+            PackedFrame::Runs { runs, .. } => runs.len() * 8,
+            PackedFrame::Raw(image) => image.pixels.len() * 4,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Decoded {
+    frames: HashMap<usize, Arc<Image>>,
+    bytes: usize,
+    /// The other frames go by how far ahead of it they are, wrapping round
+    playhead: usize,
+    recolor: Option<ColorTransform>,
+}
+
+impl Decoded {
+    /// Frames further ahead are dropped to make room, the playhead always fits
+    fn insert(&mut self, index: usize, image: Arc<Image>, budget: usize, len: usize) -> bool {
+        let bytes = image.pixels.len() * 4;
+        let playhead = self.playhead;
+        let ahead = |i: usize| (i + len - playhead % len) % len;
+        while self.bytes + bytes > budget {
+            let Some(victim) = self.frames.keys().copied().filter(|i| *i != playhead).max_by_key(|i| ahead(*i)) else {
+                break;
+            };
+            if ahead(victim) <= ahead(index) {
+                return false;
+            }
+            if let Some(dropped) = self.frames.remove(&victim) {
+                self.bytes -= dropped.pixels.len() * 4;
+            }
+        }
+        if self.bytes + bytes > budget && index != playhead {
+            return false;
+        }
+        if let Some(old) = self.frames.insert(index, image) {
+            self.bytes -= old.pixels.len() * 4;
+        }
+        self.bytes += bytes;
+        true
+    }
+}
+
+#[derive(Debug)]
+struct Shared {
+    packed: RwLock<Vec<PackedFrame>>,
+    packed_bytes: AtomicUsize,
+    decoded: Mutex<Decoded>,
+    cap: usize,
+}
+
+impl Shared {
+    fn unpack(&self, index: usize) -> Option<(Arc<Image>, usize, Option<ColorTransform>)> {
+        let recolor = self.decoded.lock().unwrap().recolor.clone();
+        let packed = self.packed.read().unwrap();
+        let image = packed.get(index)?.unpack();
+        let image = match &recolor {
+            Some(transform) => transform.apply(&image),
+            None => image,
+        };
+        Some((Arc::new(image), packed.len(), recolor))
+    }
+
+    /// Dropped if the transform changed while it was unpacked
+    fn insert(&self, index: usize, image: Arc<Image>, len: usize, recolor: &Option<ColorTransform>) -> bool {
+        let mut decoded = self.decoded.lock().unwrap();
+        decoded.recolor == *recolor && decoded.insert(index, image, self.budget(), len)
+    }
+
+    fn budget(&self) -> usize {
+        self.cap.saturating_sub(self.packed_bytes.load(Ordering::Relaxed))
+    }
+}
+
+/// Packed frames with a window of them unpacked ahead of the playhead by a worker thread
+#[derive(Debug)]
+pub struct FrameStream {
+    shared: Arc<Shared>,
+    playhead: Sender<usize>,
+}
+
+impl FrameStream {
+    pub fn new(cap: usize, prefetch: usize) -> Self {
+        let shared = Arc::new(Shared {
+            packed: RwLock::new(Vec::new()),
+            packed_bytes: AtomicUsize::new(0),
+            decoded: Mutex::new(Decoded::default()),
+            cap,
+        });
+        let (sender, receiver) = mpsc::channel::<usize>();
+        let worker = shared.clone();
+        thread::Builder::new()
+            .name("frame_stream_thread".to_string())
+            .spawn(move || {
+                // ends with the stream, once the sender is dropped
+                while let Ok(mut playhead) = receiver.recv() {
+                    // only the latest frame drawn matters
+                    while let Ok(next) = receiver.try_recv() {
+                        playhead = next;
+                    }
+                    for index in playhead + 1..=playhead + prefetch {
+                        let len = worker.packed.read().unwrap().len();
+                        let index = index % len.max(1);
+                        if index == playhead || worker.decoded.lock().unwrap().frames.contains_key(&index) {
+                            continue;
+                        }
+                        let Some((image, len, recolor)) = worker.unpack(index) else {
+                            break;
+                        };
+                        // the window is full of frames needed sooner
+                        if !worker.insert(index, image, len, &recolor) {
+                            break;
+                        }
+                    }
+                }
+            })
+            .expect("Failed to spawn frame stream thread");
+        Self { shared, playhead: sender }
+    }
+
+    pub fn push(&self, image: &Image) -> usize {
+        let packed = PackedFrame::pack(image);
+        self.shared.packed_bytes.fetch_add(packed.bytes(), Ordering::Relaxed);
+        let mut frames = self.shared.packed.write().unwrap();
+        frames.push(packed);
+        frames.len() - 1
+    }
+
+    pub fn len(&self) -> usize {
+        self.shared.packed.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn size(&self, index: usize) -> Option<(usize, usize)> {
+        self.shared.packed.read().unwrap().get(index).map(PackedFrame::size)
+    }
+
+    /// Packed and unpacked
+    pub fn bytes(&self) -> (usize, usize) {
+        (self.shared.packed_bytes.load(Ordering::Relaxed), self.shared.decoded.lock().unwrap().bytes)
+    }
+
+    pub fn set_recolor(&self, transform: Option<ColorTransform>) {
+        let mut decoded = self.shared.decoded.lock().unwrap();
+        if decoded.recolor != transform {
+            decoded.recolor = transform;
+            decoded.frames.clear();
+            decoded.bytes = 0;
+        }
+    }
+
+    /// Unpacked now unless the worker got to it first
+    pub fn frame(&self, index: usize) -> Option<Arc<Image>> {
+        let ready = {
+            let mut decoded = self.shared.decoded.lock().unwrap();
+            decoded.playhead = index;
+            decoded.frames.get(&index).cloned()
+        };
+        let image = match ready {
+            Some(image) => image,
+            None => {
+                let (image, len, recolor) = self.shared.unpack(index)?;
+                self.shared.insert(index, image.clone(), len, &recolor);
+                image
+            }
+        };
+        let _ = self.playhead.send(index);
+        Some(image)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(width: usize, height: usize, pixel: impl Fn(usize) -> [u8; 4]) -> Image {
+        Image {
+            width,
+            height,
+            pixels: (0..width * height).map(pixel).collect(),
+        }
+    }
+
+    #[test]
+    fn pack_round_trip() {
+        let few = image(16, 16, |i| [(i % 3) as u8, 0, 0, 255]);
+        let packed = PackedFrame::pack(&few);
+        assert!(matches!(packed, PackedFrame::Palette { .. }));
+        assert_eq!((packed.unpack(), packed.size(), packed.bytes()), (few, (16, 16), 3 * 4 + 256));
+
+        // runs of four pixels over 1024 colours
+        let bands = image(64, 64, |i| [(i / 4) as u8, (i / 1024) as u8, 0, 255]);
+        let packed = PackedFrame::pack(&bands);
+        assert!(matches!(packed, PackedFrame::Runs { .. }));
+        assert_eq!((packed.unpack(), packed.bytes()), (bands, 1024 * 8));
+
+        // every pixel its own run would take twice the pixels
+        let noise = image(32, 32, |i| [i as u8, (i >> 8) as u8, 0, 255]);
+        let packed = PackedFrame::pack(&noise);
+        assert_eq!(packed, PackedFrame::Raw(noise.clone()));
+        assert_eq!((packed.unpack(), packed.bytes()), (noise, 32 * 32 * 4));
+    }
+
+    #[test]
+    fn eviction_order() {
+        let pixel = || Arc::new(image(1, 1, |_| [0; 4]));
+        let mut decoded = Decoded::default();
+        // room for three frames out of ten
+        for index in 1..=3 {
+            assert!(decoded.insert(index, pixel(), 12, 10));
+        }
+        // further ahead than every frame held
+        assert!(!decoded.insert(4, pixel(), 12, 10));
+        // the playhead drops the frame furthest ahead
+        assert!(decoded.insert(0, pixel(), 12, 10));
+        assert_eq!(decoded.frames.len(), 3);
+        assert!(!decoded.frames.contains_key(&3));
+
+        // ahead of 8, frame 0 is 2 away and frame 2 is 4 away, wrapping round
+        decoded.playhead = 8;
+        assert!(decoded.insert(9, pixel(), 12, 10));
+        assert!(!decoded.frames.contains_key(&2));
+        assert!(decoded.frames.contains_key(&0) && decoded.frames.contains_key(&1));
+
+        // the playhead fits even past the budget, alone
+        assert!(decoded.insert(8, Arc::new(image(2, 2, |_| [0; 4])), 12, 10));
+        assert_eq!((decoded.frames.len(), decoded.bytes), (1, 16));
+    }
+
+    #[test]
+    fn frames_recoloured() {
+        let stream = FrameStream::new(1 << 20, 2);
+        for shade in 0..4 {
+            stream.push(&image(2, 2, |_| [shade, 0, 0, 255]));
+        }
+        assert_eq!(stream.len(), 4);
+        assert_eq!(stream.frame(1).unwrap().pixels[0], [1, 0, 0, 255]);
+        stream.set_recolor(Some(ColorTransform::Palette(vec![([1, 0, 0], [9, 9, 9])])));
+        assert_eq!(stream.frame(1).unwrap().pixels[0], [9, 9, 9, 255]);
+        assert_eq!(stream.frame(2).unwrap().pixels[0], [2, 0, 0, 255]);
+        assert!(stream.frame(4).is_none());
+    }
+}
@@ -0,0 +1,274 @@
+use std::sync::OnceLock;
+
+use fontdue::{Font, FontSettings};
+
+use super::{Bubble, Color, Text, image::Image, layout::Align};
+
+/// DejaVu Sans Mono Bold, see `resources/font/LICENSE`
+const FONT_DATA: &[u8] = include_bytes!("../../resources/font/DejaVuSansMono-Bold.ttf");
+
+static FONT: OnceLock<Font> = OnceLock::new();
+
+pub fn font() -> &'static Font {
+    FONT.get_or_init(|| Font::from_bytes(FONT_DATA, FontSettings::default()).expect("Bundled font is valid"))
+}
+
+/// `pad` empty pixels around the block
+struct Mask {
+    width: usize,
+    height: usize,
+    coverage: Vec<u8>,
+}
+
+impl Mask {
+    fn get(&self, x: isize, y: isize) -> u8 {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            return 0;
+        }
+        self.coverage[y as usize * self.width + x as usize]
+    }
+
+    fn dilate(&self, radius: isize) -> Mask {
+        let mut coverage = vec![0; self.coverage.len()];
+        for y in 0..self.height as isize {
+            for x in 0..self.width as isize {
+                let mut value = 0;
+                for dy in -radius..=radius {
+                    for dx in -radius..=radius {
+                        if dx * dx + dy * dy <= radius * radius + radius {
+                            value = value.max(self.get(x + dx, y + dy));
+                        }
+                    }
+                }
+                coverage[y as usize * self.width + x as usize] = value;
+            }
+        }
+        Mask { coverage, ..*self }
+    }
+}
+
+fn layout_mask(font: &Font, text: &str, px: f32, align: Align, pad: usize) -> Mask {
+    let line = font.horizontal_line_metrics(px).map_or(px * 1.2, |m| m.new_line_size);
+    let ascent = font.horizontal_line_metrics(px).map_or(px, |m| m.ascent);
+    let lines: Vec<&str> = text.lines().collect();
+    let widths: Vec<f32> = lines.iter().map(|l| l.chars().map(|c| font.metrics(c, px).advance_width).sum()).collect();
+    let block_width = widths.iter().copied().fold(0.0, f32::max);
+
+    let width = block_width.ceil() as usize + pad * 2;
+    let height = (line * lines.len() as f32).ceil() as usize + pad * 2;
+    let mut coverage = vec![0u8; width * height];
+    for (i, (text, line_width)) in lines.iter().zip(&widths).enumerate() {
+        let baseline = pad as f32 + ascent + i as f32 * line;
+        let mut pen = pad as f32 + align.offset(*line_width, block_width);
+        for c in text.chars() {
+            let (metrics, bitmap) = font.rasterize(c, px);
+            let left = (pen + metrics.xmin as f32).round() as isize;
+            let top = (baseline - metrics.ymin as f32 - metrics.height as f32).round() as isize;
+            for (j, value) in bitmap.iter().enumerate() {
+                let (x, y) = (left + (j % metrics.width.max(1)) as isize, top + (j / metrics.width.max(1)) as isize);
+                if x >= 0 && y >= 0 && (x as usize) < width && (y as usize) < height {
+                    let cell = &mut coverage[y as usize * width + x as usize];
+                    *cell = (*cell).max(*value);
+                }
+            }
+            pen += metrics.advance_width;
+        }
+    }
+    Mask { width, height, coverage }
+}
+
+fn paint(image: &mut Image, mask: &Mask, color: Color, offset: isize) {
+    let [r, g, b, a] = color.to_rgba8();
+    for y in 0..image.height {
+        for x in 0..image.width {
+            let coverage = mask.get(x as isize - offset, y as isize - offset);
+            if coverage > 0 {
+                image.blend(x, y, [r, g, b, (a as u16 * coverage as u16 / 255) as u8]);
+            }
+        }
+    }
+}
+
+pub fn rasterize(text: &Text, scale: f32) -> Image {
+    let style = &text.style;
+    let radius = scale.round().max(1.0) as isize;
+    let mask = layout_mask(font(), &text.text, style.size * scale, style.horizontal, radius as usize * 2);
+    let outline = style.outline.map(|_| mask.dilate(radius));
+
+    let mut image = Image::new(mask.width, mask.height);
+    if let Some(shadow) = style.shadow {
+        paint(&mut image, outline.as_ref().unwrap_or(&mask), shadow, radius);
+    }
+    if let (Some(color), Some(outline)) = (style.outline, &outline) {
+        paint(&mut image, outline, color, 0);
+    }
+    paint(&mut image, &mask, style.color, 0);
+    image
+}
+
+fn width(font: &Font, text: &str, px: f32) -> f32 {
+    text.chars().map(|c| font.metrics(c, px).advance_width).sum()
+}
+
+/// Between words where possible
+pub fn wrap(font: &Font, text: &str, px: f32, max_width: f32) -> String {
+    let mut lines = Vec::new();
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        for word in paragraph.split_whitespace() {
+            let candidate = if line.is_empty() { word.to_string() } else { format!("{} {}", line, word) };
+            if width(font, &candidate, px) <= max_width {
+                line = candidate;
+                continue;
+            }
+            if !line.is_empty() {
+                lines.push(std::mem::take(&mut line));
+            }
+            // words longer than a line are split wherever they reach the edge
+            for c in word.chars() {
+                if !line.is_empty() && width(font, &format!("{}{}", line, c), px) > max_width {
+                    lines.push(std::mem::take(&mut line));
+                }
+                line.push(c);
+            }
+        }
+        lines.push(line);
+    }
+    lines.join("\n")
+}
+
+/// `inset` pixels in from the edge
+fn rounded_rect(width: usize, height: usize, radius: f32, inset: f32) -> Mask {
+    let (half_width, half_height) = (width as f32 / 2.0 - inset, height as f32 / 2.0 - inset);
+    let radius = (radius - inset).clamp(0.0, half_width.min(half_height).max(0.0));
+    let mut coverage = vec![0; width * height];
+    for y in 0..height {
+        for x in 0..width {
+            let dx = ((x as f32 + 0.5 - width as f32 / 2.0).abs() - (half_width - radius)).max(0.0);
+            let dy = ((y as f32 + 0.5 - height as f32 / 2.0).abs() - (half_height - radius)).max(0.0);
+            let distance = (dx * dx + dy * dy).sqrt() - radius;
+            coverage[y * width + x] = ((0.5 - distance).clamp(0.0, 1.0) * 255.0).round() as u8;
+        }
+    }
+    Mask { width, height, coverage }
+}
+
+pub fn rasterize_bubble(bubble: &Bubble, scale: f32) -> Image {
+    let style = &bubble.style;
+    let padding = (style.padding * scale).round().max(0.0) as usize;
+    let max_width = (style.max_width * scale - 2.0 * padding as f32).max(1.0);
+    let wrapped = wrap(font(), &bubble.text, style.text.size * scale, max_width);
+    let text = rasterize(
+        &Text {
+            text: wrapped,
+            style: style.text,
+        },
+        scale,
+    );
+
+    let (width, height) = (text.width + 2 * padding, text.height + 2 * padding);
+    let radius = style.corner_radius * scale;
+    let border = scale.round().max(1.0);
+    let mut image = Image::new(width, height);
+    let inner = rounded_rect(width, height, radius, if style.border.is_some() { border } else { 0.0 });
+    if let Some(color) = style.border {
+        let outer = rounded_rect(width, height, radius, 0.0);
+        let ring = outer.coverage.iter().zip(&inner.coverage).map(|(o, i)| o.saturating_sub(*i)).collect();
+        paint(&mut image, &Mask { coverage: ring, ..outer }, color, 0);
+    }
+    paint(&mut image, &inner, style.background, 0);
+    for y in 0..text.height {
+        for x in 0..text.width {
+            image.blend(x + padding, y + padding, text.get(x, y));
+        }
+    }
+    image
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::TextStyle;
+
+    const PX: f32 = 12.0;
+
+    /// Width of `n` characters, the font is monospaced
+    fn chars(n: f32) -> f32 {
+        width(font(), "a", PX) * n
+    }
+
+    #[test]
+    fn wraps_between_words() {
+        assert_eq!(wrap(font(), "one two three", PX, chars(7.5)), "one two\nthree");
+        assert_eq!(wrap(font(), "one two three", PX, chars(100.0)), "one two three");
+    }
+
+    #[test]
+    fn wraps_long_words_and_keeps_newlines() {
+        assert_eq!(wrap(font(), "abcdefghij", PX, chars(4.5)), "abcd\nefgh\nij");
+        assert_eq!(wrap(font(), "a\n\nb c", PX, chars(100.0)), "a\n\nb c");
+        // one character per line at worst
+        assert_eq!(wrap(font(), "ab cd", PX, 0.0), "a\nb\nc\nd");
+    }
+
+    fn text(text: &str, outline: bool) -> Text {
+        let mut style = TextStyle::default();
+        if !outline {
+            style.outline = None;
+        }
+        Text { text: text.to_string(), style }
+    }
+
+    #[test]
+    fn rasterizes_at_device_pixels() {
+        // 96 DPI pads by 2 pixels a side, 192 DPI by 4
+        let small = rasterize(&text("cat", true), 1.0);
+        let large = rasterize(&text("cat", true), 2.0);
+        let (narrow, low) = (small.width - 4, small.height - 4);
+        let (wide, high) = (large.width - 8, large.height - 8);
+        assert_eq!(narrow, width(font(), "cat", 12.0).ceil() as usize);
+        assert!(wide.abs_diff(narrow * 2) <= 1, "{wide} vs {narrow}");
+        assert!(high.abs_diff(low * 2) <= 1, "{high} vs {low}");
+    }
+
+    #[test]
+    fn outline_rings_the_glyph() {
+        let is_glyph = |p: [u8; 4]| p == [255, 255, 255, 255];
+        let is_outline = |p: [u8; 4]| p[3] > 0 && p[0] < 64;
+        let outlined = rasterize(&text("I", true), 1.0);
+        assert!(outlined.pixels.iter().any(|&p| is_glyph(p)));
+        // every fully covered pixel has outline or glyph on its left and right, never bare transparency
+        for y in 0..outlined.height {
+            for x in 1..outlined.width - 1 {
+                if is_glyph(outlined.get(x, y)) {
+                    for side in [outlined.get(x - 1, y), outlined.get(x + 1, y)] {
+                        assert!(side[3] > 0, "bare edge at {x},{y}");
+                    }
+                }
+            }
+        }
+        // the ring itself sits outside the plain glyph
+        let plain = rasterize(&text("I", false), 1.0);
+        assert_eq!((plain.width, plain.height), (outlined.width, outlined.height));
+        let ring = (0..plain.pixels.len())
+            .filter(|&i| plain.pixels[i][3] == 0 && is_outline(outlined.pixels[i]))
+            .count();
+        assert!(ring > 0);
+        assert!(!plain.pixels.iter().any(|&p| is_outline(p)));
+    }
+
+    #[test]
+    fn empty_text_is_blank() {
+        let image = rasterize(&text("", true), 1.0);
+        assert_eq!((image.width, image.height), (4, 4));
+        assert!(image.pixels.iter().all(|p| p[3] == 0));
+    }
+
+    #[test]
+    fn unsupported_glyphs_do_not_panic() {
+        let image = rasterize(&text("\u{E000}\u{10FFFF}", true), 2.0);
+        assert!(image.width >= 8 && image.height > 8);
+        let mixed = rasterize(&text("a\u{E000}b", true), 1.0);
+        assert!(mixed.width >= rasterize(&text("ab", true), 1.0).width);
+    }
+}
@@ -1,22 +1,215 @@
-use crate::AppError;
+use super::{
+    color::ColorTransform,
+    layout::{Align, OverlayRegion},
+    transform::FrameTransform,
+};
+use crate::{AppError, animation::AnimationInfo};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tint {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    /// 0 leaves frames untouched, 1 paints them flat
+    pub strength: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Color {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    pub a: f32,
+}
+
+impl Color {
+    pub fn to_rgba8(self) -> [u8; 4] {
+        [self.r, self.g, self.b, self.a].map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sparkline {
+    /// Oldest first
+    pub values: Vec<f32>,
+    /// Values at the bottom and top of the strip
+    pub min: f32,
+    pub max: f32,
+    pub color: Color,
+    pub region: OverlayRegion,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextStyle {
+    /// In DIPs
+    pub size: f32,
+    pub color: Color,
+    /// 1 DIP ring so text reads on any taskbar colour
+    pub outline: Option<Color>,
+    pub shadow: Option<Color>,
+    pub horizontal: Align,
+    pub vertical: Align,
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        Self {
+            size: 12.0,
+            color: Color {
+                r: 1.0,
+                g: 1.0,
+                b: 1.0,
+                a: 1.0,
+            },
+            outline: Some(Color {
+                r: 0.0,
+                g: 0.0,
+                b: 0.0,
+                a: 0.8,
+            }),
+            shadow: None,
+            horizontal: Align::End,
+            vertical: Align::End,
+        }
+    }
+}
+
+/// One or more `\n` separated lines
+#[derive(Debug, Clone, PartialEq)]
+pub struct Text {
+    pub text: String,
+    pub style: TextStyle,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BubbleStyle {
+    pub text: TextStyle,
+    pub background: Color,
+    pub border: Option<Color>,
+    /// In DIPs
+    pub max_width: f32,
+    /// In DIPs
+    pub padding: f32,
+    pub corner_radius: f32,
+    /// The GPU path shows the bubble in a popup beside the window instead
+    pub horizontal: Align,
+    pub vertical: Align,
+}
+
+impl Default for BubbleStyle {
+    fn default() -> Self {
+        Self {
+            text: TextStyle {
+                color: Color {
+                    r: 0.1,
+                    g: 0.1,
+                    b: 0.1,
+                    a: 1.0,
+                },
+                outline: None,
+                horizontal: Align::Start,
+                ..Default::default()
+            },
+            background: Color {
+                r: 1.0,
+                g: 1.0,
+                b: 0.95,
+                a: 0.95,
+            },
+            border: Some(Color {
+                r: 0.2,
+                g: 0.2,
+                b: 0.2,
+                a: 1.0,
+            }),
+            max_width: 120.0,
+            padding: 4.0,
+            corner_radius: 5.0,
+            horizontal: Align::Start,
+            vertical: Align::Start,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bubble {
+    pub text: String,
+    pub style: BubbleStyle,
+    /// Changed every fade step without re-rasterising
+    pub opacity: f32,
+}
 
 pub trait Render: Send {
-    fn load_src_data(&mut self, path: &str) -> Result<(), AppError>;
-    fn render_frame(&self) -> Result<(), AppError>;
-    fn next_frame(&mut self) -> Result<(), AppError>;
+    fn load_src_data(&mut self, path: &str) -> Result<AnimationInfo, AppError>;
+    fn render_frame(&self, frame: usize) -> Result<(), AppError>;
+    /// 0 columns for a single row
+    fn render_grid(&self, frames: &[usize], columns: usize) -> Result<(), AppError>;
+    fn set_tint(&mut self, tint: Option<Tint>) -> Result<(), AppError>;
+    /// `None` gives the area back
+    fn set_sparkline(&mut self, sparkline: Option<Sparkline>) -> Result<(), AppError>;
+    fn set_text(&mut self, text: Option<Text>) -> Result<(), AppError>;
+    fn set_bubble(&mut self, bubble: Option<Bubble>) -> Result<(), AppError>;
+    /// Renders may keep recent transforms' frames
+    fn set_recolor(&mut self, transform: Option<ColorTransform>) -> Result<(), AppError>;
+    /// Run over the frames of files loaded afterwards
+    fn set_frame_transform(&mut self, transform: FrameTransform) -> Result<(), AppError>;
+    fn set_window_size(&mut self, width: u32, height: u32) -> Result<(), AppError>;
+    fn set_dpi(&mut self, dpi: u32) -> Result<(), AppError>;
 }
 
 impl Render for () {
-    fn load_src_data(&mut self, path: &str) -> Result<(), AppError> {
+    fn load_src_data(&mut self, path: &str) -> Result<AnimationInfo, AppError> {
         let _ = path;
+        Ok(AnimationInfo::default())
+    }
+
+    fn render_frame(&self, frame: usize) -> Result<(), AppError> {
+        let _ = frame;
+        Ok(())
+    }
+
+    fn render_grid(&self, frames: &[usize], columns: usize) -> Result<(), AppError> {
+        let _ = (frames, columns);
+        Ok(())
+    }
+
+    fn set_tint(&mut self, tint: Option<Tint>) -> Result<(), AppError> {
+        let _ = tint;
+        Ok(())
+    }
+
+    fn set_sparkline(&mut self, sparkline: Option<Sparkline>) -> Result<(), AppError> {
+        let _ = sparkline;
+        Ok(())
+    }
+
+    fn set_text(&mut self, text: Option<Text>) -> Result<(), AppError> {
+        let _ = text;
+        Ok(())
+    }
+
+    fn set_bubble(&mut self, bubble: Option<Bubble>) -> Result<(), AppError> {
+        let _ = bubble;
+        Ok(())
+    }
+
+    fn set_recolor(&mut self, transform: Option<ColorTransform>) -> Result<(), AppError> {
+        let _ = transform;
+        Ok(())
+    }
+
+    fn set_frame_transform(&mut self, transform: FrameTransform) -> Result<(), AppError> {
+        let _ = transform;
         Ok(())
     }
 
-    fn render_frame(&self) -> Result<(), AppError> {
+    fn set_window_size(&mut self, width: u32, height: u32) -> Result<(), AppError> {
+        let _ = (width, height);
         Ok(())
     }
 
-    fn next_frame(&mut self) -> Result<(), AppError> {
+    fn set_dpi(&mut self, dpi: u32) -> Result<(), AppError> {
+        let _ = dpi;
         Ok(())
     }
 }
@@ -0,0 +1,224 @@
+use std::str::FromStr;
+
+use super::{image::Image, resample::Kernel, upscale::Upscaler};
+use crate::AppError;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Filter {
+    #[default]
+    Linear,
+    /// Crisp pixel art
+    Nearest,
+    /// Gamma-correct, done once per window size
+    Lanczos3,
+    Mitchell,
+}
+
+impl Filter {
+    pub fn kernel(self) -> Option<Kernel> {
+        match self {
+            Filter::Linear | Filter::Nearest => None,
+            Filter::Lanczos3 => Some(Kernel::Lanczos3),
+            Filter::Mitchell => Some(Kernel::Mitchell),
+        }
+    }
+}
+
+/// Sizes in source pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameOp {
+    FlipHorizontal,
+    FlipVertical,
+    /// Clockwise quarter turns
+    Rotate(u8),
+    /// Clipped to the frame
+    Crop {
+        left: usize,
+        top: usize,
+        width: usize,
+        height: usize,
+    },
+    Pad {
+        left: usize,
+        top: usize,
+        right: usize,
+        bottom: usize,
+    },
+    /// Crops the transparent border all frames share
+    AutoTrim,
+    /// Enlarges tiny sprites before the filter scales them
+    Upscale(Upscaler),
+}
+
+/// Steps run over a file's frames as it is loaded, and the filter they are drawn with
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct FrameTransform {
+    pub ops: Vec<FrameOp>,
+    pub filter: Filter,
+}
+
+impl FrameTransform {
+    pub fn apply(&self, frames: Vec<Image>) -> Vec<Image> {
+        self.ops.iter().fold(frames, |frames, op| match *op {
+            FrameOp::AutoTrim => match opaque_bounds(&frames) {
+                Some((left, top, width, height)) => frames.iter().map(|frame| crop(frame, left, top, width, height)).collect(),
+                // nothing visible anywhere, better left alone than shrunk to nothing
+                None => frames,
+            },
+            op => frames.iter().map(|frame| apply_op(frame, op)).collect(),
+        })
+    }
+
+    /// An `AutoTrim` only looks at this frame
+    pub fn apply_frame(&self, frame: Image) -> Image {
+        self.ops.iter().fold(frame, |frame, op| apply_op(&frame, *op))
+    }
+
+    /// Each `AutoTrim` turned into the crop it makes on the decoded frames, so frames can go through one at a time
+    pub fn resolve_trims(&self, mut decode: impl FnMut(&mut dyn FnMut(Image)) -> Result<(), AppError>) -> Result<FrameTransform, AppError> {
+        let mut resolved = FrameTransform {
+            ops: Vec::new(),
+            filter: self.filter,
+        };
+        for op in &self.ops {
+            if *op != FrameOp::AutoTrim {
+                resolved.ops.push(*op);
+                continue;
+            }
+            let mut bounds = None;
+            decode(&mut |frame| bounds = extend_bounds(bounds, &resolved.apply_frame(frame)))?;
+            if let Some((left, top, width, height)) = bounds.map(corners_to_rect) {
+                resolved.ops.push(FrameOp::Crop { left, top, width, height });
+            }
+        }
+        Ok(resolved)
+    }
+}
+
+fn apply_op(image: &Image, op: FrameOp) -> Image {
+    let (width, height) = (image.width, image.height);
+    match op {
+        FrameOp::FlipHorizontal => remap(image, width, height, |x, y| (width - 1 - x, y)),
+        FrameOp::FlipVertical => remap(image, width, height, |x, y| (x, height - 1 - y)),
+        FrameOp::Rotate(turns) => match turns % 4 {
+            1 => remap(image, height, width, |x, y| (y, height - 1 - x)),
+            2 => remap(image, width, height, |x, y| (width - 1 - x, height - 1 - y)),
+            3 => remap(image, height, width, |x, y| (width - 1 - y, x)),
+            _ => image.clone(),
+        },
+        FrameOp::Crop { left, top, width, height } => crop(image, left, top, width, height),
+        FrameOp::Pad { left, top, right, bottom } => {
+            let mut padded = Image::new(width + left + right, height + top + bottom);
+            for y in 0..height {
+                for x in 0..width {
+                    padded.set(x + left, y + top, image.get(x, y));
+                }
+            }
+            padded
+        }
+        FrameOp::AutoTrim => match opaque_bounds(std::slice::from_ref(image)) {
+            Some((left, top, width, height)) => crop(image, left, top, width, height),
+            None => image.clone(),
+        },
+        FrameOp::Upscale(upscaler) => upscaler.apply(image),
+    }
+}
+
+fn remap(image: &Image, width: usize, height: usize, source: impl Fn(usize, usize) -> (usize, usize)) -> Image {
+    let mut out = Image::new(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            let (sx, sy) = source(x, y);
+            out.set(x, y, image.get(sx, sy));
+        }
+    }
+    out
+}
+
+fn crop(image: &Image, left: usize, top: usize, width: usize, height: usize) -> Image {
+    let (left, top) = (left.min(image.width), top.min(image.height));
+    let (width, height) = (width.min(image.width - left), height.min(image.height - top));
+    remap(image, width, height, |x, y| (x + left, y + top))
+}
+
+/// `(left, top, width, height)`
+pub fn opaque_bounds(frames: &[Image]) -> Option<(usize, usize, usize, usize)> {
+    frames.iter().fold(None, extend_bounds).map(corners_to_rect)
+}
+
+fn extend_bounds(mut bounds: Option<(usize, usize, usize, usize)>, frame: &Image) -> Option<(usize, usize, usize, usize)> {
+    for (i, pixel) in frame.pixels.iter().enumerate() {
+        if pixel[3] == 0 {
+            continue;
+        }
+        let (x, y) = (i % frame.width, i / frame.width);
+        let (x0, y0, x1, y1) = bounds.unwrap_or((x, y, x, y));
+        bounds = Some((x0.min(x), y0.min(y), x1.max(x), y1.max(y)));
+    }
+    bounds
+}
+
+fn corners_to_rect((x0, y0, x1, y1): (usize, usize, usize, usize)) -> (usize, usize, usize, usize) {
+    (x0, y0, x1 - x0 + 1, y1 - y0 + 1)
+}
+
+impl FromStr for FrameTransform {
+    type Err = AppError;
+
+    /// Comma separated, e.g. `flip-h, rotate 90, crop 0 0 8 8, pad 2, trim, xbr, lanczos`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut transform = FrameTransform::default();
+        for step in s.split(',').map(str::trim).filter(|step| !step.is_empty()) {
+            let bad = || AppError(format!("Bad frame transform step {:?}", step));
+            let mut words = step.split_whitespace();
+            let name = words.next().ok_or_else(bad)?;
+            let numbers = words.map(|word| word.parse::<usize>().map_err(|_| bad())).collect::<Result<Vec<_>, _>>()?;
+            let op = match (name, numbers.as_slice()) {
+                ("flip-h", []) => FrameOp::FlipHorizontal,
+                ("flip-v", []) => FrameOp::FlipVertical,
+                ("rotate", [degrees]) if degrees % 90 == 0 => FrameOp::Rotate((degrees / 90 % 4) as u8),
+                ("crop", &[left, top, width, height]) => FrameOp::Crop { left, top, width, height },
+                ("pad", &[all]) => FrameOp::Pad {
+                    left: all,
+                    top: all,
+                    right: all,
+                    bottom: all,
+                },
+                ("pad", &[left, top, right, bottom]) => FrameOp::Pad { left, top, right, bottom },
+                ("trim", []) => FrameOp::AutoTrim,
+                ("scale2x", []) => FrameOp::Upscale(Upscaler::Scale2x),
+                ("scale3x", []) => FrameOp::Upscale(Upscaler::Scale3x),
+                ("xbr", []) => FrameOp::Upscale(Upscaler::Xbr2x),
+                ("linear" | "nearest" | "lanczos" | "mitchell", []) => {
+                    transform.filter = match name {
+                        "linear" => Filter::Linear,
+                        "nearest" => Filter::Nearest,
+                        "lanczos" => Filter::Lanczos3,
+                        _ => Filter::Mitchell,
+                    };
+                    continue;
+                }
+                _ => return Err(bad()),
+            };
+            transform.ops.push(op);
+        }
+        Ok(transform)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_steps() {
+        let transform: FrameTransform = "flip-h, scale2x, xbr, nearest".parse().unwrap();
+        assert_eq!(
+            transform.ops,
+            [FrameOp::FlipHorizontal, FrameOp::Upscale(Upscaler::Scale2x), FrameOp::Upscale(Upscaler::Xbr2x)]
+        );
+        assert_eq!(transform.filter, Filter::Nearest);
+        assert!("hq2x".parse::<FrameTransform>().is_err());
+        assert!("rotate 45".parse::<FrameTransform>().is_err());
+    }
+}
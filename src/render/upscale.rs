@@ -0,0 +1,178 @@
+use super::image::Image;
+
+/// Pixel-art scalers, which round off diagonal edges without blurring flat areas
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Upscaler {
+    /// EPX/AdvMAME2x, copies neighbours into the corners along edges
+    Scale2x,
+    Scale3x,
+    /// Hyllian's 2xBR, blends corners along edges picked by colour distance
+    Xbr2x,
+}
+
+impl Upscaler {
+    pub fn factor(self) -> usize {
+        match self {
+            Upscaler::Scale3x => 3,
+            Upscaler::Scale2x | Upscaler::Xbr2x => 2,
+        }
+    }
+
+    pub fn apply(self, image: &Image) -> Image {
+        let factor = self.factor();
+        let mut out = Image::new(image.width * factor, image.height * factor);
+        for y in 0..image.height {
+            for x in 0..image.width {
+                let at = |dx: isize, dy: isize| pixel_at(image, x as isize + dx, y as isize + dy);
+                let block: &[[u8; 4]] = match self {
+                    Upscaler::Scale2x => &scale2x(at),
+                    Upscaler::Scale3x => &scale3x(at),
+                    Upscaler::Xbr2x => &xbr2x(at),
+                };
+                for (i, &pixel) in block.iter().enumerate() {
+                    out.set(x * factor + i % factor, y * factor + i / factor, pixel);
+                }
+            }
+        }
+        out
+    }
+}
+
+fn pixel_at(image: &Image, x: isize, y: isize) -> [u8; 4] {
+    image.get(x.clamp(0, image.width as isize - 1) as usize, y.clamp(0, image.height as isize - 1) as usize)
+}
+
+fn scale2x(at: impl Fn(isize, isize) -> [u8; 4]) -> [[u8; 4]; 4] {
+    let (p, a, b, c, d) = (at(0, 0), at(0, -1), at(1, 0), at(-1, 0), at(0, 1));
+    if a == d || b == c {
+        return [p; 4];
+    }
+    [
+        if c == a { a } else { p },
+        if a == b { b } else { p },
+        if d == c { c } else { p },
+        if b == d { d } else { p },
+    ]
+}
+
+fn scale3x(at: impl Fn(isize, isize) -> [u8; 4]) -> [[u8; 4]; 9] {
+    let [a, b, c, d, e, f, g, h, i] = [(-1, -1), (0, -1), (1, -1), (-1, 0), (0, 0), (1, 0), (-1, 1), (0, 1), (1, 1)].map(|(dx, dy)| at(dx, dy));
+    if b == h || d == f {
+        return [e; 9];
+    }
+    [
+        if d == b { d } else { e },
+        if (d == b && e != c) || (b == f && e != a) { b } else { e },
+        if b == f { f } else { e },
+        if (d == b && e != g) || (d == h && e != a) { d } else { e },
+        e,
+        if (b == f && e != i) || (h == f && e != c) { f } else { e },
+        if d == h { d } else { e },
+        if (d == h && e != i) || (h == f && e != g) { h } else { e },
+        if h == f { f } else { e },
+    ]
+}
+
+type Turn = fn(isize, isize) -> (isize, isize);
+
+/// Each corner worked out as the bottom right one of the neighbourhood turned round
+fn xbr2x(at: impl Fn(isize, isize) -> [u8; 4]) -> [[u8; 4]; 4] {
+    let e = at(0, 0);
+    // (x, y) of the bottom right corner's view mapped to each corner
+    let turns: [Turn; 4] = [|x, y| (-x, -y), |x, y| (y, -x), |x, y| (-y, x), |x, y| (x, y)];
+    turns.map(|turn| {
+        let at = |x, y| {
+            let (x, y) = turn(x, y);
+            at(x, y)
+        };
+        let (c, f, g, h, i) = (at(1, -1), at(1, 0), at(-1, 1), at(0, 1), at(1, 1));
+        let (d, b, f4, h5, i4, i5) = (at(-1, 0), at(0, -1), at(2, 0), at(0, 2), at(2, 1), at(1, 2));
+        if e == f || e == h {
+            return e;
+        }
+        // weight of an edge running across the corner against one running along it
+        let across = distance(e, c) + distance(e, g) + distance(i, f4) + distance(i, h5) + 4 * distance(h, f);
+        let along = distance(h, d) + distance(h, i5) + distance(f, i4) + distance(f, b) + 4 * distance(e, i);
+        if across >= along {
+            return e;
+        }
+        let towards = if distance(e, f) <= distance(e, h) { f } else { h };
+        mix(e, towards)
+    })
+}
+
+/// Luma counts most, as in the original
+fn distance(p: [u8; 4], q: [u8; 4]) -> u32 {
+    let yuv = |[r, g, b, _]: [u8; 4]| {
+        let [r, g, b] = [r, g, b].map(|c| c as f32);
+        (
+            0.299 * r + 0.587 * g + 0.114 * b,
+            -0.169 * r - 0.331 * g + 0.5 * b,
+            0.5 * r - 0.419 * g - 0.081 * b,
+        )
+    };
+    let ((py, pu, pv), (qy, qu, qv)) = (yuv(p), yuv(q));
+    ((py - qy).abs() * 48.0 + (pu - qu).abs() * 7.0 + (pv - qv).abs() * 6.0 + (p[3] as f32 - q[3] as f32).abs() * 48.0) as u32
+}
+
+fn mix(p: [u8; 4], q: [u8; 4]) -> [u8; 4] {
+    let alpha = p[3] as u32 + q[3] as u32;
+    if alpha == 0 {
+        return [0; 4];
+    }
+    let channel = |i: usize| ((p[i] as u32 * p[3] as u32 + q[i] as u32 * q[3] as u32 + alpha / 2) / alpha) as u8;
+    [channel(0), channel(1), channel(2), alpha.div_ceil(2) as u8]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `#` opaque white, `.` transparent, `+` white at half alpha
+    fn grid(rows: &[&str]) -> Image {
+        Image {
+            width: rows[0].len(),
+            height: rows.len(),
+            pixels: rows
+                .iter()
+                .flat_map(|row| row.chars())
+                .map(|c| match c {
+                    '#' => [255; 4],
+                    '+' => [255, 255, 255, 128],
+                    _ => [0; 4],
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn scale2x_edges() {
+        assert_eq!(Upscaler::Scale2x.apply(&grid(&["#.", ".#"])), grid(&["##..", "#.#.", ".#.#", "..##"]));
+        // a lone pixel has no edge to follow
+        assert_eq!(
+            Upscaler::Scale2x.apply(&grid(&["...", ".#.", "..."])).pixels,
+            grid(&["......", "......", "..##..", "..##..", "......", "......"]).pixels
+        );
+    }
+
+    #[test]
+    fn scale3x_edges() {
+        let expected = grid(&["###...", "##.#..", "#..##.", ".##..#", "..#.##", "...###"]);
+        assert_eq!(Upscaler::Scale3x.apply(&grid(&["#.", ".#"])), expected);
+    }
+
+    #[test]
+    fn xbr_edges() {
+        // the corner facing the notch is blended half way
+        assert_eq!(Upscaler::Xbr2x.apply(&grid(&["#.", "##"])), grid(&["##..", "##+.", "####", "####"]));
+    }
+
+    #[test]
+    fn flat_areas_stay_flat() {
+        let mut image = Image::new(4, 4);
+        image.fill([9, 8, 7, 255]);
+        for upscaler in [Upscaler::Scale2x, Upscaler::Scale3x, Upscaler::Xbr2x] {
+            assert!(upscaler.apply(&image).pixels.iter().all(|pixel| *pixel == [9, 8, 7, 255]));
+        }
+    }
+}
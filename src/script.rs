@@ -0,0 +1,274 @@
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use log::{info, warn};
+use rhai::{AST, Dynamic, Engine, Map, Scope};
+
+use crate::{AppError, metrics::Sample, render::Tint};
+
+/// Budget of one `update` call, an endless loop fails instead of freezing the window
+const MAX_OPERATIONS: u64 = 100_000;
+
+/// `None` where the script returned nothing, so the built in behaviour stays
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ScriptDecision {
+    pub fps: Option<f32>,
+    pub clip: Option<String>,
+    /// `Some(None)` when the script returned `()` to clear the tint
+    pub tint: Option<Option<Tint>>,
+    /// `Some(None)` when the script returned `()` to clear the text
+    pub text: Option<Option<String>>,
+    /// Queued behind any bubble already showing
+    pub say: Option<String>,
+}
+
+/// The script defines `fn update(metrics)`, `metrics` mapping names like `Cpu` or `CpuCore(0)` to their last value,
+/// and returns a map with any of `fps`, `clip`, `tint` (`#{r, g, b, strength}`), `text` and `say`:
+///
+/// ```rhai
+/// fn update(metrics) {
+///     let cpu = metrics.Cpu ?? 0.0;
+///     #{ fps: 10.0 + cpu / 2.0, clip: if cpu > 80.0 { "run" } else { "walk" } }
+/// }
+/// ```
+///
+/// Scripts have no file or network access and are recompiled when the file changes, keeping the previous version on failure.
+pub struct ScriptPolicy {
+    engine: Engine,
+    ast: AST,
+    /// `None` for scripts given as source
+    path: Option<PathBuf>,
+    modified: Option<SystemTime>,
+    metrics: Map,
+}
+
+impl ScriptPolicy {
+    pub fn load(path: &Path) -> Result<Self, AppError> {
+        let mut policy = Self::from_source(&read(path)?)?;
+        policy.modified = modified(path);
+        policy.path = Some(path.into());
+        Ok(policy)
+    }
+
+    pub fn from_source(source: &str) -> Result<Self, AppError> {
+        let mut engine = Engine::new();
+        engine
+            .set_max_operations(MAX_OPERATIONS)
+            .set_max_call_levels(32)
+            .set_max_expr_depths(64, 32)
+            .set_max_string_size(4096)
+            .set_max_array_size(1024)
+            .set_max_map_size(1024)
+            .set_max_modules(0)
+            .on_print(|text| info!("script: {}", text))
+            .on_debug(|text, _, pos| info!("script {}: {}", pos, text));
+        engine.disable_symbol("eval");
+        let ast = compile(&engine, source)?;
+        Ok(Self {
+            engine,
+            ast,
+            path: None,
+            modified: None,
+            metrics: Map::new(),
+        })
+    }
+
+    pub fn record(&mut self, sample: Sample) {
+        self.metrics.insert(sample.kind.to_string().into(), Dynamic::from_float(sample.value as f64));
+    }
+
+    /// Picks up changes to the script file first
+    pub fn decide(&mut self) -> Result<ScriptDecision, AppError> {
+        self.reload_if_changed();
+        let result: Dynamic = self
+            .engine
+            .call_fn(&mut Scope::new(), &self.ast, "update", (self.metrics.clone(),))
+            .map_err(|e| AppError(format!("Script update failed: {}", e)))?;
+        decision(result)
+    }
+
+    fn reload_if_changed(&mut self) {
+        let Some(path) = self.path.as_deref() else {
+            return;
+        };
+        let modified = modified(path);
+        if modified == self.modified {
+            return;
+        }
+        self.modified = modified;
+        match read(path).and_then(|source| compile(&self.engine, &source)) {
+            Ok(ast) => {
+                info!("Reloaded {}", path.display());
+                self.ast = ast;
+            }
+            Err(e) => warn!("Keeping the previous script: {}", e),
+        }
+    }
+}
+
+fn read(path: &Path) -> Result<String, AppError> {
+    std::fs::read_to_string(path).map_err(|e| AppError(format!("{}: {}", path.display(), e)))
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn compile(engine: &Engine, source: &str) -> Result<AST, AppError> {
+    let ast = engine.compile(source).map_err(|e| AppError(format!("Script error: {}", e)))?;
+    if !ast.iter_functions().any(|f| f.name == "update" && f.params.len() == 1) {
+        return Err(AppError("Script defines no fn update(metrics)".into()));
+    }
+    Ok(ast)
+}
+
+fn number(value: &Dynamic) -> Option<f32> {
+    value.as_float().ok().map(|v| v as f32).or_else(|| value.as_int().ok().map(|v| v as f32))
+}
+
+/// Unknown keys are ignored
+pub fn decision(result: Dynamic) -> Result<ScriptDecision, AppError> {
+    if result.is_unit() {
+        return Ok(ScriptDecision::default());
+    }
+    let type_name = result.type_name();
+    let map: Map = result
+        .try_cast()
+        .ok_or_else(|| AppError(format!("Script returned {} instead of a map", type_name)))?;
+    let bad = |key: &str| AppError(format!("Script returned a bad {}", key));
+
+    let fps = match map.get("fps") {
+        Some(fps) => Some(number(fps).filter(|fps| *fps > 0.0).ok_or_else(|| bad("fps"))?),
+        None => None,
+    };
+    let clip = match map.get("clip") {
+        Some(clip) => Some(clip.clone().into_string().map_err(|_| bad("clip"))?),
+        None => None,
+    };
+    let tint = match map.get("tint") {
+        Some(tint) if tint.is_unit() => Some(None),
+        Some(tint) => {
+            let tint = tint.read_lock::<Map>().ok_or_else(|| bad("tint"))?;
+            let channel = |key: &str| tint.get(key).and_then(number).map(|v| v.clamp(0.0, 1.0)).ok_or_else(|| bad("tint"));
+            Some(Some(Tint {
+                r: channel("r")?,
+                g: channel("g")?,
+                b: channel("b")?,
+                strength: channel("strength")?,
+            }))
+        }
+        None => None,
+    };
+    let text = match map.get("text") {
+        Some(text) if text.is_unit() => Some(None),
+        Some(text) => Some(Some(text.to_string())),
+        None => None,
+    };
+    let say = map.get("say").filter(|say| !say.is_unit()).map(|say| say.to_string());
+    Ok(ScriptDecision { fps, clip, tint, text, say })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{metrics::MetricKind, test_util::TempDir};
+    use std::{fs::File, time::Duration};
+
+    fn decide(source: &str) -> Result<ScriptDecision, AppError> {
+        ScriptPolicy::from_source(source)?.decide()
+    }
+
+    fn error(source: &str) -> String {
+        decide(source).err().unwrap().0.to_lowercase()
+    }
+
+    #[test]
+    fn sandbox_limits() {
+        assert!(error("fn update(m) { loop {} }").contains("operations"));
+        assert!(error("fn f(n) { f(n + 1) } fn update(m) { f(0) }").contains("stack overflow"));
+        assert!(error(r#"fn update(m) { let s = "x"; loop { s += s; } }"#).contains("string"));
+        assert!(error("fn update(m) { let a = []; loop { a.push(1); } }").contains("array"));
+        assert!(ScriptPolicy::from_source(r#"fn update(m) { eval("1") }"#).is_err());
+    }
+
+    #[test]
+    fn errors_leave_default_behaviour() {
+        assert!(ScriptPolicy::from_source("fn update(m) {").is_err());
+        assert!(ScriptPolicy::from_source("fn other(m) { #{} }").is_err());
+        let mut policy = ScriptPolicy::from_source(r#"fn update(m) { if m.Cpu > 50.0 { throw "hot" } #{ fps: 5 } }"#).unwrap();
+        policy.record(Sample {
+            kind: MetricKind::Cpu,
+            value: 90.0,
+        });
+        assert!(policy.decide().is_err());
+        // the next sample runs again
+        policy.record(Sample {
+            kind: MetricKind::Cpu,
+            value: 10.0,
+        });
+        assert_eq!(policy.decide().unwrap().fps, Some(5.0));
+        assert_eq!(decide("fn update(m) { }").unwrap(), ScriptDecision::default());
+        assert!(decide("fn update(m) { 3 }").is_err());
+        assert!(decide("fn update(m) { #{ fps: 0 } }").is_err());
+        assert!(decide(r#"fn update(m) { #{ tint: #{ r: 1.0 } } }"#).is_err());
+    }
+
+    #[test]
+    fn decisions() {
+        let mut policy = ScriptPolicy::from_source(
+            r#"fn update(m) {
+                #{ fps: 10.0 + m.Cpu / 2.0, clip: "run", tint: #{ r: 2, g: 0.5, b: 0, strength: 0.25 }, text: (), say: "hi", other: 1 }
+            }"#,
+        )
+        .unwrap();
+        policy.record(Sample {
+            kind: MetricKind::Cpu,
+            value: 40.0,
+        });
+        assert_eq!(
+            policy.decide().unwrap(),
+            ScriptDecision {
+                fps: Some(30.0),
+                clip: Some("run".into()),
+                tint: Some(Some(Tint {
+                    r: 1.0,
+                    g: 0.5,
+                    b: 0.0,
+                    strength: 0.25
+                })),
+                text: Some(None),
+                say: Some("hi".into()),
+            }
+        );
+        let decision = decide(r#"fn update(m) { #{ tint: (), text: 42 } }"#).unwrap();
+        assert_eq!((decision.tint, decision.text), (Some(None), Some(Some("42".into()))));
+    }
+
+    #[test]
+    fn reloads_changed_file() {
+        let dir = TempDir::new("script");
+        let write = |source: &str, age: u64| {
+            let path = dir.write("policy.rhai", source);
+            // mtimes can be coarse, so each version gets its own
+            File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(age))
+                .unwrap();
+            path
+        };
+        let path = write("fn update(m) { #{ clip: \"one\" } }", 1);
+        let mut policy = ScriptPolicy::load(&path).unwrap();
+        assert_eq!(policy.decide().unwrap().clip.as_deref(), Some("one"));
+        write("fn update(m) { #{ clip: \"two\" } }", 2);
+        assert_eq!(policy.decide().unwrap().clip.as_deref(), Some("two"));
+        // a broken version keeps the previous one
+        write("fn update(m) {", 3);
+        assert_eq!(policy.decide().unwrap().clip.as_deref(), Some("two"));
+        write("fn update(m) { #{ clip: \"three\" } }", 4);
+        assert_eq!(policy.decide().unwrap().clip.as_deref(), Some("three"));
+    }
+}
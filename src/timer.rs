@@ -6,14 +6,22 @@ use std::{
 
 use crate::eventloop::Event;
 
+struct Timer {
+    event: Event,
+    interval: Duration,
+    start: Instant,
+    active: bool,
+    /// Deactivated after firing
+    once: bool,
+}
+
 pub struct TimerManager {
-    /// Event, Duration, Start_time, Is_active
-    timers: Arc<Mutex<Vec<(Event, Duration, Instant, bool)>>>,
+    timers: Arc<Mutex<Vec<Timer>>>,
 }
 
 impl TimerManager {
     pub fn new(sx: Sender<Event>) -> Self {
-        let timers = Arc::new(Mutex::new(Vec::new()));
+        let timers = Arc::new(Mutex::new(Vec::<Timer>::new()));
         let timers_clone = timers.clone();
         let sx_clone = sx.clone();
 
@@ -28,15 +36,18 @@ impl TimerManager {
                         let mut events = Vec::new();
                         let mut min_sleep = Duration::from_secs(1);
 
-                        for (event, interval, start_time, active) in timers.iter_mut() {
-                            if *active {
-                                let elapsed = now.duration_since(*start_time);
-                                if elapsed >= *interval {
-                                    events.push(*event);
-                                    *start_time = now;
-                                    min_sleep = min_sleep.min(*interval);
+                        for timer in timers.iter_mut() {
+                            if timer.active {
+                                let elapsed = now.duration_since(timer.start);
+                                if elapsed >= timer.interval {
+                                    events.push(timer.event);
+                                    timer.start = now;
+                                    if timer.once {
+                                        timer.active = false;
+                                    }
+                                    min_sleep = min_sleep.min(timer.interval);
                                 } else {
-                                    let remaining = *interval - elapsed;
+                                    let remaining = timer.interval - elapsed;
                                     min_sleep = min_sleep.min(remaining);
                                 }
                             }
@@ -60,27 +71,41 @@ impl TimerManager {
     }
 
     pub fn start_timer(&self, event: Event, duration: Duration) {
+        self.schedule(event, duration, false);
+    }
+
+    /// Replaces any timer of the same event
+    pub fn start_once(&self, event: Event, delay: Duration) {
+        self.schedule(event, delay, true);
+    }
+
+    fn schedule(&self, event: Event, duration: Duration, once: bool) {
         let mut timers = self.timers.lock().unwrap();
         let start_time = Instant::now();
 
-        if let Some(existing) = timers.iter_mut().find(|(e, _, _, _)| *e == event) {
-            existing.1 = duration;
-            existing.2 = start_time;
-            existing.3 = true;
+        let timer = Timer {
+            event,
+            interval: duration,
+            start: start_time,
+            active: true,
+            once,
+        };
+        if let Some(existing) = timers.iter_mut().find(|t| t.event == event) {
+            *existing = timer;
         } else {
-            timers.push((event, duration, start_time, true));
+            timers.push(timer);
         }
     }
 
     pub fn stop_timer(&self, event: Event) {
         let mut timers = self.timers.lock().unwrap();
-        if let Some(timer) = timers.iter_mut().find(|(e, _, _, _)| *e == event) {
-            timer.3 = false; // 设置为不活跃
+        if let Some(timer) = timers.iter_mut().find(|t| t.event == event) {
+            timer.active = false; // 设置为不活跃
         }
     }
 
     pub fn remove_timer(&self, event: Event) {
         let mut timers = self.timers.lock().unwrap();
-        timers.retain(|(e, _, _, _)| *e != event);
+        timers.retain(|t| t.event != event);
     }
 }
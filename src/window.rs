@@ -1,6 +1,10 @@
 use crate::{
     AppError,
     eventloop::{Event, EventLoop},
+    render::{
+        image::Image,
+        layout::{self, Align},
+    },
 };
 use log::debug;
 use std::{
@@ -8,15 +12,19 @@ use std::{
 };
 use windows::{
     Win32::{
-        Foundation::{COLORREF, HWND, LPARAM, LRESULT, RECT, WPARAM},
-        Graphics::Gdi::{BeginPaint, EndPaint, HBRUSH, PAINTSTRUCT},
+        Foundation::{COLORREF, HWND, LPARAM, LRESULT, POINT, RECT, SIZE, WPARAM},
+        Graphics::Gdi::{
+            AC_SRC_ALPHA, AC_SRC_OVER, BI_RGB, BITMAPINFO, BITMAPINFOHEADER, BLENDFUNCTION, BeginPaint, CreateCompatibleDC, CreateDIBSection, DIB_RGB_COLORS,
+            DeleteDC, DeleteObject, EndPaint, GetDC, HBRUSH, PAINTSTRUCT, ReleaseDC, SelectObject,
+        },
         System::LibraryLoader::GetModuleHandleW,
         UI::{
-            HiDpi::GetDpiForSystem,
+            HiDpi::{DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2, GetDpiForSystem, SetProcessDpiAwarenessContext},
             WindowsAndMessaging::{
-                CS_PARENTDC, CS_SAVEBITS, CreateWindowExW, DispatchMessageW, FindWindowExW, GWLP_USERDATA, GetMessageW, GetWindowRect, HWND_TOPMOST, IDC_ARROW, LWA_COLORKEY, LoadCursorW, MSG,
-                RegisterClassW, SWP_NOACTIVATE, SetLayeredWindowAttributes, SetWindowLongPtrW, SetWindowPos, TranslateMessage, WNDCLASSW, WS_EX_LAYERED, WS_EX_NOACTIVATE, WS_EX_TOOLWINDOW,
-                WS_EX_TOPMOST, WS_EX_TRANSPARENT, WS_POPUP, WS_VISIBLE,
+                CS_PARENTDC, CS_SAVEBITS, CreateWindowExW, DefWindowProcW, DispatchMessageW, FindWindowExW, GWLP_USERDATA, GetMessageW, GetWindowRect,
+                HWND_TOPMOST, IDC_ARROW, LWA_COLORKEY, LoadCursorW, MSG, RegisterClassW, SW_HIDE, SW_SHOWNOACTIVATE, SWP_NOACTIVATE,
+                SetLayeredWindowAttributes, SetWindowLongPtrW, SetWindowPos, ShowWindow, TranslateMessage, ULW_ALPHA, UpdateLayeredWindow, WNDCLASSW,
+                WS_EX_LAYERED, WS_EX_NOACTIVATE, WS_EX_TOOLWINDOW, WS_EX_TOPMOST, WS_EX_TRANSPARENT, WS_POPUP, WS_VISIBLE,
             },
         },
     },
@@ -45,9 +53,102 @@ pub struct Rect {
     pub h: u16,
 }
 
+const BUBBLE_GAP: i32 = 4;
+
+/// Layered popup beside the cat's window, so the taskbar doesn't clip the speech bubble
+#[derive(Debug, Default, Clone, Copy)]
+pub struct BubbleWindow {
+    pub hwnd: WindowHandle,
+    pub anchor: WindowHandle,
+}
+
+impl BubbleWindow {
+    pub fn show(&self, image: &Image, horizontal: Align, opacity: f32) -> Result<(), AppError> {
+        if image.width == 0 || image.height == 0 {
+            self.hide();
+            return Ok(());
+        }
+        let mut anchor = RECT::default();
+        unsafe { GetWindowRect(self.anchor.0, &mut anchor)? };
+        let anchor = layout::Rect::new(
+            anchor.left as f32,
+            anchor.top as f32,
+            (anchor.right - anchor.left) as f32,
+            (anchor.bottom - anchor.top) as f32,
+        );
+        let (x, y) = layout::beside(image.width as f32, image.height as f32, anchor, horizontal, BUBBLE_GAP as f32);
+        let size = SIZE {
+            cx: image.width as i32,
+            cy: image.height as i32,
+        };
+        let info = BITMAPINFO {
+            bmiHeader: BITMAPINFOHEADER {
+                biSize: size_of::<BITMAPINFOHEADER>() as u32,
+                biWidth: size.cx,
+                // top down
+                biHeight: -size.cy,
+                biPlanes: 1,
+                biBitCount: 32,
+                biCompression: BI_RGB.0,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        unsafe {
+            let screen = GetDC(None);
+            let dc = CreateCompatibleDC(Some(screen));
+            let mut bits = std::ptr::null_mut();
+            let result = CreateDIBSection(Some(dc), &info, DIB_RGB_COLORS, &mut bits, None, 0).and_then(|bitmap| {
+                let pixels = image.to_premultiplied_bgra();
+                std::ptr::copy_nonoverlapping(pixels.as_ptr(), bits as *mut u8, pixels.len());
+                let old = SelectObject(dc, bitmap.into());
+                let result = UpdateLayeredWindow(
+                    self.hwnd.0,
+                    Some(screen),
+                    Some(&POINT { x: x as i32, y: y as i32 }),
+                    Some(&size),
+                    Some(dc),
+                    Some(&POINT::default()),
+                    COLORREF(0),
+                    Some(&blend(opacity)),
+                    ULW_ALPHA,
+                );
+                SelectObject(dc, old);
+                let _ = DeleteObject(bitmap.into());
+                result
+            });
+            let _ = DeleteDC(dc);
+            ReleaseDC(None, screen);
+            result?;
+            let _ = ShowWindow(self.hwnd.0, SW_SHOWNOACTIVATE);
+        }
+        Ok(())
+    }
+
+    /// Fades without redrawing
+    pub fn set_opacity(&self, opacity: f32) -> Result<(), AppError> {
+        unsafe { UpdateLayeredWindow(self.hwnd.0, None, None, None, None, None, COLORREF(0), Some(&blend(opacity)), ULW_ALPHA)? };
+        Ok(())
+    }
+
+    pub fn hide(&self) {
+        let _ = unsafe { ShowWindow(self.hwnd.0, SW_HIDE) };
+    }
+}
+
+fn blend(opacity: f32) -> BLENDFUNCTION {
+    BLENDFUNCTION {
+        BlendOp: AC_SRC_OVER as u8,
+        BlendFlags: 0,
+        SourceConstantAlpha: (opacity.clamp(0.0, 1.0) * 255.0).round() as u8,
+        AlphaFormat: AC_SRC_ALPHA as u8,
+    }
+}
+
 #[derive(Debug)]
 pub struct Window {
     pub hwnd: WindowHandle,
+    pub bubble: BubbleWindow,
     pub thread_handle: Option<JoinHandle<()>>,
     pub parent_hwnd: Option<WindowHandle>,
     pub rect: Rect,
@@ -55,6 +156,10 @@ pub struct Window {
 }
 impl Window {
     pub fn init(event_loop: &EventLoop) -> Result<Self, AppError> {
+        // without it Windows scales the window itself and never sends WM_DPICHANGED
+        if let Err(e) = unsafe { SetProcessDpiAwarenessContext(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2) } {
+            debug!("DPI awareness not set: {}", e);
+        }
         Self::register_class()?;
         let event_sender = event_loop.event_sender.clone();
         let (result_tx, result_rx) = std::sync::mpsc::channel();
@@ -73,10 +178,12 @@ impl Window {
                 let dpi = unsafe { GetDpiForSystem() as f32 };
                 let scale = dpi / 96.0;
 
-                let height = ((notify_rect.bottom - notify_rect.top - 20) as f32 * scale) as i32;
+                // the tray rect is in physical pixels once DPI aware, only the margins scale
+                let margin = |dips: f32| (dips * scale).round() as i32;
+                let height = notify_rect.bottom - notify_rect.top - margin(20.0);
                 let width = (height as f32 * 3.0) as i32;
-                let x = notify_rect.left - width - 10;
-                let y = notify_rect.top + 10;
+                let x = notify_rect.left - width - margin(10.0);
+                let y = notify_rect.top + margin(10.0);
 
                 let hwnd = unsafe {
                     CreateWindowExW(
@@ -99,6 +206,24 @@ impl Window {
                     let _ = SetLayeredWindowAttributes(hwnd, COLORREF(0), 0, LWA_COLORKEY);
                     let _ = SetWindowPos(hwnd, Some(HWND_TOPMOST), x, y, width, height, SWP_NOACTIVATE);
                 }
+                // hidden until there is a bubble, sized and placed on each one
+                let bubble_hwnd = unsafe {
+                    CreateWindowExW(
+                        WS_EX_LAYERED | WS_EX_TOOLWINDOW | WS_EX_TRANSPARENT | WS_EX_TOPMOST | WS_EX_NOACTIVATE,
+                        w!("RUST_CAT_BUBBLE"),
+                        w!("rust_cat_bubble"),
+                        WS_POPUP,
+                        x,
+                        y,
+                        0,
+                        0,
+                        None,
+                        None,
+                        None,
+                        None,
+                    )
+                    .expect("Failed to create bubble window")
+                };
 
                 debug!("实际窗口位置: {:?}", {
                     let mut actual_rect = RECT::default();
@@ -109,6 +234,10 @@ impl Window {
                 result_tx
                     .send((
                         hwnd.into(),
+                        BubbleWindow {
+                            hwnd: bubble_hwnd.into(),
+                            anchor: hwnd.into(),
+                        },
                         Some(sys_tray.into()),
                         Rect {
                             x: x as u16,
@@ -127,7 +256,8 @@ impl Window {
                     SetWindowLongPtrW(hwnd, GWLP_USERDATA, sender_ptr2);
                 }
                 let mut msg = MSG::default();
-                while unsafe { GetMessageW(&mut msg, Some(hwnd), 0, 0).into() } {
+                // for the bubble's window too
+                while unsafe { GetMessageW(&mut msg, None, 0, 0).into() } {
                     unsafe {
                         let _ = TranslateMessage(&msg);
                         DispatchMessageW(&msg);
@@ -137,11 +267,12 @@ impl Window {
             .unwrap();
 
         // wait window created
-        let (hwnd, parent_hwnd, rect) = result_rx.recv().unwrap();
+        let (hwnd, bubble, parent_hwnd, rect) = result_rx.recv().unwrap();
 
         Ok(Window {
             thread_handle: Some(thread_handle),
             hwnd,
+            bubble,
             parent_hwnd,
             rect,
             event_sender: event_loop.event_sender.clone(),
@@ -163,6 +294,15 @@ impl Window {
             if RegisterClassW(&wc) == 0 {
                 return Err(AppError("Error register class :(".into()));
             }
+            let bubble = WNDCLASSW {
+                hInstance: instance.into(),
+                lpszClassName: w!("RUST_CAT_BUBBLE"),
+                lpfnWndProc: Some(bubble_proc),
+                ..Default::default()
+            };
+            if RegisterClassW(&bubble) == 0 {
+                return Err(AppError("Error register bubble class".into()));
+            }
             Ok(())
         }
     }
@@ -191,6 +331,22 @@ unsafe extern "system" fn wnd_proc(hwnd: HWND, msg: u32, wparam: WPARAM, lparam:
                 let height = ((lparam.0 >> 16) & 0xFFFF) as u32;
                 let _ = sender.send(Event::Resize(width, height));
             }
+            WM_DPICHANGED => {
+                let _ = sender.send(Event::DpiChanged((wparam.0 & 0xFFFF) as u32));
+                // the suggested rect keeps the window the same physical size on the new monitor
+                unsafe {
+                    let rect = &*(lparam.0 as *const RECT);
+                    let _ = SetWindowPos(
+                        hwnd,
+                        None,
+                        rect.left,
+                        rect.top,
+                        rect.right - rect.left,
+                        rect.bottom - rect.top,
+                        SWP_NOZORDER | SWP_NOACTIVATE,
+                    );
+                }
+            }
             WM_CLOSE => {
                 let _ = sender.send(Event::Close);
             }
@@ -220,6 +376,10 @@ unsafe extern "system" fn wnd_proc(hwnd: HWND, msg: u32, wparam: WPARAM, lparam:
     LRESULT(10086)
 }
 
+unsafe extern "system" fn bubble_proc(hwnd: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    unsafe { DefWindowProcW(hwnd, msg, wparam, lparam) }
+}
+
 pub fn get_tray_notify() -> HWND {
     let sys_tray = unsafe { FindWindowExW(None, None, w!("Shell_TrayWnd"), None) }.expect("Failed to find SysTray");
     let sys_tray_notify = unsafe { FindWindowExW(Some(sys_tray), None, w!("TrayNotifyWnd"), None) }.expect("Failed to find SysTrayNotify");